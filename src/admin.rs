@@ -0,0 +1,609 @@
+use crate::api::EnrollRequest;
+use crate::audit::{AuditAction, AuditRecord, record};
+use crate::bundle::{BundleFormat, ConflictPolicy, ImportDiff};
+use crate::extract::Input;
+use crate::group::{GROUP_PREFIX, Group, GroupInfo, Policy};
+use crate::lockout::{LOCKOUTS, LockoutStatus};
+use crate::store::{ACCOUNTS, Account, AccountInfo, Enrollment};
+use crate::utils::now_secs;
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouter};
+use utoipa_axum::routes;
+
+/// Default number of audit records returned at once.
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+/// Max number of audit records returned at once.
+const MAX_AUDIT_LIMIT: usize = 1000;
+
+/// An account with its state, as seen by admins.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AdminAccount {
+    /// The account.
+    #[serde(flatten)]
+    info: AccountInfo,
+    /// Failures and lockout of the account.
+    #[serde(flatten)]
+    lockout: LockoutStatus,
+}
+
+impl From<Account> for AdminAccount {
+    fn from(account: Account) -> Self {
+        AdminAccount {
+            lockout: LOCKOUTS.status(&account.name, now_secs()),
+            info: account.into(),
+        }
+    }
+}
+
+/// Request body which sets the group and the policy of an account.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AccountPolicy {
+    /// The group which the account is in, or none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "game-x")]
+    group: Option<String>,
+    /// Settings which override the ones of the group.
+    #[serde(flatten)]
+    policy: Policy,
+}
+
+/// Filters of audit records.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AuditQuery {
+    /// Max number of records, newest first.
+    #[param(minimum = 1, maximum = 1000, default = 100)]
+    limit: Option<usize>,
+    /// Only records of this account.
+    account: Option<String>,
+}
+
+/// List every account which the caller is allowed to access, with its state.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/accounts",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Accounts which the caller can access.", body = [AdminAccount]),
+        (status = 401, response = crate::Error),
+    )
+)]
+async fn list_accounts(caller: crate::Caller) -> Json<Vec<AdminAccount>> {
+    let accounts = ACCOUNTS
+        .list()
+        .into_iter()
+        .filter(|account| caller.authorize(&account.name).is_ok())
+        .map(AdminAccount::from)
+        .collect();
+    Json(accounts)
+}
+
+/// Create an account with a random secret.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/accounts",
+    tag = "admin",
+    request_body(
+        description = "The account.",
+        content(
+            (EnrollRequest = "application/json"),
+            (EnrollRequest = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "alice"),
+        )
+    ),
+    responses(
+        (status = 201, description = "The account has been created.", body = Enrollment),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 409, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+    )
+)]
+async fn create_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Input(request): Input<EnrollRequest>,
+) -> crate::Result<(StatusCode, Json<Enrollment>)> {
+    let enrollment = crate::api::enroll_account(&client, &caller, request)?;
+    Ok((StatusCode::CREATED, Json(enrollment)))
+}
+
+/// Disable an account, whose codes are rejected until it's enabled.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/accounts/{id}/disable",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 200, description = "The account has been disabled.", body = AdminAccount),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn disable_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<AdminAccount>> {
+    set_disabled(&client, &caller, &id, true)
+}
+
+/// Enable a disabled account.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/accounts/{id}/enable",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 200, description = "The account has been enabled.", body = AdminAccount),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn enable_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<AdminAccount>> {
+    set_disabled(&client, &caller, &id, false)
+}
+
+/// Disable or enable an account on behalf of the caller, recording it in the audit log.
+fn set_disabled(
+    client: &crate::ClientInfo,
+    caller: &crate::Caller,
+    id: &str,
+    disabled: bool,
+) -> crate::Result<Json<AdminAccount>> {
+    let action = if disabled {
+        AuditAction::Disable
+    } else {
+        AuditAction::Enable
+    };
+    let result = caller
+        .authorize(id)
+        .and_then(|()| ACCOUNTS.set_disabled(id, disabled));
+    record(action, id, client, &result);
+    result.map(|account| Json(account.into()))
+}
+
+/// Reset the failures of an account, lifting its lockout.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/accounts/{id}/reset",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 200, description = "The failures have been reset.", body = AdminAccount),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+    )
+)]
+async fn reset_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<AdminAccount>> {
+    let result = caller.authorize(&id).and_then(|()| ACCOUNTS.get(&id));
+    if result.is_ok() {
+        LOCKOUTS.reset(&id);
+    }
+    record(AuditAction::Reset, &id, &client, &result);
+    result.map(|account| Json(account.into()))
+}
+
+/// Remove an account.
+#[utoipa::path(
+    delete,
+    path = "/v1/admin/accounts/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 204, description = "The account has been removed."),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn remove_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<StatusCode> {
+    let result = caller.authorize(&id).and_then(|()| ACCOUNTS.remove(&id));
+    if result.is_ok() {
+        LOCKOUTS.reset(&id);
+        crate::totp::USED_STEPS.forget(&id);
+    }
+    record(AuditAction::Remove, &id, &client, &result);
+    result.map(|_| StatusCode::NO_CONTENT)
+}
+
+/// Replace the secret of an account with a random one.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/accounts/{id}/rotate",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 200, description = "The secret has been rotated.", body = Enrollment),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn rotate_account(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<Enrollment>> {
+    let result = caller.authorize(&id).and_then(|()| ACCOUNTS.rotate(&id));
+    if result.is_ok() {
+        crate::totp::USED_STEPS.forget(&id);
+    }
+    record(AuditAction::Rotate, &id, &client, &result);
+    result.map(|account| Json(account.into()))
+}
+
+/// Set the group and the policy of an account, replacing the current ones.
+///
+/// Algorithm, digits and period take effect when the secret is rotated.
+#[utoipa::path(
+    put,
+    path = "/v1/admin/accounts/{id}/policy",
+    tag = "admin",
+    params(("id" = String, Path, description = "Name of the account.")),
+    request_body(description = "The group and the policy.", content(
+        (AccountPolicy = "application/json"),
+    )),
+    responses(
+        (status = 200, description = "The policy has been set.", body = AdminAccount),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn set_account_policy(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+    body: Result<Json<AccountPolicy>, JsonRejection>,
+) -> crate::Result<Json<AdminAccount>> {
+    let result = caller.authorize(&id).and_then(|()| {
+        let Json(body) = body.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+        ACCOUNTS.set_policy(&id, body.group, body.policy)
+    });
+    record(AuditAction::Policy, &id, &client, &result);
+    result.map(|account| Json(account.into()))
+}
+
+/// List every group with its members, which are limited to accounts the caller can access.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/groups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Groups and their members.", body = [GroupInfo]),
+        (status = 401, response = crate::Error),
+    )
+)]
+async fn list_groups(caller: crate::Caller) -> Json<Vec<GroupInfo>> {
+    let mut groups = ACCOUNTS.groups();
+    for group in &mut groups {
+        group
+            .members
+            .retain(|member| caller.authorize(member).is_ok());
+    }
+    Json(groups)
+}
+
+/// Create or replace a group, whose policy applies to its members at once.
+///
+/// Algorithm, digits and period take effect when secrets of members are generated.
+#[utoipa::path(
+    put,
+    path = "/v1/admin/groups/{group}",
+    tag = "admin",
+    params(("group" = String, Path, description = "Name of the group.")),
+    request_body(description = "The policy of the group.", content(
+        (Policy = "application/json"),
+    )),
+    responses(
+        (status = 200, description = "The group has been saved.", body = Group),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+    )
+)]
+async fn put_group(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(group): Path<String>,
+    body: Result<Json<Policy>, JsonRejection>,
+) -> crate::Result<Json<Group>> {
+    let result = caller.authorize_all().and_then(|()| {
+        let Json(policy) = body.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+        ACCOUNTS.put_group(Group {
+            name: group.clone(),
+            policy,
+        })
+    });
+    record(
+        AuditAction::Policy,
+        &format!("{GROUP_PREFIX}{group}"),
+        &client,
+        &result,
+    );
+    result.map(Json)
+}
+
+/// Remove a group, which must have no members.
+#[utoipa::path(
+    delete,
+    path = "/v1/admin/groups/{group}",
+    tag = "admin",
+    params(("group" = String, Path, description = "Name of the group.")),
+    responses(
+        (status = 204, description = "The group has been removed."),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn remove_group(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(group): Path<String>,
+) -> crate::Result<StatusCode> {
+    let key = format!("{GROUP_PREFIX}{group}");
+    let result = caller
+        .authorize_all()
+        .and_then(|()| ACCOUNTS.remove_group(&group));
+    if result.is_ok() {
+        LOCKOUTS.reset(&key);
+    }
+    record(AuditAction::Policy, &key, &client, &result);
+    result.map(|_| StatusCode::NO_CONTENT)
+}
+
+/// Header carrying the passphrase of encrypted bundles.
+const PASSPHRASE_HEADER: &str = "x-bundle-passphrase";
+
+/// Options of exported bundles.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ExportQuery {
+    /// Format of the bundle.
+    #[param(inline)]
+    #[serde(default)]
+    format: BundleFormat,
+    /// Whether the bundle is encrypted (age/scrypt) with the passphrase
+    /// in header `x-bundle-passphrase`.
+    #[param(default = true)]
+    #[serde(default = "default_encrypt")]
+    encrypt: bool,
+}
+
+const fn default_encrypt() -> bool {
+    true
+}
+
+/// Options of imports.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ImportQuery {
+    /// What to do with existing accounts which differ from the imported ones.
+    #[param(inline)]
+    #[serde(default)]
+    policy: ConflictPolicy,
+    /// Whether to only report what would be changed.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Get the passphrase of encrypted bundles from the request headers.
+fn passphrase(headers: &HeaderMap) -> crate::Result<Option<String>> {
+    headers
+        .get(PASSPHRASE_HEADER)
+        .map(|value| {
+            value.to_str().map(ToOwned::to_owned).map_err(|_| {
+                crate::Error::InvalidInput(format!("{PASSPHRASE_HEADER} must be visible ASCII"))
+            })
+        })
+        .transpose()
+}
+
+/// Run blocking work (e.g. scrypt) off the async runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> crate::Result<T> + Send + 'static,
+) -> crate::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Export every enrolled account (but the default one) as a bundle, which includes secrets.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/export",
+    tag = "admin",
+    params(
+        ExportQuery,
+        ("x-bundle-passphrase" = Option<String>, Header, description = "Passphrase of the encrypted bundle."),
+    ),
+    responses(
+        (status = 200, description = "The bundle, which is ASCII-armored if encrypted.", content(
+            (String = "application/json"),
+            (String = "text/csv"),
+            (String = "application/octet-stream"),
+        )),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+    )
+)]
+async fn export_bundle(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> crate::Result<Response> {
+    let result = async {
+        let Query(query) = query.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+        caller.authorize_all()?;
+        let passphrase = match passphrase(&headers)? {
+            Some(passphrase) if query.encrypt => Some(passphrase),
+            None if query.encrypt => {
+                return Err(crate::Error::InvalidInput(format!(
+                    "{PASSPHRASE_HEADER} is required, unless encrypt=false"
+                )));
+            }
+            _ => None,
+        };
+        let format = query.format;
+        let bundle = blocking(move || crate::export_bundle(format, passphrase.as_deref())).await?;
+        Ok((format, query.encrypt, bundle))
+    }
+    .await;
+    record(AuditAction::Export, "*", &client, &result);
+    let (format, encrypted, bundle) = result?;
+    let (content_type, filename) = if encrypted {
+        (
+            "application/octet-stream",
+            format!("accounts.{}.age", format.extension()),
+        )
+    } else {
+        (
+            format.content_type(),
+            format!("accounts.{}", format.extension()),
+        )
+    };
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        ),
+        (header::CACHE_CONTROL, "no-store".to_owned()),
+    ];
+    Ok((headers, bundle).into_response())
+}
+
+/// Import accounts from a bundle in either format, which is decrypted if it's encrypted.
+///
+/// Either every change is made, or none of them is.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/import",
+    tag = "admin",
+    params(
+        ImportQuery,
+        ("x-bundle-passphrase" = Option<String>, Header, description = "Passphrase of the encrypted bundle."),
+    ),
+    request_body(description = "The bundle.", content(
+        (String = "application/json"),
+        (String = "text/csv"),
+        (String = "application/octet-stream"),
+    )),
+    responses(
+        (status = 200, description = "What has been changed, or would be in dry runs.", body = ImportDiff),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 409, response = crate::Error),
+    )
+)]
+async fn import_bundle(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    query: Result<Query<ImportQuery>, QueryRejection>,
+    body: axum::body::Bytes,
+) -> crate::Result<Json<ImportDiff>> {
+    let result = async {
+        let Query(query) = query.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+        caller.authorize_all()?;
+        let passphrase = passphrase(&headers)?;
+        // Bundles which cannot be decrypted within the request timeout are rejected.
+        let max_work_factor = Some(crate::bundle::WORK_FACTOR);
+        let accounts =
+            blocking(move || crate::bundle::decode(&body, passphrase.as_deref(), max_work_factor))
+                .await?;
+        ACCOUNTS.restore(accounts, query.policy, query.dry_run)
+    }
+    .await;
+    record(AuditAction::Import, "*", &client, &result);
+    result.map(Json)
+}
+
+/// Get the latest audit records, which are kept in memory (up to 1000).
+#[utoipa::path(
+    get,
+    path = "/v1/admin/audit",
+    tag = "admin",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Audit records, newest first.", body = [AuditRecord]),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+    )
+)]
+async fn get_audit_records(
+    caller: crate::Caller,
+    query: Result<Query<AuditQuery>, QueryRejection>,
+) -> crate::Result<Json<Vec<AuditRecord>>> {
+    let Query(query) = query.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    if !(1..=MAX_AUDIT_LIMIT).contains(&limit) {
+        return Err(crate::Error::InvalidInput(format!(
+            "limit must be between 1 and {MAX_AUDIT_LIMIT}"
+        )));
+    }
+    match &query.account {
+        Some(account) => caller.authorize(account)?,
+        None => caller.authorize_all()?,
+    }
+    let records = crate::audit::recent_records(limit, query.account.as_deref());
+    Ok(Json(records))
+}
+
+/// Routes of the admin API (`/v1/admin/...`), which are rate limited by `limited`.
+///
+/// Anonymous callers are rejected (see [`crate::auth::require_caller`]).
+pub(crate) fn admin_router(
+    limited: impl Fn(UtoipaMethodRouter) -> UtoipaMethodRouter,
+) -> OpenApiRouter {
+    OpenApiRouter::new()
+        .routes(limited(routes!(list_accounts, create_account)))
+        .routes(limited(routes!(remove_account)))
+        .routes(limited(routes!(disable_account)))
+        .routes(limited(routes!(enable_account)))
+        .routes(limited(routes!(reset_account)))
+        .routes(limited(routes!(rotate_account)))
+        .routes(limited(routes!(set_account_policy)))
+        .routes(limited(routes!(list_groups)))
+        .routes(limited(routes!(put_group, remove_group)))
+        .routes(limited(routes!(get_audit_records)))
+        .routes(limited(routes!(export_bundle)))
+        .routes(limited(routes!(import_bundle)))
+        .route_layer(axum::middleware::from_fn(crate::auth::require_caller))
+}
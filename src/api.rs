@@ -0,0 +1,765 @@
+use crate::extract::Input;
+use crate::otpauth::OTPAUTH_CONFIG;
+use crate::qr::{QrEcLevel, QrFormat, QrOptions};
+use crate::session::{SESSION_CONFIG, SessionClaims};
+use crate::store::{ACCOUNTS, AccountInfo, Enrollment};
+use crate::unlock::{UNLOCK_CONFIG, UnlockClaims};
+use axum::Json;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_axum::router::{OpenApiRouter, UtoipaMethodRouter};
+use utoipa_axum::routes;
+
+/// The route of the `OpenAPI` document.
+pub(crate) const OPENAPI_ROUTE: &str = "/v1/openapi.json";
+
+#[derive(Debug, OpenApi)]
+#[openapi(
+    info(description = "Time-based One-time Password (TOTP) web server."),
+    components(responses(crate::Error)),
+    modifiers(&SecuritySchemes),
+    security((), ("api_key" = [])),
+    tags(
+        (name = "verify", description = "Verify TOTP codes."),
+        (name = "accounts", description = "Look up accounts."),
+        (name = "enrollment", description = "Enroll new accounts."),
+        (name = "sessions", description = "Exchange TOTP codes for session tokens."),
+        (name = "unlocks", description = "Exchange TOTP codes for offline unlock tokens."),
+        (name = "approvals", description = "Approve with TOTP codes of several members of a group."),
+        (name = "challenges", description = "Answer challenges of relying parties with TOTP codes."),
+        (name = "admin", description = "Manage the lifecycle of accounts."),
+    )
+)]
+struct ApiDoc;
+
+/// Add security schemes, which are referred to by operations.
+#[derive(Debug)]
+struct SecuritySchemes;
+
+impl utoipa::Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(
+                crate::auth::API_KEY_HEADER,
+            ))),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Request body of enrollment.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct EnrollRequest {
+    /// Name of the new account, which is 1 to 64 characters of
+    /// ASCII letters, digits, `-`, `_`, `.` and `@`.
+    #[schema(example = "alice")]
+    account: String,
+    /// Issuer and label shown by authenticator apps.
+    #[serde(flatten)]
+    profile: crate::OtpauthProfile,
+    /// The group which the account joins, whose policy gives the parameters of the secret.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "game-x")]
+    group: Option<String>,
+}
+
+impl crate::extract::FromPlainText for EnrollRequest {
+    /// The text is the name of the account.
+    fn from_plain_text(text: &str) -> Self {
+        EnrollRequest {
+            account: text.to_owned(),
+            profile: crate::OtpauthProfile::default(),
+            group: None,
+        }
+    }
+}
+
+/// Request body of group verification.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct GroupToken {
+    /// The current TOTP code of any member.
+    #[schema(example = "123456")]
+    token: String,
+}
+
+impl crate::extract::FromPlainText for GroupToken {
+    /// The text is the token itself.
+    fn from_plain_text(text: &str) -> Self {
+        GroupToken {
+            token: text.to_owned(),
+        }
+    }
+}
+
+/// The member of a group which a TOTP code is valid for.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct GroupMatch {
+    /// Name of the group.
+    #[schema(example = "game-x")]
+    group: String,
+    /// Name of the member.
+    #[schema(example = "alice")]
+    account: String,
+}
+
+/// Options of QR code images.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct QrQuery {
+    /// Minimum width and height in pixels, which is rounded up to fit the modules of the code.
+    #[param(minimum = 64, maximum = 2048, default = 256)]
+    size: Option<u32>,
+    /// Error-correction level.
+    #[param(inline)]
+    ec: Option<QrEcLevel>,
+}
+
+/// A session token and its claims.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Session {
+    /// The token, which should be sent as `Authorization: Bearer <token>`.
+    token: String,
+    /// Claims of the token.
+    claims: SessionClaims,
+}
+
+/// Request body of unlocks.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct UnlockRequest {
+    /// The code and its account.
+    #[serde(flatten)]
+    input_token: crate::InputToken,
+    /// The device which the token is bound to (1 to 64 bytes).
+    #[schema(example = "3f2a9c1e-device")]
+    device_id: String,
+    /// Features which the token unlocks (at most 16), which are granted as scopes
+    /// (along with `scope`) within the policy of the account.
+    #[serde(default)]
+    #[schema(example = json!(["console", "cheats"]))]
+    features: Vec<String>,
+}
+
+/// An unlock token and its claims.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Unlock {
+    /// The base64url-encoded token, which clients verify with the public key.
+    token: String,
+    /// Claims of the token.
+    claims: UnlockClaims,
+}
+
+/// The public key which unlock tokens are verified with.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct UnlockKey {
+    /// The base64-encoded Ed25519 public key (32 bytes).
+    public_key: String,
+}
+
+/// List accounts which the caller is allowed to access.
+#[utoipa::path(
+    get,
+    path = "/v1/accounts",
+    tag = "accounts",
+    responses(
+        (status = 200, description = "Accounts which the caller can access.", body = [AccountInfo]),
+        (status = 401, response = crate::Error),
+    )
+)]
+async fn list_accounts(caller: crate::Caller) -> Json<Vec<AccountInfo>> {
+    let accounts = ACCOUNTS
+        .list()
+        .into_iter()
+        .filter(|account| caller.authorize(&account.name).is_ok())
+        .map(AccountInfo::from)
+        .collect();
+    Json(accounts)
+}
+
+/// Get an account.
+#[utoipa::path(
+    get,
+    path = "/v1/accounts/{id}",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Name of the account.")),
+    responses(
+        (status = 200, description = "The account.", body = AccountInfo),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+    )
+)]
+async fn get_account(
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<AccountInfo>> {
+    caller.authorize(&id)?;
+    ACCOUNTS.get(&id).map(|account| Json(account.into()))
+}
+
+/// Get the QR code of the otpauth URL of an account as a PNG image.
+#[utoipa::path(
+    get,
+    path = "/v1/accounts/{id}/qr.png",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Name of the account."), QrQuery),
+    responses(
+        (status = 200, description = "The QR code.", content_type = "image/png"),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+    )
+)]
+async fn get_account_qr_png(
+    caller: crate::Caller,
+    Path(id): Path<String>,
+    query: Result<Query<QrQuery>, QueryRejection>,
+) -> crate::Result<Response> {
+    account_qr(&caller, &id, query, QrFormat::Png)
+}
+
+/// Get the QR code of the otpauth URL of an account as an SVG image.
+#[utoipa::path(
+    get,
+    path = "/v1/accounts/{id}/qr.svg",
+    tag = "accounts",
+    params(("id" = String, Path, description = "Name of the account."), QrQuery),
+    responses(
+        (status = 200, description = "The QR code.", content_type = "image/svg+xml", body = String),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+    )
+)]
+async fn get_account_qr_svg(
+    caller: crate::Caller,
+    Path(id): Path<String>,
+    query: Result<Query<QrQuery>, QueryRejection>,
+) -> crate::Result<Response> {
+    account_qr(&caller, &id, query, QrFormat::Svg)
+}
+
+/// Render the QR code of the account, which isn't cached as it contains the secret.
+fn account_qr(
+    caller: &crate::Caller,
+    id: &str,
+    query: Result<Query<QrQuery>, QueryRejection>,
+    format: QrFormat,
+) -> crate::Result<Response> {
+    let Query(query) = query.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+    caller.authorize(id)?;
+    let account = ACCOUNTS.get(id)?;
+    let options = QrOptions {
+        size: query.size.unwrap_or(crate::qr::DEFAULT_QR_SIZE),
+        ec_level: query.ec.unwrap_or_default(),
+    };
+    let image = crate::qr::render_qr_image(&OTPAUTH_CONFIG.url(&account), format, options)?;
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type()),
+        (header::CACHE_CONTROL, "no-store"),
+    ];
+    Ok((headers, image).into_response())
+}
+
+/// Enroll a new account with a random secret.
+#[utoipa::path(
+    post,
+    path = "/v1/enrollment",
+    tag = "enrollment",
+    request_body(
+        description = "The account, which is also accepted in the query string without a body.",
+        content(
+            (EnrollRequest = "application/json"),
+            (EnrollRequest = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "alice"),
+        )
+    ),
+    responses(
+        (status = 201, description = "The account has been enrolled.", body = Enrollment),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 409, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+    )
+)]
+async fn enroll(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Input(request): Input<EnrollRequest>,
+) -> crate::Result<(StatusCode, Json<Enrollment>)> {
+    let enrollment = enroll_account(&client, &caller, request)?;
+    Ok((StatusCode::CREATED, Json(enrollment)))
+}
+
+/// Enroll a new account on behalf of the caller, recording it in the audit log.
+///
+/// # Errors
+///
+/// Returns Err if the caller cannot access the account, the name or the profile
+/// is invalid, the account already exists, the group doesn't exist,
+/// or the accounts file cannot be written.
+pub(crate) fn enroll_account(
+    client: &crate::ClientInfo,
+    caller: &crate::Caller,
+    request: EnrollRequest,
+) -> crate::Result<Enrollment> {
+    use crate::audit::{AuditAction, record};
+    let result = caller.authorize(&request.account).and_then(|()| {
+        ACCOUNTS.enroll(&request.account, request.profile, request.group.as_deref())
+    });
+    record(AuditAction::Enroll, &request.account, client, &result);
+    result.map(Enrollment::from)
+}
+
+/// Verify a TOTP code and issue a session token.
+#[utoipa::path(
+    post,
+    path = "/v1/sessions",
+    tag = "sessions",
+    request_body(
+        description = "The token, which is also accepted in the query string without a body.",
+        content(
+            (crate::InputToken = "application/json"),
+            (crate::InputToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 201, description = "The token is valid, and a session is issued.", body = Session),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument(skip(headers))]
+async fn create_session(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    Input(input_token): Input<crate::InputToken>,
+) -> crate::Result<(StatusCode, Json<Session>)> {
+    let account = input_token.account(&headers);
+    let verified = crate::totp::verify(
+        &client,
+        &caller,
+        account,
+        input_token.token(),
+        input_token.second_token(),
+    )?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let scopes = verified.grant(&input_token.scopes()).scopes;
+    let (token, claims) = SESSION_CONFIG.issue(
+        account,
+        now,
+        verified.policy.session_ttl,
+        scopes,
+        verified.step,
+    );
+    Ok((StatusCode::CREATED, Json(Session { token, claims })))
+}
+
+/// Verify another TOTP code of the account of a session, and issue a session token
+/// with an updated `auth_time` and the newly granted scopes added.
+///
+/// The code must be later than the one which the session has been issued for,
+/// so the code which created it cannot step it up.
+#[utoipa::path(
+    post,
+    path = "/v1/sessions/step-up",
+    tag = "sessions",
+    security(("session" = [])),
+    request_body(
+        description = "The token, whose account is the one of the session.",
+        content(
+            (crate::InputToken = "application/json"),
+            (crate::InputToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 201, description = "The token is valid, and a session is issued.", body = Session),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 423, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument]
+async fn step_up_session(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    claims: SessionClaims,
+    Input(input_token): Input<crate::InputToken>,
+) -> crate::Result<(StatusCode, Json<Session>)> {
+    let verified = crate::totp::verify(
+        &client,
+        &caller,
+        &claims.sub,
+        input_token.token(),
+        input_token.second_token(),
+    )?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let scopes = verified.grant(&input_token.scopes()).scopes;
+    let (token, claims) = SESSION_CONFIG.step_up(
+        claims,
+        now,
+        verified.policy.session_ttl,
+        scopes,
+        verified.step,
+    )?;
+    Ok((StatusCode::CREATED, Json(Session { token, claims })))
+}
+
+/// Check if a TOTP code is valid for any member of a group.
+///
+/// Members which the caller cannot access, or which are disabled or locked out,
+/// are skipped. Invalid codes count against the lockout of the group.
+#[utoipa::path(
+    post,
+    path = "/v1/groups/{group}/verify",
+    tag = "verify",
+    params(("group" = String, Path, description = "Name of the group.")),
+    request_body(
+        description = "The token of any member.",
+        content(
+            (GroupToken = "application/json"),
+            (GroupToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 200, description = "The token is valid for a member.", body = GroupMatch),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 423, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument]
+async fn verify_group(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(group): Path<String>,
+    Input(request): Input<GroupToken>,
+) -> crate::Result<Json<GroupMatch>> {
+    let (member, _) = crate::totp::verify_group(&client, &caller, &group, &request.token)?;
+    Ok(Json(GroupMatch {
+        group,
+        account: member.name,
+    }))
+}
+
+/// Verify a TOTP code and issue an unlock token bound to the device,
+/// which game clients verify offline.
+///
+/// Features which aren't granted as scopes are left out of the token.
+#[utoipa::path(
+    post,
+    path = "/v1/unlocks",
+    tag = "unlocks",
+    request_body(description = "The token, the device and the features.", content(
+        (UnlockRequest = "application/json"),
+    )),
+    responses(
+        (status = 201, description = "The token is valid, and an unlock token is issued.", body = Unlock),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 423, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument(skip(headers))]
+async fn create_unlock(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    body: Result<Json<UnlockRequest>, JsonRejection>,
+) -> crate::Result<(StatusCode, Json<Unlock>)> {
+    let Json(request) = body.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+    let input_token = &request.input_token;
+    let mut requested = request.features;
+    requested.extend(input_token.scopes());
+    // Invalid requests are rejected before the code is checked.
+    crate::unlock::validate_claims(&request.device_id, &requested)?;
+    let account = input_token.account(&headers);
+    let verified = crate::totp::verify(
+        &client,
+        &caller,
+        account,
+        input_token.token(),
+        input_token.second_token(),
+    )?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let features = verified.grant(&requested).scopes;
+    let (token, claims) = UNLOCK_CONFIG.issue(account, &request.device_id, &features, now)?;
+    Ok((StatusCode::CREATED, Json(Unlock { token, claims })))
+}
+
+/// Get the public key which unlock tokens are verified with.
+#[utoipa::path(
+    get,
+    path = "/v1/unlocks/key",
+    tag = "unlocks",
+    responses(
+        (status = 200, description = "The public key.", body = UnlockKey),
+        (status = 401, response = crate::Error),
+    )
+)]
+async fn get_unlock_key() -> Json<UnlockKey> {
+    use base64::Engine;
+    let public_key = base64::engine::general_purpose::STANDARD.encode(UNLOCK_CONFIG.public_key());
+    Json(UnlockKey { public_key })
+}
+
+/// Get the claims of the session token.
+#[utoipa::path(
+    get,
+    path = "/v1/sessions",
+    tag = "sessions",
+    security(("session" = [])),
+    responses(
+        (status = 200, description = "The session is valid.", body = SessionClaims),
+        (status = 401, response = crate::Error),
+    )
+)]
+async fn get_session(claims: SessionClaims) -> Json<SessionClaims> {
+    Json(claims)
+}
+
+/// Mark responses of the deprecated route `POST /`, pointing to its successor.
+async fn deprecated(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(
+        header::LINK,
+        HeaderValue::from_static("</v1/verify>; rel=\"successor-version\""),
+    );
+    response
+}
+
+/// Which routes of the versioned API are served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Routes {
+    /// Every route.
+    All,
+    /// Every route but the admin API, which is served on its own listener.
+    Public,
+    /// Only the admin API (`/v1/admin/...`).
+    Admin,
+}
+
+impl Routes {
+    /// Routes served on the public listeners (`LISTEN_ADDRS`, or AWS Lambda).
+    ///
+    /// The admin API is left out if it's served on its own listeners
+    /// (`ADMIN_LISTEN_ADDRS`), or if no caller can be authenticated.
+    pub(crate) fn public() -> Self {
+        if crate::listen::ADMIN_LISTEN_ADDRS.is_some() {
+            return Routes::Public;
+        }
+        if !crate::auth::can_authenticate() {
+            tracing::warn!(
+                "The admin API isn't served, since no caller can be authenticated. \
+                 Set API_KEYS, HMAC_KEYS or ROLE_BINDINGS, or ADMIN_LISTEN_ADDRS."
+            );
+            return Routes::Public;
+        }
+        Routes::All
+    }
+}
+
+/// Routes of the versioned API, which are documented by the `OpenAPI` document.
+///
+/// Only `served` routes are added. Every route is authenticated,
+/// and rate limited if `rate_limit` is true.
+fn api_router(rate_limit: bool, served: Routes) -> OpenApiRouter {
+    use axum::middleware::{from_fn, from_fn_with_state};
+
+    let limited = |(schemas, paths, method_router): UtoipaMethodRouter| {
+        let method_router = match paths.paths.keys().next() {
+            Some(path) if rate_limit => crate::rate_limit::rate_limited(path, method_router),
+            _ => method_router,
+        };
+        (schemas, paths, method_router)
+    };
+    let mut router = OpenApiRouter::with_openapi(ApiDoc::openapi());
+    if served != Routes::Admin {
+        router = router
+            .routes(limited(routes!(crate::totp::check_current)))
+            .routes(limited(routes!(list_accounts)))
+            .routes(limited(routes!(get_account)))
+            .routes(limited(routes!(get_account_qr_png)))
+            .routes(limited(routes!(get_account_qr_svg)))
+            .routes(limited(routes!(enroll)))
+            .routes(limited(routes!(create_session, get_session)))
+            .routes(limited(routes!(step_up_session)))
+            .routes(limited(routes!(verify_group)))
+            .routes(limited(routes!(crate::approval::create_approval)))
+            .routes(limited(routes!(
+                crate::approval::get_approval,
+                crate::approval::approve
+            )))
+            .routes(limited(routes!(crate::challenge::create_challenge)))
+            .routes(limited(routes!(crate::challenge::answer_challenge)))
+            .routes(limited(routes!(create_unlock)))
+            .routes(limited(routes!(get_unlock_key)));
+    }
+    if served != Routes::Public {
+        router = router.merge(crate::admin::admin_router(limited));
+    }
+    let router = router
+        .route_layer(from_fn(crate::rbac::authorize_role))
+        .route_layer(from_fn(crate::authenticate));
+    if !rate_limit {
+        return router;
+    }
+    let paths = router.get_openapi().paths.paths.keys().map(String::as_str);
+    let ip_limits = crate::rate_limit::IpLimits::new(paths);
+    router.route_layer(from_fn_with_state(
+        ip_limits,
+        crate::rate_limit::limit_by_ip,
+    ))
+}
+
+/// The `OpenAPI` document of the versioned API.
+#[cfg_attr(not(test), expect(dead_code))]
+pub(crate) fn openapi() -> utoipa::openapi::OpenApi {
+    api_router(false, Routes::All).into_openapi()
+}
+
+/// Routes of the versioned API (`/v1/...`), its `OpenAPI` document,
+/// and the deprecated route `POST /`, which is an alias of `POST /v1/verify`.
+///
+/// Routes are rate limited if `rate_limit` is true.
+pub(crate) fn router(rate_limit: bool, served: Routes) -> axum::Router {
+    use axum::middleware::{from_fn, from_fn_with_state, map_response};
+    use axum::routing::get;
+
+    let (router, openapi) = api_router(rate_limit, served).split_for_parts();
+    let router = router.route(OPENAPI_ROUTE, get(move || async move { Json(openapi) }));
+    if served == Routes::Admin {
+        return router;
+    }
+    let root = get(crate::handler_405).post(crate::check_current);
+    // Rate limited around authentication, as the routes above.
+    let root = if rate_limit {
+        crate::rate_limit::rate_limited("/", root)
+            .layer(from_fn(crate::rbac::authorize_role))
+            .layer(from_fn(crate::authenticate))
+            .layer(from_fn_with_state(
+                crate::rate_limit::IpLimits::new(["/"]),
+                crate::rate_limit::limit_by_ip,
+            ))
+    } else {
+        root.layer(from_fn(crate::rbac::authorize_role))
+            .layer(from_fn(crate::authenticate))
+    };
+    let root = root.layer(map_response(deprecated));
+    router.route("/", root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    /// Path of the committed `OpenAPI` document, which clients are generated from.
+    const OPENAPI_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/docs/openapi.json");
+
+    /// The committed document must be identical to the generated one.
+    ///
+    /// Run tests with env var `UPDATE_OPENAPI=1` to overwrite the committed document.
+    #[test]
+    fn test_openapi_document() {
+        let generated = openapi().to_pretty_json().unwrap() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(OPENAPI_PATH, &generated).unwrap();
+        }
+        let committed = std::fs::read_to_string(OPENAPI_PATH).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "{OPENAPI_PATH} is outdated. Run tests with UPDATE_OPENAPI=1 to update it."
+        );
+        assert!(generated.contains(r#""openapi": "3.1.0""#));
+    }
+
+    /// Every operation in the document must be routed.
+    #[tokio::test]
+    async fn test_openapi_operations_are_routed() {
+        let router = router(false, Routes::All);
+        let document = serde_json::to_value(openapi()).unwrap();
+        let paths = document["paths"].as_object().unwrap();
+        assert!(!paths.is_empty());
+        for (path, operations) in paths {
+            for method in operations.as_object().unwrap().keys() {
+                let request = axum::http::Request::builder()
+                    .method(method.to_uppercase().as_str())
+                    .uri(
+                        path.replace("{id}", "default")
+                            .replace("{group}", "missing"),
+                    )
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap();
+                let response = router.clone().oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{method} {path}");
+                // Unmatched routes fall through with an empty 404.
+                assert!(
+                    status != StatusCode::NOT_FOUND || !body.is_empty(),
+                    "{method} {path} isn't routed"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_openapi_route() {
+        let request = axum::http::Request::get(OPENAPI_ROUTE)
+            .body(Body::empty())
+            .unwrap();
+        let response = router(false, Routes::All).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let served: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(served, serde_json::to_value(openapi()).unwrap());
+    }
+}
@@ -0,0 +1,409 @@
+use crate::extract::Input;
+use crate::store::{ACCOUNTS, Account};
+use crate::utils::now_secs;
+use axum::Json;
+use axum::extract::Path;
+use axum::extract::rejection::JsonRejection;
+use axum::http::{HeaderMap, StatusCode};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use utoipa::ToSchema;
+
+/// Env var used to set how long (in seconds) approvals wait for their approvers.
+const APPROVAL_TTL: &str = "APPROVAL_TTL";
+/// Max number of approvers which an approval may require.
+const MAX_REQUIRED: u8 = 16;
+/// Max length (in bytes) of the purpose of an approval.
+const MAX_PURPOSE_LEN: usize = 256;
+
+/// An approval, which is approved once codes of `required` distinct members
+/// of the group have been verified before it expires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Approval {
+    /// Random id, which approvers send their codes to.
+    #[schema(example = "q2V0bGJ8c3VwZXJzZWNyZXQ")]
+    pub(crate) id: String,
+    /// The group whose members may approve.
+    #[schema(example = "ops")]
+    pub(crate) group: String,
+    /// What is being approved, e.g. shown to approvers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "enable god mode on eu-1")]
+    pub(crate) purpose: Option<String>,
+    /// Number of distinct members which must approve.
+    #[schema(example = 2)]
+    pub(crate) required: u8,
+    /// Members which have approved, in order.
+    #[schema(example = json!(["alice", "bob"]))]
+    pub(crate) approvers: Vec<String>,
+    /// When the approval was created (seconds since the Unix epoch).
+    pub(crate) created_at: u64,
+    /// When the approval expires unless approved (seconds since the Unix epoch).
+    pub(crate) expires_at: u64,
+    /// When the last required member approved (seconds since the Unix epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) approved_at: Option<u64>,
+}
+
+/// State of an approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ApprovalStatus {
+    /// More members must approve.
+    Pending,
+    /// Enough members have approved.
+    Approved,
+    /// It expired before enough members approved.
+    Expired,
+}
+
+impl Approval {
+    /// Create an approval with a random id, which expires after `ttl` seconds.
+    pub(crate) fn new(
+        group: String,
+        required: u8,
+        purpose: Option<String>,
+        now: u64,
+        ttl: u64,
+    ) -> Self {
+        Approval {
+            id: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()),
+            group,
+            purpose,
+            required,
+            approvers: Vec::new(),
+            created_at: now,
+            expires_at: now.saturating_add(ttl),
+            approved_at: None,
+        }
+    }
+
+    /// The state of the approval at `now`.
+    pub(crate) fn status(&self, now: u64) -> ApprovalStatus {
+        if self.approved_at.is_some() {
+            ApprovalStatus::Approved
+        } else if self.expires_at <= now {
+            ApprovalStatus::Expired
+        } else {
+            ApprovalStatus::Pending
+        }
+    }
+
+    /// Add the account, whose code has been verified at `now`, to the approvers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ApprovalRejected`] if the approval isn't pending,
+    /// the account isn't a member of the group, or it has approved already.
+    pub(crate) fn approve(&mut self, account: &Account, now: u64) -> crate::Result<()> {
+        let reject = |reason| {
+            Err(crate::Error::ApprovalRejected {
+                id: self.id.clone(),
+                reason,
+            })
+        };
+        match self.status(now) {
+            ApprovalStatus::Pending => {}
+            ApprovalStatus::Approved => return reject("it has been approved already"),
+            ApprovalStatus::Expired => return reject("it has expired"),
+        }
+        if account.group.as_ref() != Some(&self.group) {
+            return reject("the account isn't a member of the group");
+        }
+        if self.approvers.contains(&account.name) {
+            return reject("the account has approved it already");
+        }
+        self.approvers.push(account.name.clone());
+        if self.approvers.len() >= usize::from(self.required) {
+            self.approved_at = Some(now);
+        }
+        Ok(())
+    }
+}
+
+/// An approval and its state.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ApprovalInfo {
+    #[serde(flatten)]
+    approval: Approval,
+    /// The state of the approval.
+    status: ApprovalStatus,
+}
+
+impl ApprovalInfo {
+    fn new(approval: Approval, now: u64) -> Self {
+        let status = approval.status(now);
+        ApprovalInfo { approval, status }
+    }
+}
+
+/// Request body of approvals.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ApprovalRequest {
+    /// Number of distinct members which must approve (2 to 16).
+    #[serde(default = "default_required")]
+    #[schema(default = 2, example = 2)]
+    required: u8,
+    /// What is being approved (at most 256 bytes), e.g. shown to approvers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "enable god mode on eu-1")]
+    purpose: Option<String>,
+}
+
+const fn default_required() -> u8 {
+    2
+}
+
+impl ApprovalRequest {
+    /// Check if the number of approvers and the purpose are valid.
+    fn validate(&self) -> crate::Result<()> {
+        if !(2..=MAX_REQUIRED).contains(&self.required) {
+            return Err(crate::Error::InvalidInput(format!(
+                "required must be 2 to {MAX_REQUIRED}"
+            )));
+        }
+        self.purpose.as_deref().map_or(Ok(()), validate_purpose)
+    }
+}
+
+/// Check if the purpose (of approvals or challenges) is 1 to 256 bytes
+/// without control characters.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if the purpose is invalid.
+pub(crate) fn validate_purpose(purpose: &str) -> crate::Result<()> {
+    if purpose.is_empty()
+        || purpose.len() > MAX_PURPOSE_LEN
+        || purpose.chars().any(char::is_control)
+    {
+        return Err(crate::Error::InvalidInput(format!(
+            "purpose must be 1 to {MAX_PURPOSE_LEN} bytes without control characters"
+        )));
+    }
+    Ok(())
+}
+
+/// Check if the caller is allowed to access every member of the group,
+/// or every account if the group doesn't exist anymore.
+fn authorize_group(caller: &crate::Caller, group: &str) -> crate::Result<()> {
+    match ACCOUNTS.members(group) {
+        Ok((_, members)) => members
+            .iter()
+            .try_for_each(|member| caller.authorize(&member.name)),
+        Err(_) => caller.authorize_all(),
+    }
+}
+
+/// Create an approval, which must be approved by codes of distinct members of the group
+/// before it expires (see `APPROVAL_TTL`).
+///
+/// The caller must be allowed to access every member of the group.
+#[utoipa::path(
+    post,
+    path = "/v1/groups/{group}/approvals",
+    tag = "approvals",
+    params(("group" = String, Path, description = "Name of the group.")),
+    request_body(description = "The number of approvers and the purpose.", content(
+        (ApprovalRequest = "application/json"),
+    )),
+    responses(
+        (status = 201, description = "The approval has been created.", body = ApprovalInfo),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+    )
+)]
+#[tracing::instrument]
+pub(crate) async fn create_approval(
+    caller: crate::Caller,
+    Path(group): Path<String>,
+    body: Result<Json<ApprovalRequest>, JsonRejection>,
+) -> crate::Result<(StatusCode, Json<ApprovalInfo>)> {
+    let Json(request) = body.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+    request.validate()?;
+    authorize_group(&caller, &group)?;
+    let now = now_secs();
+    let approval = Approval::new(
+        group,
+        request.required,
+        request.purpose,
+        now,
+        *APPROVAL_TTL_SECS,
+    );
+    let approval = ACCOUNTS.create_approval(approval, now)?;
+    Ok((StatusCode::CREATED, Json(ApprovalInfo::new(approval, now))))
+}
+
+/// Get an approval and its state, with the members which have approved it so far.
+#[utoipa::path(
+    get,
+    path = "/v1/approvals/{id}",
+    tag = "approvals",
+    params(("id" = String, Path, description = "Id of the approval.")),
+    responses(
+        (status = 200, description = "The approval.", body = ApprovalInfo),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+    )
+)]
+pub(crate) async fn get_approval(
+    caller: crate::Caller,
+    Path(id): Path<String>,
+) -> crate::Result<Json<ApprovalInfo>> {
+    let approval = ACCOUNTS.get_approval(&id)?;
+    authorize_group(&caller, &approval.group)?;
+    Ok(Json(ApprovalInfo::new(approval, now_secs())))
+}
+
+/// Approve with a TOTP code of a member of the group.
+///
+/// The code is verified like `POST /v1/verify`, and each member approves once.
+#[utoipa::path(
+    post,
+    path = "/v1/approvals/{id}",
+    tag = "approvals",
+    params(("id" = String, Path, description = "Id of the approval.")),
+    request_body(
+        description = "The token of a member, which is also accepted in the query string without a body.",
+        content(
+            (crate::InputToken = "application/json"),
+            (crate::InputToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 200, description = "The member has approved.", body = ApprovalInfo),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 409, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 423, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument(skip(headers))]
+pub(crate) async fn approve(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Input(input_token): Input<crate::InputToken>,
+) -> crate::Result<Json<ApprovalInfo>> {
+    use crate::audit::{AuditAction, record};
+    // Unknown approvals are rejected before the code is checked.
+    ACCOUNTS.get_approval(&id)?;
+    let account = input_token.account(&headers);
+    let verified = crate::totp::verify(&client, &caller, account, input_token.token(), None)?;
+    let now = now_secs();
+    let result = ACCOUNTS.approve(&id, &verified.account, now);
+    record(AuditAction::Approve, account, &client, &result);
+    Ok(Json(ApprovalInfo::new(result?, now)))
+}
+
+/// Seconds which approvals wait for their approvers.
+///
+/// If env var `APPROVAL_TTL` hasn't been set, the default value 300 will be set.
+///
+/// # Panics
+///
+/// Panics when `APPROVAL_TTL` isn't a positive integer.
+pub(crate) static APPROVAL_TTL_SECS: LazyLock<u64> = LazyLock::new(init_approval_ttl);
+
+fn init_approval_ttl() -> u64 {
+    std::env::var(APPROVAL_TTL).map_or(300, |value| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&ttl| ttl != 0)
+            .unwrap_or_else(|| panic!("{APPROVAL_TTL} must be a positive integer!"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn member(name: &str, group: Option<&str>) -> Account {
+        let mut account = Account::new(name, vec![0; 20], NOW, crate::OtpauthProfile::default());
+        account.group = group.map(ToOwned::to_owned);
+        account
+    }
+
+    #[test]
+    fn test_approval() {
+        let mut approval = Approval::new("ops".to_owned(), 2, None, NOW, 60);
+        let alice = member("alice", Some("ops"));
+        let bob = member("bob", Some("ops"));
+        let carol = member("carol", Some("ops"));
+        assert_eq!(approval.status(NOW), ApprovalStatus::Pending);
+        approval.approve(&alice, NOW + 1).unwrap();
+        let rejected = approval.approve(&alice, NOW + 2);
+        assert!(matches!(
+            rejected,
+            Err(crate::Error::ApprovalRejected { .. })
+        ));
+        let outsider = member("mallory", Some("dev"));
+        assert!(approval.approve(&outsider, NOW + 2).is_err());
+        assert!(approval.approve(&member("trent", None), NOW + 2).is_err());
+        approval.approve(&bob, NOW + 3).unwrap();
+        assert_eq!(approval.approvers, ["alice", "bob"]);
+        assert_eq!(approval.approved_at, Some(NOW + 3));
+        // Approved ones stay approved after they would have expired.
+        assert_eq!(approval.status(NOW + 60), ApprovalStatus::Approved);
+        assert!(approval.approve(&carol, NOW + 4).is_err());
+    }
+
+    #[test]
+    fn test_approval_expired() {
+        let mut approval = Approval::new("ops".to_owned(), 2, None, NOW, 60);
+        approval
+            .approve(&member("alice", Some("ops")), NOW)
+            .unwrap();
+        assert_eq!(approval.status(NOW + 60), ApprovalStatus::Expired);
+        assert!(
+            approval
+                .approve(&member("bob", Some("ops")), NOW + 60)
+                .is_err()
+        );
+        assert_eq!(approval.approvers, ["alice"]);
+    }
+
+    #[rstest]
+    #[case("{}", true)]
+    #[case(r#"{"required":16,"purpose":"enable god mode"}"#, true)]
+    #[case(r#"{"required":1}"#, false)]
+    #[case(r#"{"required":17}"#, false)]
+    #[case(r#"{"purpose":"line\nbreak"}"#, false)]
+    #[case(r#"{"purpose":""}"#, false)]
+    fn test_approval_request(#[case] json: &str, #[case] valid: bool) {
+        let request: ApprovalRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.validate().is_ok(), valid);
+    }
+
+    #[test]
+    fn test_approval_ttl_default() {
+        assert!(std::env::var(APPROVAL_TTL).is_err());
+        assert_eq!(*APPROVAL_TTL_SECS, 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "APPROVAL_TTL must be a positive integer!")]
+    fn test_approval_ttl_var_panic() {
+        unsafe { std::env::set_var(APPROVAL_TTL, "0") }
+        let _ = *APPROVAL_TTL_SECS;
+    }
+}
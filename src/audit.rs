@@ -0,0 +1,398 @@
+use crate::client::ClientInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+/// Env var used to set the path of the audit log file (JSON Lines).
+const AUDIT_LOG_PATH: &str = "AUDIT_LOG_PATH";
+
+/// Max number of recent records kept in memory (see [`recent_records`]).
+const MAX_RECENT_RECORDS: usize = 1000;
+
+/// The `prev_hash` of the first record in an audit log.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What kind of operation is audited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// A TOTP code has been verified.
+    Verify,
+    /// An account has been enrolled.
+    Enroll,
+    /// The secret of an account has been rotated.
+    Rotate,
+    /// An account has been removed.
+    Remove,
+    /// An account has been locked out.
+    Lockout,
+    /// Accounts have been imported from a bundle.
+    Import,
+    /// Accounts have been exported as a bundle.
+    Export,
+    /// An account has been disabled.
+    Disable,
+    /// An account has been enabled.
+    Enable,
+    /// The failures and the lockout of an account have been reset.
+    Reset,
+    /// A request has been denied, since no role of the caller grants the permission.
+    Deny,
+    /// A group (`group:<name>`) or the group and policy of an account have been changed.
+    Policy,
+    /// A member of a group has approved an approval.
+    Approve,
+}
+
+/// Whether the audited operation succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The operation succeeded.
+    Success,
+    /// The operation failed (see [`AuditRecord::reason`]).
+    Failure,
+}
+
+/// A record in the audit log, which is hash-chained to the previous one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AuditRecord {
+    /// Sequence number, starting from 0.
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// What kind of operation is audited.
+    pub action: AuditAction,
+    /// The account which the operation is applied to.
+    pub account: String,
+    /// Source IP address of the request.
+    #[schema(value_type = Option<String>, example = "192.0.2.1")]
+    pub source_ip: Option<IpAddr>,
+    /// `User-Agent` of the request.
+    pub user_agent: Option<String>,
+    /// Whether the operation succeeded.
+    pub outcome: AuditOutcome,
+    /// Why the operation failed.
+    pub reason: Option<String>,
+    /// `X-Request-Id` of the request.
+    pub request_id: Option<String>,
+    /// The hash of the previous record ([`GENESIS_HASH`] for the first record).
+    pub prev_hash: String,
+    /// SHA-256 of this record (hex-encoded), computed with this field left empty.
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// Compute the hash of this record, regardless of the current `hash` field.
+    fn compute_hash(&self) -> String {
+        use sha2::{Digest, Sha256};
+        let unhashed = AuditRecord {
+            hash: String::new(),
+            ..self.clone()
+        };
+        let bytes = serde_json::to_vec(&unhashed).unwrap_or_else(|e| {
+            panic!("Failed to serialize audit record. Error: {e}.");
+        });
+        hex::encode(Sha256::digest(bytes))
+    }
+}
+
+/// Append-only audit log written to a JSON Lines file (if any),
+/// whose latest records are kept in memory.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    file: Option<std::fs::File>,
+    next_seq: u64,
+    last_hash: String,
+    /// The latest records, oldest first.
+    recent: VecDeque<AuditRecord>,
+}
+
+impl AuditLog {
+    /// Create an audit log which isn't written to any file.
+    pub(crate) fn in_memory() -> Self {
+        AuditLog {
+            file: None,
+            next_seq: 0,
+            last_hash: GENESIS_HASH.to_owned(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Open (or create) the audit log file and verify the existing records.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the file cannot be opened or the hash chain is broken.
+    pub(crate) fn open(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path.as_ref())?;
+        let mut recent = VecDeque::new();
+        let (next_seq, last_hash) = verify_chain(std::io::BufReader::new(&file), |record| {
+            push_recent(&mut recent, record);
+        })?;
+        Ok(AuditLog {
+            file: Some(file),
+            next_seq,
+            last_hash,
+            recent,
+        })
+    }
+
+    /// Append a new record to the audit log.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the record cannot be written to the file.
+    pub(crate) fn append(
+        &mut self,
+        action: AuditAction,
+        account: &str,
+        client: &ClientInfo,
+        reason: Option<String>,
+    ) -> crate::Result<AuditRecord> {
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis();
+        let mut record = AuditRecord {
+            seq: self.next_seq,
+            timestamp_ms: u64::try_from(timestamp_ms).unwrap_or(u64::MAX),
+            action,
+            account: account.to_owned(),
+            source_ip: client.ip,
+            user_agent: client.user_agent.clone(),
+            outcome: if reason.is_none() {
+                AuditOutcome::Success
+            } else {
+                AuditOutcome::Failure
+            },
+            reason,
+            request_id: client.request_id.clone(),
+            prev_hash: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+
+        if let Some(file) = &mut self.file {
+            let mut line = serde_json::to_vec(&record).unwrap_or_else(|e| {
+                panic!("Failed to serialize audit record. Error: {e}.");
+            });
+            line.push(b'\n');
+            file.write_all(&line)?;
+            file.flush()?;
+        }
+
+        self.next_seq += 1;
+        self.last_hash.clone_from(&record.hash);
+        push_recent(&mut self.recent, record.clone());
+        Ok(record)
+    }
+}
+
+/// Keep the record in memory, dropping the oldest one if there are too many.
+fn push_recent(recent: &mut VecDeque<AuditRecord>, record: AuditRecord) {
+    if recent.len() >= MAX_RECENT_RECORDS {
+        recent.pop_front();
+    }
+    recent.push_back(record);
+}
+
+/// The audit log.
+///
+/// If env var `AUDIT_LOG_PATH` hasn't been set, audit records are only
+/// written as logs and kept in memory.
+///
+/// # Panics
+///
+/// Panics if the file cannot be opened or its hash chain is broken.
+pub(crate) static AUDIT_LOG: LazyLock<Mutex<AuditLog>> = LazyLock::new(init_audit_log);
+
+fn init_audit_log() -> Mutex<AuditLog> {
+    let Ok(path) = std::env::var(AUDIT_LOG_PATH) else {
+        tracing::info!("Env var {AUDIT_LOG_PATH} hasn't been set. Audit log file is disabled.");
+        return Mutex::new(AuditLog::in_memory());
+    };
+    let audit_log = AuditLog::open(&path)
+        .unwrap_or_else(|e| panic!("Failed to open audit log {path:?}. Error: {e}."));
+    Mutex::new(audit_log)
+}
+
+/// Get up to `limit` of the latest audit records (newest first),
+/// only of the account if it's given.
+pub(crate) fn recent_records(limit: usize, account: Option<&str>) -> Vec<AuditRecord> {
+    let audit_log = AUDIT_LOG
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    audit_log
+        .recent
+        .iter()
+        .rev()
+        .filter(|record| account.is_none_or(|account| record.account == account))
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+/// Record an audited operation, given the result of the operation.
+pub(crate) fn record<T>(
+    action: AuditAction,
+    account: &str,
+    client: &ClientInfo,
+    result: &crate::Result<T>,
+) {
+    let reason = result.as_ref().err().map(ToString::to_string);
+    tracing::info!(
+        target: "audit",
+        ?action,
+        account,
+        source_ip = ?client.ip,
+        request_id = ?client.request_id,
+        reason,
+        "audit"
+    );
+    crate::webhook::notify(action, account, client, reason.is_none());
+    let mut audit_log = AUDIT_LOG
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    if let Err(e) = audit_log.append(action, account, client, reason) {
+        tracing::error!("Failed to write audit log. Error: {e}.");
+    }
+}
+
+/// Verify the hash chain of records read from `reader`, passing each record to `on_record`.
+///
+/// Returns the next sequence number and the hash of the last record.
+fn verify_chain(
+    reader: impl BufRead,
+    mut on_record: impl FnMut(AuditRecord),
+) -> crate::Result<(u64, String)> {
+    let mut next_seq = 0;
+    let mut last_hash = GENESIS_HASH.to_owned();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let invalid = |reason: &str| crate::Error::AuditLogInvalid {
+            line: index + 1,
+            reason: reason.to_owned(),
+        };
+        let record: AuditRecord =
+            serde_json::from_str(&line).map_err(|e| invalid(&e.to_string()))?;
+        if record.seq != next_seq {
+            return Err(invalid("unexpected sequence number"));
+        }
+        if record.prev_hash != last_hash {
+            return Err(invalid("prev_hash doesn't match the previous record"));
+        }
+        if record.hash != record.compute_hash() {
+            return Err(invalid("hash doesn't match the record"));
+        }
+        next_seq += 1;
+        last_hash.clone_from(&record.hash);
+        on_record(record);
+    }
+    Ok((next_seq, last_hash))
+}
+
+/// Verify the hash chain of the audit log file at `path`.
+///
+/// Returns the number of records in the file.
+///
+/// # Errors
+///
+/// Returns Err if the file cannot be read, or if any record has been
+/// modified, removed, reordered or inserted.
+pub fn verify_audit_log(path: impl AsRef<Path>) -> crate::Result<u64> {
+    let file = std::fs::File::open(path)?;
+    let (count, _) = verify_chain(std::io::BufReader::new(file), drop)?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> std::path::PathBuf {
+        let name = format!("totp-audit-{}.jsonl", rand::random::<u64>());
+        std::env::temp_dir().join(name)
+    }
+
+    fn append_records(path: &Path, count: usize) {
+        let mut audit_log = AuditLog::open(path).unwrap();
+        let client = ClientInfo {
+            ip: Some(IpAddr::from([127, 0, 0, 1])),
+            user_agent: Some("test".to_owned()),
+            request_id: None,
+            account_limit: None,
+        };
+        for i in 0..count {
+            let reason = (i % 2 == 1).then(|| "invalid TOTP".to_owned());
+            audit_log
+                .append(AuditAction::Verify, "default", &client, reason)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_audit_log_chain() {
+        let path = temp_path();
+        append_records(&path, 3);
+        // Reopening continues the existing chain.
+        append_records(&path, 2);
+        assert_eq!(verify_audit_log(&path).unwrap(), 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_log_recent() {
+        let path = temp_path();
+        append_records(&path, 3);
+        // Records are reloaded from the file.
+        let audit_log = AuditLog::open(&path).unwrap();
+        let seqs: Vec<u64> = audit_log.recent.iter().map(|record| record.seq).collect();
+        assert_eq!(seqs, [0, 1, 2]);
+        let mut audit_log = AuditLog::in_memory();
+        let client = ClientInfo {
+            ip: None,
+            user_agent: None,
+            request_id: None,
+            account_limit: None,
+        };
+        for _ in 0..=MAX_RECENT_RECORDS {
+            audit_log
+                .append(AuditAction::Verify, "default", &client, None)
+                .unwrap();
+        }
+        assert_eq!(audit_log.recent.len(), MAX_RECENT_RECORDS);
+        assert_eq!(audit_log.recent[0].seq, 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_log_tampered() {
+        let path = temp_path();
+        append_records(&path, 3);
+        let content = std::fs::read_to_string(&path).unwrap();
+        let tampered = content.replacen("\"failure\"", "\"success\"", 1);
+        std::fs::write(&path, tampered).unwrap();
+        let err = verify_audit_log(&path).unwrap_err();
+        assert!(matches!(err, crate::Error::AuditLogInvalid { line: 2, .. }));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_audit_log_removed_record() {
+        let path = temp_path();
+        append_records(&path, 3);
+        let content = std::fs::read_to_string(&path).unwrap();
+        let removed: Vec<&str> = content.lines().skip(1).collect();
+        std::fs::write(&path, removed.join("\n")).unwrap();
+        let err = verify_audit_log(&path).unwrap_err();
+        assert!(matches!(err, crate::Error::AuditLogInvalid { line: 1, .. }));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+use axum::body::Body;
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// Env var used to set API keys, which are stored as SHA-256 hashes.
+///
+/// Keys are comma-separated, each of which is defined as `id:sha256-hex[:accounts]`.
+const API_KEYS: &str = "API_KEYS";
+/// Env var used to set secrets of HMAC-signed requests.
+///
+/// Keys are comma-separated, each of which is defined as `id:secret[:accounts]`.
+const HMAC_KEYS: &str = "HMAC_KEYS";
+/// Env var used to set how far (in seconds) the timestamp of a signed request
+/// may be from the current time.
+const HMAC_REPLAY_WINDOW: &str = "HMAC_REPLAY_WINDOW";
+
+/// Header of the API key.
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+/// Header of the id of the HMAC key which signs the request.
+pub(crate) const KEY_ID_HEADER: &str = "x-totp-key-id";
+/// Header of the Unix timestamp (in seconds) when the request is signed.
+pub(crate) const TIMESTAMP_HEADER: &str = "x-totp-timestamp";
+/// Header of the signature, formatted as `sha256=<hex>`.
+pub(crate) const SIGNATURE_HEADER: &str = "x-totp-signature";
+
+/// Max size of the body of signed requests.
+const MAX_SIGNED_BODY: usize = 64 * 1024;
+
+/// Accounts which a key is allowed to access.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AccountScope {
+    /// Every account (`*`, or when accounts are omitted).
+    All,
+    /// Only the listed accounts (separated by `|`).
+    Only(Vec<String>),
+}
+
+impl AccountScope {
+    fn parse(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            None | Some("*") => Self::All,
+            Some(accounts) => Self::Only(
+                accounts
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect(),
+            ),
+        }
+    }
+
+    fn contains(&self, account: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(accounts) => accounts.iter().any(|a| a == account),
+        }
+    }
+}
+
+/// A key which callers authenticate with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CallerKey {
+    id: String,
+    /// SHA-256 of the API key, or the secret of the HMAC key.
+    material: Vec<u8>,
+    scope: AccountScope,
+}
+
+/// Settings of request authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AuthConfig {
+    api_keys: Vec<CallerKey>,
+    hmac_keys: Vec<CallerKey>,
+    replay_window: u64,
+}
+
+impl AuthConfig {
+    /// Authentication is only required if any key has been configured.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.api_keys.is_empty() || !self.hmac_keys.is_empty()
+    }
+
+    /// Find the caller of the given API key.
+    fn api_key_caller(&self, key: &str) -> crate::Result<Caller> {
+        use sha2::{Digest, Sha256};
+        let hash = Sha256::digest(key.as_bytes());
+        self.api_keys
+            .iter()
+            .find(|k| k.material.as_slice() == hash.as_slice())
+            .map(Caller::from)
+            .ok_or(crate::Error::Unauthenticated("invalid API key"))
+    }
+
+    /// Verify the signature of a request, given its body and the current Unix time.
+    fn signature_caller(&self, parts: &Parts, body: &[u8], now: u64) -> crate::Result<Caller> {
+        use hmac::{Hmac, KeyInit, Mac};
+        type E = crate::Error;
+
+        let header = |name: &str| parts.headers.get(name).and_then(|v| v.to_str().ok());
+        let key_id = header(KEY_ID_HEADER).ok_or(E::Unauthenticated("missing key id"))?;
+        let key = self
+            .hmac_keys
+            .iter()
+            .find(|k| k.id == key_id)
+            .ok_or(E::Unauthenticated("unknown key id"))?;
+        let timestamp: u64 = header(TIMESTAMP_HEADER)
+            .and_then(|v| v.parse().ok())
+            .ok_or(E::Unauthenticated("invalid timestamp"))?;
+        if now.abs_diff(timestamp) > self.replay_window {
+            return Err(E::Unauthenticated("timestamp is outside the replay window"));
+        }
+        let signature = header(SIGNATURE_HEADER)
+            .and_then(|v| v.strip_prefix("sha256="))
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or(E::Unauthenticated("invalid signature"))?;
+
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(&key.material)
+            .unwrap_or_else(|e| panic!("HMAC accepts keys of any size. Error: {e}."));
+        mac.update(&signed_prefix(
+            timestamp,
+            parts.method.as_str(),
+            &path_and_query(parts),
+        ));
+        mac.update(body);
+        mac.verify_slice(&signature)
+            .map_err(|_| E::Unauthenticated("invalid signature"))?;
+
+        // The same signature can't be used twice within the replay window.
+        let mut seen = SEEN_SIGNATURES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        seen.retain(|_, &mut ts| now.abs_diff(ts) <= self.replay_window);
+        if seen.insert(signature, timestamp).is_some() {
+            return Err(E::Unauthenticated("replayed request"));
+        }
+        Ok(Caller::from(key))
+    }
+}
+
+/// Signatures which have been accepted within the replay window.
+static SEEN_SIGNATURES: LazyLock<Mutex<HashMap<Vec<u8>, u64>>> = LazyLock::new(Default::default);
+
+fn path_and_query(parts: &Parts) -> String {
+    parts
+        .uri
+        .path_and_query()
+        .map_or_else(|| parts.uri.path().to_owned(), ToString::to_string)
+}
+
+/// The part of the signed message which precedes the body.
+fn signed_prefix(timestamp: u64, method: &str, path_and_query: &str) -> Vec<u8> {
+    format!("{timestamp}.{method}.{path_and_query}.").into_bytes()
+}
+
+/// Settings of request authentication.
+///
+/// # Panics
+///
+/// Panics when env var `API_KEYS`, `HMAC_KEYS` or `HMAC_REPLAY_WINDOW` cannot be parsed,
+/// or when key ids are duplicated.
+pub(crate) static AUTH_CONFIG: LazyLock<AuthConfig> = LazyLock::new(init_auth_config);
+
+fn init_auth_config() -> AuthConfig {
+    let parse_keys = |name: &str, parse_material: fn(&str) -> Option<Vec<u8>>| {
+        let Ok(value) = std::env::var(name) else {
+            return Vec::new();
+        };
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|item| {
+                let mut fields = item.splitn(3, ':');
+                let (Some(id), Some(material)) = (fields.next(), fields.next()) else {
+                    panic!("{name}: {item:?} should be like id:key[:accounts].");
+                };
+                let material = parse_material(material)
+                    .unwrap_or_else(|| panic!("{name}: the key of {id:?} is invalid."));
+                CallerKey {
+                    id: id.to_owned(),
+                    material,
+                    scope: AccountScope::parse(fields.next()),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let api_keys = parse_keys(API_KEYS, |hash| {
+        hex::decode(hash).ok().filter(|bytes| bytes.len() == 32)
+    });
+    let hmac_keys = parse_keys(HMAC_KEYS, |secret| {
+        (!secret.is_empty()).then(|| secret.as_bytes().to_vec())
+    });
+    for (i, key) in api_keys.iter().chain(&hmac_keys).enumerate() {
+        let is_duplicated = api_keys
+            .iter()
+            .chain(&hmac_keys)
+            .skip(i + 1)
+            .any(|k| k.id == key.id);
+        assert!(
+            !is_duplicated,
+            "{API_KEYS} and {HMAC_KEYS}: key id {:?} is duplicated.",
+            key.id
+        );
+    }
+    let replay_window = std::env::var(HMAC_REPLAY_WINDOW).map_or(300, |value| {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("{HMAC_REPLAY_WINDOW} must be an unsigned integer!"))
+    });
+    let config = AuthConfig {
+        api_keys,
+        hmac_keys,
+        replay_window,
+    };
+    if !config.is_enabled() {
+        tracing::warn!(
+            "Neither {API_KEYS} nor {HMAC_KEYS} has been set. Request authentication is disabled."
+        );
+    }
+    config
+}
+
+/// The authenticated caller of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Caller {
+    /// Id of the key, or `None` if authentication is disabled.
+    pub(crate) key_id: Option<String>,
+    scope: AccountScope,
+}
+
+impl From<&CallerKey> for Caller {
+    fn from(key: &CallerKey) -> Self {
+        Caller {
+            key_id: Some(key.id.clone()),
+            scope: key.scope.clone(),
+        }
+    }
+}
+
+impl Caller {
+    /// The caller when authentication is disabled, who can access every account.
+    pub(crate) const fn anonymous() -> Self {
+        Caller {
+            key_id: None,
+            scope: AccountScope::All,
+        }
+    }
+
+    /// The caller identified by a TLS client certificate, which can access every account,
+    /// if any role is bound to its common name (see `ROLE_BINDINGS`).
+    fn from_client_cert(cert: &crate::tls::ClientCert) -> Option<Self> {
+        let config = crate::rbac::RBAC_CONFIG.as_ref()?;
+        config.binds_cert(&cert.common_name).then(|| Caller {
+            key_id: Some(format!(
+                "{}{}",
+                crate::rbac::CERT_SUBJECT_PREFIX,
+                cert.common_name
+            )),
+            scope: AccountScope::All,
+        })
+    }
+
+    /// Check if the caller is allowed to access the given account.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Forbidden`] if the account is out of the scope of the key.
+    pub(crate) fn authorize(&self, account: &str) -> crate::Result<()> {
+        if self.scope.contains(account) {
+            Ok(())
+        } else {
+            Err(crate::Error::Forbidden {
+                account: account.to_owned(),
+            })
+        }
+    }
+
+    /// Check if the caller is allowed to access every account, e.g. for bulk operations.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Forbidden`] if the key is limited to some accounts.
+    pub(crate) fn authorize_all(&self) -> crate::Result<()> {
+        if self.scope == AccountScope::All {
+            Ok(())
+        } else {
+            Err(crate::Error::Forbidden {
+                account: "*".to_owned(),
+            })
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = crate::Error;
+
+    /// Get the caller set by [`authenticate`].
+    ///
+    /// Requests which haven't gone through [`authenticate`] are rejected,
+    /// unless authentication is disabled.
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(caller) = parts.extensions.get::<Caller>() {
+            return Ok(caller.clone());
+        }
+        if AUTH_CONFIG.is_enabled() {
+            return Err(crate::Error::Unauthenticated("missing credentials"));
+        }
+        Ok(Caller::anonymous())
+    }
+}
+
+/// Middleware which authenticates callers by either an API key
+/// (header `X-Api-Key`), an HMAC signature (headers `X-Totp-Key-Id`,
+/// `X-Totp-Timestamp` and `X-Totp-Signature`), or else a TLS client certificate
+/// whose common name is bound to roles (see `ROLE_BINDINGS`).
+///
+/// The [`Caller`] is inserted into request extensions.
+pub(crate) async fn authenticate(mut request: Request, next: Next) -> Response {
+    let has_credentials = [API_KEY_HEADER, SIGNATURE_HEADER]
+        .iter()
+        .any(|name| request.headers().contains_key(*name));
+    let cert_caller = request
+        .extensions()
+        .get::<crate::tls::ClientCert>()
+        .and_then(Caller::from_client_cert);
+    if let Some(caller) = cert_caller.filter(|_| !has_credentials) {
+        tracing::debug!(key_id = ?caller.key_id, "Caller has been authenticated by certificate.");
+        request.extensions_mut().insert(caller);
+        return next.run(request).await;
+    }
+    if !AUTH_CONFIG.is_enabled() {
+        return next.run(request).await;
+    }
+    match authenticate_request(&AUTH_CONFIG, request).await {
+        Ok((caller, mut request)) => {
+            tracing::debug!(key_id = ?caller.key_id, "Caller has been authenticated.");
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        }
+        Err(e) => {
+            tracing::warn!("Request authentication failed. Error: {e}.");
+            e.into_response()
+        }
+    }
+}
+
+/// Whether any caller can be authenticated, either by a key (`API_KEYS`, `HMAC_KEYS`)
+/// or by a TLS client certificate bound to roles (`ROLE_BINDINGS`).
+pub(crate) fn can_authenticate() -> bool {
+    AUTH_CONFIG.is_enabled() || crate::rbac::RBAC_CONFIG.is_some()
+}
+
+/// Middleware which rejects anonymous callers, i.e. requests which [`authenticate`]
+/// hasn't identified a [`Caller`] of, even if authentication is disabled.
+///
+/// It must be layered inside [`authenticate`].
+pub(crate) async fn require_caller(request: Request, next: Next) -> Response {
+    if request.extensions().get::<Caller>().is_none() {
+        tracing::warn!(
+            "Anonymous caller of {} has been rejected.",
+            request.uri().path()
+        );
+        return crate::Error::Unauthenticated("credentials are required").into_response();
+    }
+    next.run(request).await
+}
+
+async fn authenticate_request(
+    config: &AuthConfig,
+    request: Request,
+) -> crate::Result<(Caller, Request)> {
+    let headers = request.headers();
+    if let Some(key) = headers.get(API_KEY_HEADER) {
+        let key = key
+            .to_str()
+            .map_err(|_| crate::Error::Unauthenticated("invalid API key"))?;
+        return Ok((config.api_key_caller(key)?, request));
+    }
+    if !headers.contains_key(SIGNATURE_HEADER) {
+        return Err(crate::Error::Unauthenticated("missing credentials"));
+    }
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_SIGNED_BODY)
+        .await
+        .map_err(|_| crate::Error::Unauthenticated("body of signed request is too large"))?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let caller = config.signature_caller(&parts, &body, now)?;
+    Ok((caller, Request::from_parts(parts, Body::from(body))))
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    const NOW: u64 = 1_700_000_000;
+
+    /// Sign a request, returning the value of the `X-Totp-Signature` header.
+    ///
+    /// The signature is HMAC-SHA256 of `{timestamp}.{METHOD}.{path_and_query}.{body}`.
+    pub(crate) fn sign_request(
+        secret: &[u8],
+        timestamp: u64,
+        method: &str,
+        path_and_query: &str,
+        body: &[u8],
+    ) -> String {
+        let mut message = signed_prefix(timestamp, method, path_and_query);
+        message.extend_from_slice(body);
+        format!(
+            "sha256={}",
+            crate::webhook::hmac_sha256_hex(secret, &message)
+        )
+    }
+
+    fn test_config() -> AuthConfig {
+        use sha2::{Digest, Sha256};
+        AuthConfig {
+            api_keys: vec![CallerKey {
+                id: "game".to_owned(),
+                material: Sha256::digest(b"game-key").to_vec(),
+                scope: AccountScope::Only(vec!["alice".to_owned()]),
+            }],
+            hmac_keys: vec![CallerKey {
+                id: "ci".to_owned(),
+                material: b"ci-secret".to_vec(),
+                scope: AccountScope::All,
+            }],
+            replay_window: 300,
+        }
+    }
+
+    fn signed_parts(key_id: &str, timestamp: u64, signature: &str) -> Parts {
+        let (parts, ()) = axum::http::Request::post("/?a=1")
+            .header(KEY_ID_HEADER, key_id)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+    }
+
+    #[test]
+    fn test_api_key_caller() {
+        let config = test_config();
+        let caller = config.api_key_caller("game-key").unwrap();
+        assert_eq!(caller.key_id.as_deref(), Some("game"));
+        assert!(caller.authorize("alice").is_ok());
+        assert!(matches!(
+            caller.authorize("bob"),
+            Err(crate::Error::Forbidden { .. })
+        ));
+        assert!(config.api_key_caller("wrong-key").is_err());
+    }
+
+    #[test]
+    fn test_signature_caller() {
+        let config = test_config();
+        let body = br#"{"token":"123456"}"#;
+        let signature = sign_request(b"ci-secret", NOW, "POST", "/?a=1", body);
+        let parts = signed_parts("ci", NOW, &signature);
+        let caller = config.signature_caller(&parts, body, NOW + 10).unwrap();
+        assert_eq!(caller.key_id.as_deref(), Some("ci"));
+        assert!(caller.authorize("anyone").is_ok());
+        // The same request can't be replayed.
+        let err = config.signature_caller(&parts, body, NOW + 20).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::Unauthenticated("replayed request")
+        ));
+    }
+
+    #[rstest]
+    #[case::wrong_body("ci", NOW, "ci-secret", br#"{"token":"654321"}"#, NOW)]
+    #[case::wrong_secret("ci", NOW, "wrong-secret", br#"{"token":"123456"}"#, NOW)]
+    #[case::unknown_key("cd", NOW, "ci-secret", br#"{"token":"123456"}"#, NOW)]
+    #[case::stale("ci", NOW, "ci-secret", br#"{"token":"123456"}"#, NOW + 301)]
+    #[case::future("ci", NOW + 301, "ci-secret", br#"{"token":"123456"}"#, NOW)]
+    fn test_signature_caller_err(
+        #[case] key_id: &str,
+        #[case] timestamp: u64,
+        #[case] secret: &str,
+        #[case] body: &[u8],
+        #[case] now: u64,
+    ) {
+        let config = test_config();
+        let signature = sign_request(
+            secret.as_bytes(),
+            timestamp,
+            "POST",
+            "/?a=1",
+            b"{\"token\":\"123456\"}",
+        );
+        let parts = signed_parts(key_id, timestamp, &signature);
+        let err = config.signature_caller(&parts, body, now).unwrap_err();
+        assert!(matches!(err, crate::Error::Unauthenticated(_)));
+    }
+
+    #[test]
+    fn test_auth_config_default() {
+        assert!(std::env::var(API_KEYS).is_err());
+        assert!(!AUTH_CONFIG.is_enabled());
+    }
+
+    #[test]
+    fn test_auth_config_var() {
+        let hash = "ed0dbc8fd9bc4d20ab9b4fea1c3e8c3c8b3fc1a4e2e3bd2e0ed0ba1f5bd49bc0";
+        unsafe {
+            std::env::set_var(API_KEYS, format!("game:{hash}:alice|bob, bot:{hash}:*"));
+            std::env::set_var(HMAC_KEYS, "ci:ci-secret");
+            std::env::set_var(HMAC_REPLAY_WINDOW, "60");
+        }
+        assert!(AUTH_CONFIG.is_enabled());
+        assert_eq!(AUTH_CONFIG.replay_window, 60);
+        let scopes: Vec<_> = AUTH_CONFIG.api_keys.iter().map(|k| &k.scope).collect();
+        let alice_bob = AccountScope::Only(vec!["alice".to_owned(), "bob".to_owned()]);
+        assert_eq!(scopes, [&alice_bob, &AccountScope::All]);
+        assert_eq!(AUTH_CONFIG.hmac_keys[0].material, b"ci-secret");
+        assert_eq!(AUTH_CONFIG.hmac_keys[0].scope, AccountScope::All);
+    }
+
+    #[rstest]
+    #[case(API_KEYS, "game")]
+    #[case(API_KEYS, "game:not-hex")]
+    #[case(API_KEYS, "game:abcd")]
+    #[case(HMAC_KEYS, "ci:")]
+    #[case(HMAC_KEYS, "ci:a,ci:b")]
+    #[should_panic(expected = "_KEYS")]
+    fn test_auth_config_var_panic(#[case] name: &str, #[case] value: &str) {
+        unsafe { std::env::set_var(name, value) }
+        let _ = &*AUTH_CONFIG;
+    }
+
+    #[test]
+    #[should_panic(expected = "HMAC_REPLAY_WINDOW")]
+    fn test_replay_window_var_panic() {
+        unsafe { std::env::set_var(HMAC_REPLAY_WINDOW, "-1") }
+        let _ = &*AUTH_CONFIG;
+    }
+}
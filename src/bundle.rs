@@ -0,0 +1,363 @@
+use crate::group::TotpParams;
+use crate::otpauth::OtpauthProfile;
+use crate::store::{ACCOUNTS, Account};
+use age::secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+
+/// Version of the bundle format, which is written into every bundle.
+const BUNDLE_VERSION: u32 = 1;
+/// First line of CSV bundles, which carries the version.
+const CSV_PREAMBLE: &str = "# totp-server accounts, version 1";
+/// Prefix of ASCII-armored age files.
+const ARMOR_PREFIX: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+/// Prefix of binary age files.
+const BINARY_PREFIX: &[u8] = b"age-encryption.org/";
+/// log2 of the scrypt work factor of encrypted bundles.
+///
+/// It's fixed rather than calibrated (as age does) so that bundles can be
+/// decrypted by the server within its request timeout.
+pub(crate) const WORK_FACTOR: u8 = 16;
+
+/// Formats of account bundles.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleFormat {
+    /// `{ "version": 1, "accounts": [...] }`.
+    #[default]
+    Json,
+    /// A header row and one row per account, after a line with the version.
+    Csv,
+}
+
+impl BundleFormat {
+    /// The media type of unencrypted bundles in this format.
+    pub(crate) const fn content_type(self) -> &'static str {
+        match self {
+            BundleFormat::Json => "application/json",
+            BundleFormat::Csv => "text/csv",
+        }
+    }
+
+    /// The file extension of unencrypted bundles in this format.
+    pub(crate) const fn extension(self) -> &'static str {
+        match self {
+            BundleFormat::Json => "json",
+            BundleFormat::Csv => "csv",
+        }
+    }
+}
+
+/// What to do with imported accounts which already exist with different secrets or profiles.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing accounts.
+    Skip,
+    /// Replace the existing accounts.
+    Overwrite,
+    /// Import nothing.
+    #[default]
+    Fail,
+}
+
+/// An account in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct BundleAccount {
+    id: String,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    issuer: Option<String>,
+    algorithm: String,
+    digits: u32,
+    period: u64,
+    /// The base32-encoded secret.
+    secret: String,
+    #[serde(default)]
+    created_at: u64,
+}
+
+impl From<&Account> for BundleAccount {
+    fn from(account: &Account) -> Self {
+        BundleAccount {
+            id: account.name.clone(),
+            label: account.profile.label.clone(),
+            issuer: account.profile.issuer.clone(),
+            algorithm: account.totp.algorithm.to_string(),
+            digits: account.totp.digits.into(),
+            period: account.totp.period,
+            secret: account.secret_base32(),
+            created_at: account.created_at,
+        }
+    }
+}
+
+impl TryFrom<BundleAccount> for Account {
+    type Error = crate::Error;
+
+    fn try_from(account: BundleAccount) -> crate::Result<Self> {
+        let invalid = |reason: &str| {
+            crate::Error::InvalidInput(format!("bundle: account {:?} {reason}", account.id))
+        };
+        let algorithm = account
+            .algorithm
+            .parse()
+            .map_err(|_| invalid("has an algorithm other than SHA1, SHA256 or SHA512"))?;
+        let totp = TotpParams {
+            algorithm,
+            digits: u8::try_from(account.digits).unwrap_or(u8::MAX),
+            period: account.period,
+        };
+        totp.validate()
+            .map_err(|_| invalid("has digits (6 to 8) or a period (10 to 300) out of range"))?;
+        let secret = totp_rs::Secret::Encoded(account.secret.clone())
+            .to_bytes()
+            .map_err(|_| invalid("has an invalid base32 secret"))?;
+        if secret.len() < 16 {
+            return Err(invalid("has a secret shorter than 128 bits"));
+        }
+        let profile = OtpauthProfile {
+            issuer: account.issuer.filter(|issuer| !issuer.is_empty()),
+            label: account.label.filter(|label| !label.is_empty()),
+        };
+        Ok(Account {
+            totp,
+            ..Account::new(&account.id, secret, account.created_at, profile)
+        })
+    }
+}
+
+/// Content of JSON bundles.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    version: u32,
+    accounts: Vec<BundleAccount>,
+}
+
+/// Changes made (or to be made, in dry runs) by an import.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ImportDiff {
+    /// Whether nothing has been changed.
+    pub dry_run: bool,
+    /// Accounts which don't exist yet.
+    pub added: Vec<String>,
+    /// Existing accounts which are overwritten.
+    pub updated: Vec<String>,
+    /// Existing accounts which are the same as the imported ones.
+    pub unchanged: Vec<String>,
+    /// Existing accounts which are kept as they are, by [`ConflictPolicy::Skip`].
+    pub skipped: Vec<String>,
+}
+
+/// Encode accounts into a bundle, which is encrypted if `passphrase` is given.
+pub(crate) fn encode(
+    accounts: &[Account],
+    format: BundleFormat,
+    passphrase: Option<&str>,
+) -> crate::Result<Vec<u8>> {
+    let accounts: Vec<BundleAccount> = accounts.iter().map(BundleAccount::from).collect();
+    let plaintext = match format {
+        BundleFormat::Json => {
+            let file = BundleFile {
+                version: BUNDLE_VERSION,
+                accounts,
+            };
+            serde_json::to_vec_pretty(&file).map_err(std::io::Error::from)?
+        }
+        BundleFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(format!("{CSV_PREAMBLE}\n").into_bytes());
+            for account in accounts {
+                writer.serialize(account).map_err(std::io::Error::other)?;
+            }
+            writer
+                .into_inner()
+                .map_err(csv::IntoInnerError::into_error)?
+        }
+    };
+    let Some(passphrase) = passphrase else {
+        return Ok(plaintext);
+    };
+    let mut recipient = age::scrypt::Recipient::new(SecretString::from(passphrase.to_owned()));
+    recipient.set_work_factor(WORK_FACTOR);
+    let armored = age::encrypt_and_armor(&recipient, &plaintext)
+        .map_err(|e| crate::Error::InvalidInput(format!("bundle: {e}")))?;
+    Ok(armored.into_bytes())
+}
+
+/// Check if the bundle has been encrypted by age.
+pub(crate) fn is_encrypted(bundle: &[u8]) -> bool {
+    let bundle = bundle.trim_ascii_start();
+    bundle.starts_with(ARMOR_PREFIX) || bundle.starts_with(BINARY_PREFIX)
+}
+
+/// Decode accounts from a bundle in either format, which is decrypted if it's encrypted.
+///
+/// Encrypted bundles whose scrypt work factor exceeds `max_work_factor` are
+/// rejected, as well as those which take age more than about 16 seconds.
+pub(crate) fn decode(
+    bundle: &[u8],
+    passphrase: Option<&str>,
+    max_work_factor: Option<u8>,
+) -> crate::Result<Vec<Account>> {
+    type E = crate::Error;
+    let plaintext = if is_encrypted(bundle) {
+        let passphrase = passphrase.ok_or_else(|| {
+            E::InvalidInput("bundle: a passphrase is required to decrypt it".to_owned())
+        })?;
+        let mut identity = age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+        if let Some(max_work_factor) = max_work_factor {
+            identity.set_max_work_factor(max_work_factor);
+        }
+        age::decrypt(&identity, bundle.trim_ascii()).map_err(|e| match e {
+            // Its `Display` underflows when the cap is below age's calibrated target.
+            age::DecryptError::ExcessiveWork { required, .. } => E::InvalidInput(format!(
+                "bundle: scrypt work factor 2^{required} is too costly to decrypt"
+            )),
+            e => E::InvalidInput(format!("bundle cannot be decrypted: {e}")),
+        })?
+    } else {
+        bundle.to_vec()
+    };
+    let text = std::str::from_utf8(&plaintext)
+        .map_err(|_| E::InvalidInput("bundle: invalid UTF-8".to_owned()))?
+        .trim_start();
+    let accounts = if text.starts_with('{') {
+        let file: BundleFile = serde_json::from_str(text)
+            .map_err(|e| E::InvalidInput(format!("bundle: invalid JSON: {e}")))?;
+        if file.version != BUNDLE_VERSION {
+            return Err(E::InvalidInput(format!(
+                "bundle: unsupported version {}",
+                file.version
+            )));
+        }
+        file.accounts
+    } else {
+        let rows = text.strip_prefix(CSV_PREAMBLE).ok_or_else(|| {
+            E::InvalidInput(format!("bundle: CSV should start with {CSV_PREAMBLE:?}"))
+        })?;
+        csv::Reader::from_reader(rows.trim_start().as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| E::InvalidInput(format!("bundle: invalid CSV: {e}")))?
+    };
+    accounts.into_iter().map(Account::try_from).collect()
+}
+
+/// Export every enrolled account (but the default one) as a bundle,
+/// which is encrypted with `passphrase` (age/scrypt) if it's given.
+///
+/// # Errors
+///
+/// Returns Err if the bundle cannot be encoded or encrypted.
+pub fn export_bundle(format: BundleFormat, passphrase: Option<&str>) -> crate::Result<Vec<u8>> {
+    encode(&ACCOUNTS.enrolled(), format, passphrase)
+}
+
+/// Import accounts from a bundle in either format into the accounts file,
+/// resolving conflicts with existing accounts by `policy`.
+///
+/// Encrypted bundles are detected and decrypted with `passphrase`.
+/// Nothing is changed in dry runs, whose diff tells what would be changed.
+/// A running server doesn't see the change until it's restarted.
+///
+/// # Errors
+///
+/// Returns Err if the bundle cannot be decrypted or decoded, any account is invalid
+/// or conflicts under [`ConflictPolicy::Fail`] (in which case nothing is imported),
+/// or if the accounts file cannot be written.
+pub fn import_bundle(
+    bundle: &[u8],
+    passphrase: Option<&str>,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> crate::Result<ImportDiff> {
+    let accounts = decode(bundle, passphrase, None)?;
+    ACCOUNTS.restore(accounts, policy, dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn accounts() -> Vec<Account> {
+        vec![
+            Account::new(
+                "alice",
+                b"12345678901234567890".to_vec(),
+                1_700_000_000,
+                OtpauthProfile {
+                    issuer: Some("Game Studio".to_owned()),
+                    label: Some("Alice, \"QA\"".to_owned()),
+                },
+            ),
+            Account {
+                totp: TotpParams {
+                    algorithm: crate::group::TotpAlgorithm::Sha256,
+                    digits: 8,
+                    period: 60,
+                },
+                ..Account::new(
+                    "bob",
+                    vec![0xff; 32],
+                    1_700_000_001,
+                    OtpauthProfile::default(),
+                )
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case(BundleFormat::Json, None)]
+    #[case(BundleFormat::Csv, None)]
+    #[case(BundleFormat::Json, Some("correct horse battery staple"))]
+    #[case(BundleFormat::Csv, Some("correct horse battery staple"))]
+    fn test_round_trip(#[case] format: BundleFormat, #[case] passphrase: Option<&str>) {
+        let bundle = encode(&accounts(), format, passphrase).unwrap();
+        assert_eq!(is_encrypted(&bundle), passphrase.is_some());
+        let decoded = decode(&bundle, passphrase, Some(WORK_FACTOR)).unwrap();
+        assert_eq!(decoded, accounts());
+        if passphrase.is_some() {
+            assert!(decode(&bundle, None, None).is_err());
+            assert!(decode(&bundle, Some("wrong"), None).is_err());
+            // Bundles which are too costly to decrypt are rejected.
+            assert!(decode(&bundle, passphrase, Some(WORK_FACTOR - 1)).is_err());
+            let text = String::from_utf8(bundle).unwrap();
+            assert!(!text.contains("alice"));
+        }
+    }
+
+    #[test]
+    fn test_csv_layout() {
+        let bundle = encode(&accounts(), BundleFormat::Csv, None).unwrap();
+        let text = String::from_utf8(bundle).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], CSV_PREAMBLE);
+        assert_eq!(
+            lines[1],
+            "id,label,issuer,algorithm,digits,period,secret,created_at"
+        );
+        assert_eq!(
+            lines[2],
+            r#"alice,"Alice, ""QA""",Game Studio,SHA1,6,30,GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ,1700000000"#
+        );
+    }
+
+    #[rstest]
+    #[case(r#"{"version":2,"accounts":[]}"#)]
+    #[case(r#"{"version":1,"accounts":[{"id":"a","algorithm":"MD5","digits":6,"period":30,"secret":"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"}]}"#)]
+    #[case(r#"{"version":1,"accounts":[{"id":"a","algorithm":"SHA1","digits":9,"period":30,"secret":"GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"}]}"#)]
+    #[case(r#"{"version":1,"accounts":[{"id":"a","algorithm":"SHA1","digits":6,"period":30,"secret":"GEZDGNBV"}]}"#)]
+    #[case("id,label,issuer,algorithm,digits,period,secret\n")]
+    #[case("not a bundle")]
+    fn test_decode_err(#[case] bundle: &str) {
+        assert!(matches!(
+            decode(bundle.as_bytes(), None, None),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+}
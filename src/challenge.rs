@@ -0,0 +1,388 @@
+use crate::extract::Input;
+use crate::unlock::UNLOCK_CONFIG;
+use crate::utils::now_secs;
+use axum::Json;
+use axum::extract::Path;
+use axum::extract::rejection::JsonRejection;
+use axum::http::{HeaderMap, StatusCode};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use utoipa::ToSchema;
+
+/// Env var used to set how long (in seconds) challenges and their assertions are valid.
+const CHALLENGE_TTL: &str = "CHALLENGE_TTL";
+/// Max length (in bytes) of nonces.
+const MAX_NONCE_LEN: usize = 128;
+
+/// A challenge, which is answered by a code of the account before it expires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Challenge {
+    /// Random id, which the code is sent to.
+    #[schema(example = "Zm9vYmFyYmF6cXV4cXV1eA")]
+    pub(crate) id: String,
+    /// The account whose code answers the challenge.
+    #[schema(example = "alice")]
+    pub(crate) account: String,
+    /// The nonce of the relying party, which the assertion carries.
+    #[schema(example = "txn-8d1f2c")]
+    pub(crate) nonce: String,
+    /// What the code is verified for, e.g. shown to the user.
+    #[schema(example = "transfer 500 gems")]
+    pub(crate) purpose: String,
+    /// When the challenge expires (seconds since the Unix epoch).
+    pub(crate) expires_at: u64,
+}
+
+/// Request body of challenges.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ChallengeRequest {
+    /// The account whose code answers the challenge.
+    ///
+    /// It falls back to header `X-Totp-Account`, and then to the default account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "alice")]
+    account: Option<String>,
+    /// The nonce of the relying party (1 to 128 printable ASCII characters),
+    /// e.g. the id of its transaction.
+    #[schema(example = "txn-8d1f2c")]
+    nonce: String,
+    /// What the code is verified for (1 to 256 bytes), e.g. shown to the user.
+    #[schema(example = "transfer 500 gems")]
+    purpose: String,
+}
+
+/// Prefix of the message which assertions sign, so that their signatures cannot be
+/// passed off as those of unlock tokens, which are signed by the same key.
+pub(crate) const ASSERTION_DOMAIN: &[u8] = b"totp-server/assertion/v1\0";
+
+/// Claims of an assertion, which relying parties check against their nonce.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AssertionClaims {
+    /// The account whose code has been verified.
+    #[schema(example = "alice")]
+    pub(crate) sub: String,
+    /// The nonce of the challenge.
+    #[schema(example = "txn-8d1f2c")]
+    pub(crate) nonce: String,
+    /// The purpose of the challenge.
+    #[schema(example = "transfer 500 gems")]
+    pub(crate) purpose: String,
+    /// Id of the challenge.
+    #[schema(example = "Zm9vYmFyYmF6cXV4cXV1eA")]
+    pub(crate) challenge: String,
+    /// When the TOTP code was verified (seconds since the Unix epoch).
+    pub(crate) auth_time: u64,
+    /// When the assertion expires (seconds since the Unix epoch).
+    pub(crate) exp: u64,
+}
+
+/// A signed assertion and its claims.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Assertion {
+    /// The assertion, formatted as `<claims>.<signature>`, both of which are base64url-encoded.
+    ///
+    /// The signature is Ed25519 of `totp-server/assertion/v1`, a NUL byte and `<claims>`
+    /// (as encoded), which is verified with the public key of unlock tokens
+    /// (`GET /v1/unlocks/key`).
+    assertion: String,
+    /// Claims of the assertion.
+    claims: AssertionClaims,
+}
+
+/// Check if the nonce is 1 to 128 printable ASCII characters.
+fn validate_nonce(nonce: &str) -> crate::Result<()> {
+    let valid =
+        (1..=MAX_NONCE_LEN).contains(&nonce.len()) && nonce.bytes().all(|b| b.is_ascii_graphic());
+    if valid {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidInput(format!(
+            "nonce must be 1 to {MAX_NONCE_LEN} printable ASCII characters"
+        )))
+    }
+}
+
+/// Sign the claims, returning `<claims>.<signature>` (see [`Assertion`]).
+fn sign_assertion(claims: &AssertionClaims) -> String {
+    let payload = serde_json::to_vec(claims).unwrap_or_else(|e| {
+        panic!("Failed to serialize assertion claims. Error: {e}.");
+    });
+    let payload = URL_SAFE_NO_PAD.encode(payload);
+    let message = [ASSERTION_DOMAIN, payload.as_bytes()].concat();
+    let signature = URL_SAFE_NO_PAD.encode(UNLOCK_CONFIG.sign(&message));
+    format!("{payload}.{signature}")
+}
+
+/// Pending challenges, each of which is answered once.
+///
+/// Codes are accepted once by [`crate::totp::verify`], so a code which has
+/// answered a challenge cannot answer another one, nor can any older code of the account.
+#[derive(Debug, Default)]
+pub(crate) struct ChallengeStore {
+    challenges: Mutex<HashMap<String, Challenge>>,
+}
+
+impl ChallengeStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Challenge>> {
+        self.challenges
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Keep the challenge, forgetting expired ones.
+    pub(crate) fn insert(&self, challenge: Challenge, now: u64) {
+        let mut challenges = self.lock();
+        challenges.retain(|_, challenge| challenge.expires_at > now);
+        challenges.insert(challenge.id.clone(), challenge);
+    }
+
+    /// Get the challenge by id.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ChallengeNotFound`] if the challenge doesn't exist,
+    /// has expired or has been answered.
+    pub(crate) fn get(&self, id: &str, now: u64) -> crate::Result<Challenge> {
+        self.lock()
+            .get(id)
+            .filter(|challenge| challenge.expires_at > now)
+            .cloned()
+            .ok_or_else(|| crate::Error::ChallengeNotFound { id: id.to_owned() })
+    }
+
+    /// Answer the challenge once a code of its account has been verified.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ChallengeNotFound`] (see [`Self::get`]).
+    pub(crate) fn answer(&self, id: &str, now: u64) -> crate::Result<Challenge> {
+        let mut challenges = self.lock();
+        challenges
+            .remove(id)
+            .filter(|challenge| challenge.expires_at > now)
+            .ok_or_else(|| crate::Error::ChallengeNotFound { id: id.to_owned() })
+    }
+}
+
+/// Pending challenges, which are kept in memory only.
+pub(crate) static CHALLENGES: LazyLock<ChallengeStore> = LazyLock::new(ChallengeStore::default);
+
+/// Create a challenge bound to the nonce of the relying party, which is answered
+/// by a code of the account before it expires (see `CHALLENGE_TTL`).
+#[utoipa::path(
+    post,
+    path = "/v1/challenges",
+    tag = "challenges",
+    request_body(description = "The account, the nonce and the purpose.", content(
+        (ChallengeRequest = "application/json"),
+    )),
+    responses(
+        (status = 201, description = "The challenge has been created.", body = Challenge),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+    )
+)]
+#[tracing::instrument(skip(headers))]
+pub(crate) async fn create_challenge(
+    caller: crate::Caller,
+    headers: HeaderMap,
+    body: Result<Json<ChallengeRequest>, JsonRejection>,
+) -> crate::Result<(StatusCode, Json<Challenge>)> {
+    let Json(request) = body.map_err(|e| crate::Error::InvalidInput(e.body_text()))?;
+    validate_nonce(&request.nonce)?;
+    crate::approval::validate_purpose(&request.purpose)?;
+    let account = crate::totp::account_or_default(request.account.as_deref(), &headers);
+    caller.authorize(account)?;
+    crate::store::ACCOUNTS.get(account)?;
+    let now = now_secs();
+    let challenge = Challenge {
+        id: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()),
+        account: account.to_owned(),
+        nonce: request.nonce,
+        purpose: request.purpose,
+        expires_at: now.saturating_add(*CHALLENGE_TTL_SECS),
+    };
+    CHALLENGES.insert(challenge.clone(), now);
+    Ok((StatusCode::CREATED, Json(challenge)))
+}
+
+/// Answer a challenge with a TOTP code of its account, and get an assertion
+/// carrying the nonce of the challenge.
+///
+/// The code is verified like `POST /v1/verify`. Each challenge is answered once,
+/// and codes which have answered a challenge cannot answer another one.
+#[utoipa::path(
+    post,
+    path = "/v1/challenges/{id}",
+    tag = "challenges",
+    params(("id" = String, Path, description = "Id of the challenge.")),
+    request_body(
+        description = "The token, whose account is the one of the challenge.",
+        content(
+            (crate::InputToken = "application/json"),
+            (crate::InputToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 200, description = "The token is valid, and an assertion is issued.", body = Assertion),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 423, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument]
+pub(crate) async fn answer_challenge(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    Path(id): Path<String>,
+    Input(input_token): Input<crate::InputToken>,
+) -> crate::Result<Json<Assertion>> {
+    let challenge = CHALLENGES.get(&id, now_secs())?;
+    crate::totp::verify(
+        &client,
+        &caller,
+        &challenge.account,
+        input_token.token(),
+        None,
+    )?;
+    let now = now_secs();
+    let challenge = CHALLENGES.answer(&id, now)?;
+    let claims = AssertionClaims {
+        sub: challenge.account,
+        nonce: challenge.nonce,
+        purpose: challenge.purpose,
+        challenge: challenge.id,
+        auth_time: now,
+        exp: now.saturating_add(*CHALLENGE_TTL_SECS),
+    };
+    let assertion = sign_assertion(&claims);
+    Ok(Json(Assertion { assertion, claims }))
+}
+
+/// Seconds which challenges (and their assertions) are valid for.
+///
+/// If env var `CHALLENGE_TTL` hasn't been set, the default value 120 will be set.
+///
+/// # Panics
+///
+/// Panics when `CHALLENGE_TTL` isn't a positive integer.
+pub(crate) static CHALLENGE_TTL_SECS: LazyLock<u64> = LazyLock::new(init_challenge_ttl);
+
+fn init_challenge_ttl() -> u64 {
+    std::env::var(CHALLENGE_TTL).map_or(120, |value| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&ttl| ttl != 0)
+            .unwrap_or_else(|| panic!("{CHALLENGE_TTL} must be a positive integer!"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn challenge(id: &str, account: &str) -> Challenge {
+        Challenge {
+            id: id.to_owned(),
+            account: account.to_owned(),
+            nonce: "txn-1".to_owned(),
+            purpose: "transfer".to_owned(),
+            expires_at: NOW + 60,
+        }
+    }
+
+    #[test]
+    fn test_challenge_store() {
+        let store = ChallengeStore::default();
+        store.insert(challenge("a", "alice"), NOW);
+        store.insert(challenge("b", "alice"), NOW);
+        store.insert(challenge("c", "bob"), NOW);
+        assert_eq!(store.answer("a", NOW).unwrap().id, "a");
+        // Each challenge is answered once.
+        assert!(matches!(
+            store.get("a", NOW),
+            Err(crate::Error::ChallengeNotFound { .. })
+        ));
+        assert!(matches!(
+            store.answer("a", NOW),
+            Err(crate::Error::ChallengeNotFound { .. })
+        ));
+        assert!(store.answer("c", NOW).is_ok());
+        assert!(store.answer("b", NOW).is_ok());
+    }
+
+    #[test]
+    fn test_challenge_store_expired() {
+        let store = ChallengeStore::default();
+        store.insert(challenge("a", "alice"), NOW);
+        assert!(store.get("a", NOW + 59).is_ok());
+        assert!(store.answer("a", NOW + 60).is_err());
+        // Expired challenges are forgotten when others are created.
+        store.insert(challenge("b", "alice"), NOW + 60);
+        assert_eq!(store.lock().len(), 1);
+    }
+
+    #[test]
+    fn test_sign_assertion() {
+        let claims = AssertionClaims {
+            sub: "alice".to_owned(),
+            nonce: "txn-1".to_owned(),
+            purpose: "transfer".to_owned(),
+            challenge: "a".to_owned(),
+            auth_time: NOW,
+            exp: NOW + 120,
+        };
+        let assertion = sign_assertion(&claims);
+        let (payload, signature) = assertion.split_once('.').unwrap();
+        let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&UNLOCK_CONFIG.public_key()).unwrap();
+        let signature = ed25519_dalek::Signature::from_slice(&signature).unwrap();
+        let message = [ASSERTION_DOMAIN, payload.as_bytes()].concat();
+        key.verify_strict(&message, &signature).unwrap();
+        let decoded: AssertionClaims =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        assert_eq!(decoded, claims);
+    }
+
+    #[rstest]
+    #[case("txn-8d1f2c", true)]
+    #[case(&"n".repeat(128), true)]
+    #[case("", false)]
+    #[case("two words", false)]
+    #[case("ü", false)]
+    #[case(&"n".repeat(129), false)]
+    fn test_validate_nonce(#[case] nonce: &str, #[case] valid: bool) {
+        assert_eq!(validate_nonce(nonce).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_challenge_ttl_default() {
+        assert!(std::env::var(CHALLENGE_TTL).is_err());
+        assert_eq!(*CHALLENGE_TTL_SECS, 120);
+    }
+
+    #[test]
+    #[should_panic(expected = "CHALLENGE_TTL must be a positive integer!")]
+    fn test_challenge_ttl_var_panic() {
+        unsafe { std::env::set_var(CHALLENGE_TTL, "0") }
+        let _ = *CHALLENGE_TTL_SECS;
+    }
+}
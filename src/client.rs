@@ -0,0 +1,248 @@
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+/// Information about the client who sent the request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientInfo {
+    /// IP address of the client (see [`client_ip`]).
+    pub(crate) ip: Option<IpAddr>,
+    /// Value of the `User-Agent` header.
+    pub(crate) user_agent: Option<String>,
+    /// Value of the `X-Request-Id` header.
+    pub(crate) request_id: Option<String>,
+    /// Rate limit of the route by account, if any.
+    pub(crate) account_limit: Option<crate::rate_limit::KeyedLimit>,
+}
+
+impl ClientInfo {
+    /// Count a request of the client against the rate limit of `account`, if any.
+    pub(crate) fn check_account_limit(&self, account: &str) -> crate::Result<()> {
+        self.account_limit
+            .as_ref()
+            .map_or(Ok(()), |limit| limit.check(account))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        Ok(ClientInfo {
+            ip: client_ip(
+                &parts.headers,
+                &parts.extensions,
+                &crate::TRUSTED_PROXIES,
+                *crate::TRUSTED_PROXY_HEADER,
+            ),
+            user_agent: header("user-agent"),
+            request_id: header("x-request-id"),
+            account_limit: parts.extensions.get().cloned(),
+        })
+    }
+}
+
+/// The forwarding header which trusted proxies set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ProxyHeader {
+    /// `Forwarded` (RFC 7239).
+    Forwarded,
+    /// `X-Forwarded-For`.
+    #[default]
+    XForwardedFor,
+    /// `X-Real-IP`.
+    XRealIp,
+}
+
+impl std::str::FromStr for ProxyHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "forwarded" => Ok(ProxyHeader::Forwarded),
+            "x-forwarded-for" => Ok(ProxyHeader::XForwardedFor),
+            "x-real-ip" => Ok(ProxyHeader::XRealIp),
+            _ => Err(format!("unknown proxy header: {s:?}")),
+        }
+    }
+}
+
+/// Get the IP address of the client.
+///
+/// If the peer is one of the `trusted_proxies`, the client IP is taken from
+/// `proxy_header` only, since any other forwarding header is passed through
+/// from the client as is. Addresses appended by trusted proxies are skipped,
+/// so the result is the rightmost address that isn't a trusted proxy.
+/// Otherwise forwarding headers are ignored, since they can be forged.
+pub(crate) fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trusted_proxies: &[ipnet::IpNet],
+    proxy_header: ProxyHeader,
+) -> Option<IpAddr> {
+    let peer = peer_ip(extensions)?;
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+    let forwarded = match proxy_header {
+        ProxyHeader::Forwarded => forwarded_for(headers),
+        ProxyHeader::XForwardedFor => header_ips(headers, "x-forwarded-for"),
+        ProxyHeader::XRealIp => header_ips(headers, "x-real-ip"),
+    };
+    let Some(forwarded) = forwarded else {
+        return Some(peer);
+    };
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted(ip))
+        .or(forwarded.first())
+        .copied()
+        .or(Some(peer))
+}
+
+/// Parse comma-separated IP addresses of the given header (e.g. `X-Forwarded-For`).
+fn header_ips(headers: &HeaderMap, name: &str) -> Option<Vec<IpAddr>> {
+    let ips: Vec<IpAddr> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|s| parse_node(s.trim()))
+        .collect();
+    (!ips.is_empty()).then_some(ips)
+}
+
+/// Parse the `for` parameters of the `Forwarded` header (RFC 7239).
+fn forwarded_for(headers: &HeaderMap) -> Option<Vec<IpAddr>> {
+    let ips: Vec<IpAddr> = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim().trim_matches('"')))?
+            })
+        })
+        .collect();
+    (!ips.is_empty()).then_some(ips)
+}
+
+/// Parse a node, which may come with a port (e.g. `192.0.2.1:8080` or `[2001:db8::1]:8080`).
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    node.strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .and_then(|s| s.parse().ok())
+}
+
+/// Get the IP address of the peer, either from [`ConnectInfo`]
+/// (standalone server) or from the request context (AWS Lambda).
+fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    use lambda_http::RequestExt;
+    use lambda_http::request::RequestContext;
+
+    if let Some(ConnectInfo(addr)) = extensions.get::<ConnectInfo<SocketAddr>>() {
+        return Some(addr.ip());
+    }
+    let source_ip = match extensions.request_context_ref()? {
+        RequestContext::ApiGatewayV2(context) => context.http.source_ip.as_deref(),
+        RequestContext::ApiGatewayV1(context) => context.identity.source_ip.as_deref(),
+        _ => None,
+    };
+    source_ip?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn client_ip_with(
+        peer: &str,
+        headers: &[(&'static str, &str)],
+        proxy_header: ProxyHeader,
+    ) -> Option<IpAddr> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            header_map.append(*name, value.parse().unwrap());
+        }
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 443)));
+        let trusted_proxies = ["10.0.0.0/8".parse().unwrap()];
+        client_ip(&header_map, &extensions, &trusted_proxies, proxy_header)
+    }
+
+    #[rstest]
+    #[case("10.0.0.1", &[], "10.0.0.1")]
+    #[case("10.0.0.1", &[("x-forwarded-for", "192.0.2.1")], "192.0.2.1")]
+    #[case("10.0.0.1", &[("x-forwarded-for", "198.51.100.7, 192.0.2.1, 10.0.0.2")], "192.0.2.1")]
+    #[case("10.0.0.1", &[("x-forwarded-for", "10.0.0.3, 10.0.0.2")], "10.0.0.3")]
+    #[case("10.0.0.1", &[("x-forwarded-for", "unknown")], "10.0.0.1")]
+    // A client-supplied header is ignored next to the one appended by the proxy.
+    #[case("10.0.0.1", &[("forwarded", "for=198.51.100.7"), ("x-forwarded-for", "192.0.2.1")], "192.0.2.1")]
+    #[case("10.0.0.1", &[("forwarded", "for=198.51.100.7")], "10.0.0.1")]
+    #[case("10.0.0.1", &[("x-real-ip", "198.51.100.7")], "10.0.0.1")]
+    // Forwarding headers from untrusted peers are ignored.
+    #[case("192.0.2.99", &[("x-forwarded-for", "192.0.2.1")], "192.0.2.99")]
+    fn test_client_ip(
+        #[case] peer: &str,
+        #[case] headers: &[(&'static str, &str)],
+        #[case] expected: &str,
+    ) {
+        let expected: IpAddr = expected.parse().unwrap();
+        let ip = client_ip_with(peer, headers, ProxyHeader::XForwardedFor);
+        assert_eq!(ip, Some(expected));
+    }
+
+    #[rstest]
+    #[case("10.0.0.1", &[("x-real-ip", "192.0.2.1")], ProxyHeader::XRealIp, "192.0.2.1")]
+    #[case("10.0.0.1", &[("x-real-ip", "192.0.2.1"), ("x-forwarded-for", "198.51.100.7")], ProxyHeader::XRealIp, "192.0.2.1")]
+    #[case("10.0.0.1", &[("forwarded", "for=192.0.2.60;proto=http;by=203.0.113.43")], ProxyHeader::Forwarded, "192.0.2.60")]
+    #[case("10.0.0.1", &[("forwarded", r#"For="[2001:db8:cafe::17]:4711""#)], ProxyHeader::Forwarded, "2001:db8:cafe::17")]
+    #[case("10.0.0.1", &[("forwarded", "for=192.0.2.43, for=198.51.100.17")], ProxyHeader::Forwarded, "198.51.100.17")]
+    #[case("10.0.0.1", &[("forwarded", "for=192.0.2.43"), ("x-forwarded-for", "198.51.100.7")], ProxyHeader::Forwarded, "192.0.2.43")]
+    #[case("192.0.2.99", &[("forwarded", "for=192.0.2.1")], ProxyHeader::Forwarded, "192.0.2.99")]
+    fn test_client_ip_proxy_header(
+        #[case] peer: &str,
+        #[case] headers: &[(&'static str, &str)],
+        #[case] proxy_header: ProxyHeader,
+        #[case] expected: &str,
+    ) {
+        let expected: IpAddr = expected.parse().unwrap();
+        assert_eq!(client_ip_with(peer, headers, proxy_header), Some(expected));
+    }
+
+    #[rstest]
+    #[case("Forwarded", ProxyHeader::Forwarded)]
+    #[case("x-forwarded-for", ProxyHeader::XForwardedFor)]
+    #[case(" X-Real-IP ", ProxyHeader::XRealIp)]
+    fn test_proxy_header_parse(#[case] value: &str, #[case] expected: ProxyHeader) {
+        assert_eq!(value.parse::<ProxyHeader>(), Ok(expected));
+    }
+
+    #[test]
+    fn test_client_ip_without_peer() {
+        let headers = HeaderMap::new();
+        let ip = client_ip(&headers, &Extensions::new(), &[], ProxyHeader::default());
+        assert_eq!(ip, None);
+    }
+}
@@ -12,7 +12,10 @@ pub const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 /// Env var which is used to set [`RATE_LIMIT`].
 const REQUEST_RATE_LIMIT: &str = "REQUEST_RATE_LIMIT";
 
-/// Request rate limit in every 30 seconds.
+/// Burst size of the `default` rate-limit policy.
+///
+/// Each client may send this many requests at once, after which one more
+/// request is allowed every 30 seconds (see `RATE_LIMIT_POLICIES` for other policies).
 ///
 /// If env var `REQUEST_RATE_LIMIT` hans't been set, the default value 25 will be set.
 pub(crate) static RATE_LIMIT: LazyLock<u32> = LazyLock::new(init_rate_limit);
@@ -67,18 +70,163 @@ fn init_bind_port() -> u16 {
     }
 }
 
+/// Env var which is used to set [`DRAIN_TIMEOUT`].
+const SHUTDOWN_DRAIN_TIMEOUT: &str = "SHUTDOWN_DRAIN_TIMEOUT";
+
+/// Seconds to wait for in-flight requests to complete after a shutdown signal.
+///
+/// If env var `SHUTDOWN_DRAIN_TIMEOUT` hasn't been set, the default value 5 will be set.
+///
+/// # Panics
+///
+/// Panics when [`SHUTDOWN_DRAIN_TIMEOUT`] isn't an unsigned integer.
+pub(crate) static DRAIN_TIMEOUT: LazyLock<u64> = LazyLock::new(init_drain_timeout);
+
+fn init_drain_timeout() -> u64 {
+    if let Ok(value) = std::env::var(SHUTDOWN_DRAIN_TIMEOUT) {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("{SHUTDOWN_DRAIN_TIMEOUT} must be an unsigned integer!"))
+    } else {
+        let default_value: u64 = 5;
+        tracing::info!(
+            "Env var {} hasn't been set. Using default value: {}.",
+            SHUTDOWN_DRAIN_TIMEOUT,
+            default_value
+        );
+        default_value
+    }
+}
+
+/// Env var which is used to set [`PRE_STOP_DELAY`].
+const SHUTDOWN_PRE_STOP_DELAY: &str = "SHUTDOWN_PRE_STOP_DELAY";
+
+/// Seconds to keep accepting connections with a failing health check after `SIGTERM`,
+/// so that load balancers stop routing requests before connections are refused.
+///
+/// If env var `SHUTDOWN_PRE_STOP_DELAY` hasn't been set, the default value 5 will be set.
+///
+/// # Panics
+///
+/// Panics when [`SHUTDOWN_PRE_STOP_DELAY`] isn't an unsigned integer.
+pub(crate) static PRE_STOP_DELAY: LazyLock<u64> = LazyLock::new(init_pre_stop_delay);
+
+fn init_pre_stop_delay() -> u64 {
+    if let Ok(value) = std::env::var(SHUTDOWN_PRE_STOP_DELAY) {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("{SHUTDOWN_PRE_STOP_DELAY} must be an unsigned integer!"))
+    } else {
+        let default_value: u64 = 5;
+        tracing::info!(
+            "Env var {} hasn't been set. Using default value: {}.",
+            SHUTDOWN_PRE_STOP_DELAY,
+            default_value
+        );
+        default_value
+    }
+}
+
+/// Env var which is used to set [`TRUSTED_PROXIES`].
+const TRUSTED_PROXIES_VAR: &str = "TRUSTED_PROXIES";
+
+/// Networks of reverse proxies whose forwarding headers are trusted.
+///
+/// Env var `TRUSTED_PROXIES` is a comma-separated list of CIDRs or IP addresses
+/// (e.g. `10.0.0.0/8,127.0.0.1`). If it hasn't been set, no proxy is trusted.
+///
+/// # Panics
+///
+/// Panics when any item in `TRUSTED_PROXIES` isn't a valid CIDR or IP address.
+pub(crate) static TRUSTED_PROXIES: LazyLock<Vec<ipnet::IpNet>> =
+    LazyLock::new(init_trusted_proxies);
+
+fn init_trusted_proxies() -> Vec<ipnet::IpNet> {
+    let Ok(value) = std::env::var(TRUSTED_PROXIES_VAR) else {
+        return Vec::new();
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<ipnet::IpNet>()
+                .or_else(|_| s.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "{TRUSTED_PROXIES_VAR} must contain CIDRs or IP addresses (invalid: {s:?})."
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Env var which is used to set [`TRUSTED_PROXY_HEADER`].
+const TRUSTED_PROXY_HEADER_VAR: &str = "TRUSTED_PROXY_HEADER";
+
+/// The forwarding header which [`TRUSTED_PROXIES`] set, and which the client IP is taken from.
+///
+/// Env var `TRUSTED_PROXY_HEADER` is one of `x-forwarded-for`, `forwarded` and `x-real-ip`.
+/// If it hasn't been set, `x-forwarded-for` is used.
+///
+/// # Panics
+///
+/// Panics when `TRUSTED_PROXY_HEADER` isn't one of the headers above.
+pub(crate) static TRUSTED_PROXY_HEADER: LazyLock<crate::client::ProxyHeader> =
+    LazyLock::new(init_trusted_proxy_header);
+
+fn init_trusted_proxy_header() -> crate::client::ProxyHeader {
+    let Ok(value) = std::env::var(TRUSTED_PROXY_HEADER_VAR) else {
+        return crate::client::ProxyHeader::default();
+    };
+    value.parse().unwrap_or_else(|_| {
+        panic!(
+            "{TRUSTED_PROXY_HEADER_VAR} must be one of x-forwarded-for, forwarded and x-real-ip!"
+        )
+    })
+}
+
 /// Check if required env vars have been set correctly.
 ///
 /// Required env vars include: `RAW_SECRET`.
-/// Optional env vars include: `REQUEST_RATE_LIMIT`, `TCP_BIND_PORT`.
+/// Optional env vars include: `REQUEST_RATE_LIMIT`, `TCP_BIND_PORT`, `LISTEN_ADDRS`, `ADMIN_LISTEN_ADDRS`,
+/// `SHUTDOWN_DRAIN_TIMEOUT`, `SHUTDOWN_PRE_STOP_DELAY`, `AUDIT_LOG_PATH`, `WEBHOOK_URLS`, `WEBHOOK_SECRET`,
+/// `WEBHOOK_FAILURE_THRESHOLD`, `WEBHOOK_FAILURE_WINDOW`, `TRUSTED_PROXIES`, `TRUSTED_PROXY_HEADER`,
+/// `RATE_LIMIT_POLICIES`, `RATE_LIMIT_ROUTES`, `API_KEYS`, `HMAC_KEYS`, `HMAC_REPLAY_WINDOW`,
+/// `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS`, `CORS_ALLOWED_HEADERS`, `CORS_ALLOW_CREDENTIALS`,
+/// `CORS_MAX_AGE`, `TLS_CERT_PATH`, `TLS_KEY_PATH`, `TLS_CLIENT_CA_PATH`, `TLS_REDIRECT_PORT`, `TLS_RELOAD_INTERVAL`,
+/// `ACCOUNTS_PATH`, `SESSION_SECRET`, `SESSION_TTL`, `OTPAUTH_ISSUER`, `OTPAUTH_LABEL`, `OTPAUTH_IMAGE`,
+/// `LOCKOUT_THRESHOLD`, `LOCKOUT_DURATION`, `ROLE_BINDINGS`, `UNLOCK_SIGNING_KEY`, `UNLOCK_TTL`,
+/// `APPROVAL_TTL`, `CHALLENGE_TTL`.
 ///
 /// # Panics
 /// It panics when any one of the required env var hasn't been set.
 /// It also panics when any parsing fails.
-pub(crate) fn env_var_check() {
+pub fn env_var_check() {
     let _ = crate::VEC_SECRET.clone();
     let _ = *RATE_LIMIT;
     let _ = *BIND_PORT;
+    let _ = &*crate::listen::LISTEN_ADDRS;
+    let _ = &*crate::listen::ADMIN_LISTEN_ADDRS;
+    let _ = *DRAIN_TIMEOUT;
+    let _ = *PRE_STOP_DELAY;
+    let _ = &*TRUSTED_PROXIES;
+    let _ = *TRUSTED_PROXY_HEADER;
+    let _ = &*crate::rate_limit::POLICIES;
+    let _ = &*crate::rate_limit::ROUTES;
+    let _ = &*crate::tls::TLS_CONFIG;
+    let _ = &*crate::auth::AUTH_CONFIG;
+    let _ = &*crate::rbac::RBAC_CONFIG;
+    let _ = &*crate::cors::CORS_CONFIG;
+    let _ = &*crate::store::ACCOUNTS;
+    let _ = &*crate::session::SESSION_CONFIG;
+    let _ = &*crate::unlock::UNLOCK_CONFIG;
+    let _ = *crate::approval::APPROVAL_TTL_SECS;
+    let _ = *crate::challenge::CHALLENGE_TTL_SECS;
+    let _ = &*crate::otpauth::OTPAUTH_CONFIG;
+    let _ = &*crate::audit::AUDIT_LOG;
+    let _ = &*crate::webhook::WEBHOOK_CONFIG;
+    let _ = &*crate::lockout::LOCKOUT_CONFIG;
 }
 
 #[cfg(test)]
@@ -144,6 +292,101 @@ mod tests {
         let _ = *BIND_PORT;
     }
 
+    #[test]
+    fn test_drain_timeout_default() {
+        assert!(std::env::var(SHUTDOWN_DRAIN_TIMEOUT).is_err());
+        assert_eq!(*DRAIN_TIMEOUT, 5);
+    }
+
+    #[rstest]
+    #[case("0")]
+    #[case("30")]
+    fn test_drain_timeout_var(#[case] value: &str) {
+        unsafe { std::env::set_var(SHUTDOWN_DRAIN_TIMEOUT, value) }
+        assert_eq!(*DRAIN_TIMEOUT, value.parse::<u64>().unwrap());
+    }
+
+    #[rstest]
+    #[case("abc")]
+    #[case("-1")]
+    #[should_panic(expected = "SHUTDOWN_DRAIN_TIMEOUT must be an unsigned integer")]
+    fn test_drain_timeout_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(SHUTDOWN_DRAIN_TIMEOUT, value) }
+        let _ = *DRAIN_TIMEOUT;
+    }
+
+    #[test]
+    fn test_pre_stop_delay_default() {
+        assert!(std::env::var(SHUTDOWN_PRE_STOP_DELAY).is_err());
+        assert_eq!(*PRE_STOP_DELAY, 5);
+    }
+
+    #[rstest]
+    #[case("0")]
+    #[case("15")]
+    fn test_pre_stop_delay_var(#[case] value: &str) {
+        unsafe { std::env::set_var(SHUTDOWN_PRE_STOP_DELAY, value) }
+        assert_eq!(*PRE_STOP_DELAY, value.parse::<u64>().unwrap());
+    }
+
+    #[rstest]
+    #[case("5s")]
+    #[case("-1")]
+    #[should_panic(expected = "SHUTDOWN_PRE_STOP_DELAY must be an unsigned integer")]
+    fn test_pre_stop_delay_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(SHUTDOWN_PRE_STOP_DELAY, value) }
+        let _ = *PRE_STOP_DELAY;
+    }
+
+    #[test]
+    fn test_trusted_proxies_default() {
+        assert!(std::env::var(TRUSTED_PROXIES_VAR).is_err());
+        assert!(TRUSTED_PROXIES.is_empty());
+    }
+
+    #[test]
+    fn test_trusted_proxies_var() {
+        unsafe { std::env::set_var(TRUSTED_PROXIES_VAR, "10.0.0.0/8, 127.0.0.1,::1") }
+        let expected: Vec<ipnet::IpNet> = vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "127.0.0.1/32".parse().unwrap(),
+            "::1/128".parse().unwrap(),
+        ];
+        assert_eq!(*TRUSTED_PROXIES, expected);
+    }
+
+    #[rstest]
+    #[case("10.0.0.0/33")]
+    #[case("localhost")]
+    #[should_panic(expected = "TRUSTED_PROXIES must contain CIDRs or IP addresses")]
+    fn test_trusted_proxies_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(TRUSTED_PROXIES_VAR, value) }
+        let _ = &*TRUSTED_PROXIES;
+    }
+
+    #[test]
+    fn test_trusted_proxy_header_default() {
+        use crate::client::ProxyHeader;
+        assert!(std::env::var(TRUSTED_PROXY_HEADER_VAR).is_err());
+        assert_eq!(*TRUSTED_PROXY_HEADER, ProxyHeader::XForwardedFor);
+    }
+
+    #[test]
+    fn test_trusted_proxy_header_var() {
+        use crate::client::ProxyHeader;
+        unsafe { std::env::set_var(TRUSTED_PROXY_HEADER_VAR, "Forwarded") }
+        assert_eq!(*TRUSTED_PROXY_HEADER, ProxyHeader::Forwarded);
+    }
+
+    #[rstest]
+    #[case("x-forwarded-host")]
+    #[case("")]
+    #[should_panic(expected = "TRUSTED_PROXY_HEADER must be one of")]
+    fn test_trusted_proxy_header_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(TRUSTED_PROXY_HEADER_VAR, value) }
+        let _ = *TRUSTED_PROXY_HEADER;
+    }
+
     #[test]
     fn test_cargo_name_pkg_name() {
         assert_eq!(CRATE_NAME, "totp_server"); // underscore
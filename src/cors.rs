@@ -0,0 +1,220 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Env var used to set allowed origins, e.g. `https://tools.example.com,https://*.example.com`.
+const CORS_ALLOWED_ORIGINS: &str = "CORS_ALLOWED_ORIGINS";
+/// Env var used to set allowed methods, e.g. `GET,POST`.
+const CORS_ALLOWED_METHODS: &str = "CORS_ALLOWED_METHODS";
+/// Env var used to set allowed request headers.
+const CORS_ALLOWED_HEADERS: &str = "CORS_ALLOWED_HEADERS";
+/// Env var used to set whether credentials (cookies, TLS client certificates) are allowed.
+const CORS_ALLOW_CREDENTIALS: &str = "CORS_ALLOW_CREDENTIALS";
+/// Env var used to set how long (in seconds) preflight responses may be cached.
+const CORS_MAX_AGE: &str = "CORS_MAX_AGE";
+
+/// Request headers which are allowed by default.
+const DEFAULT_ALLOWED_HEADERS: &str = "content-type,x-request-id,x-api-key,x-totp-account,\
+    x-totp-key-id,x-totp-timestamp,x-totp-signature";
+/// Response headers which browsers are allowed to read.
+const EXPOSED_HEADERS: [&str; 5] = [
+    "x-request-id",
+    "retry-after",
+    "ratelimit-limit",
+    "ratelimit-remaining",
+    "ratelimit-reset",
+];
+
+/// CORS settings, which are `None` if env var `CORS_ALLOWED_ORIGINS` hasn't been set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CorsConfig {
+    /// Allowed origins, which are either exact (`https://example.com`),
+    /// wildcard subdomains (`https://*.example.com`) or any (`*`).
+    pub(crate) origins: Vec<String>,
+    pub(crate) methods: Vec<Method>,
+    pub(crate) headers: Vec<HeaderName>,
+    pub(crate) credentials: bool,
+    pub(crate) max_age: Duration,
+}
+
+/// CORS settings.
+///
+/// # Panics
+///
+/// Panics when any CORS env var cannot be parsed.
+pub(crate) static CORS_CONFIG: LazyLock<Option<CorsConfig>> = LazyLock::new(init_cors_config);
+
+fn init_cors_config() -> Option<CorsConfig> {
+    let Ok(origins) = std::env::var(CORS_ALLOWED_ORIGINS) else {
+        tracing::info!("Env var {CORS_ALLOWED_ORIGINS} hasn't been set. CORS is disabled.");
+        return None;
+    };
+    let list = |name: &str, default: &str| -> Vec<String> {
+        std::env::var(name)
+            .unwrap_or_else(|_| default.to_owned())
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(ToOwned::to_owned)
+            .collect()
+    };
+    let origins = list(CORS_ALLOWED_ORIGINS, &origins);
+    for origin in &origins {
+        assert!(
+            origin == "*" || origin.contains("://"),
+            "{CORS_ALLOWED_ORIGINS}: {origin:?} should be like https://example.com."
+        );
+    }
+    let methods = list(CORS_ALLOWED_METHODS, "GET,POST")
+        .iter()
+        .map(|m| {
+            m.to_ascii_uppercase()
+                .parse()
+                .unwrap_or_else(|_| panic!("{CORS_ALLOWED_METHODS}: {m:?} is invalid."))
+        })
+        .collect();
+    let headers = list(CORS_ALLOWED_HEADERS, DEFAULT_ALLOWED_HEADERS)
+        .iter()
+        .map(|h| {
+            h.parse()
+                .unwrap_or_else(|_| panic!("{CORS_ALLOWED_HEADERS}: {h:?} is invalid."))
+        })
+        .collect();
+    let credentials = std::env::var(CORS_ALLOW_CREDENTIALS).is_ok_and(|value| {
+        value
+            .parse::<bool>()
+            .unwrap_or_else(|_| panic!("{CORS_ALLOW_CREDENTIALS} must be true or false!"))
+    });
+    // Credentials would be exposed to every site, since matched origins are reflected.
+    assert!(
+        !(credentials && origins.iter().any(|origin| origin == "*")),
+        "{CORS_ALLOW_CREDENTIALS} cannot be true when {CORS_ALLOWED_ORIGINS} contains \"*\"."
+    );
+    let max_age = std::env::var(CORS_MAX_AGE).map_or(600, |value| {
+        value
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("{CORS_MAX_AGE} must be an unsigned integer!"))
+    });
+    Some(CorsConfig {
+        origins,
+        methods,
+        headers,
+        credentials,
+        max_age: Duration::from_secs(max_age),
+    })
+}
+
+/// Check if `origin` matches the allowed origin `pattern`.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" || pattern.eq_ignore_ascii_case(origin) {
+        return true;
+    }
+    let Some((scheme, suffix)) = pattern.split_once("://*.") else {
+        return false;
+    };
+    let origin = origin.to_ascii_lowercase();
+    origin
+        .strip_prefix(&format!("{}://", scheme.to_ascii_lowercase()))
+        .and_then(|host| host.strip_suffix(&suffix.to_ascii_lowercase()))
+        .and_then(|subdomain| subdomain.strip_suffix('.'))
+        .is_some_and(|subdomain| !subdomain.is_empty() && !subdomain.contains(['/', ':', '@']))
+}
+
+/// Build the CORS layer, which is shared by the standalone server and AWS Lambda.
+///
+/// Returns `None` if CORS is disabled.
+pub(crate) fn cors_layer() -> Option<CorsLayer> {
+    let config = CORS_CONFIG.as_ref()?;
+    let origins = config.origins.clone();
+    let allow_origin = AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+        origin
+            .to_str()
+            .is_ok_and(|origin| origins.iter().any(|p| origin_matches(p, origin)))
+    });
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods(config.methods.clone())
+            .allow_headers(config.headers.clone())
+            .allow_credentials(config.credentials)
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+            .max_age(config.max_age),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("*", "https://anywhere.example.org", true)]
+    #[case("https://tools.example.com", "https://tools.example.com", true)]
+    #[case("https://tools.example.com", "https://TOOLS.example.com", true)]
+    #[case("https://tools.example.com", "http://tools.example.com", false)]
+    #[case("https://tools.example.com", "https://tools.example.com:8443", false)]
+    #[case("https://*.example.com", "https://tools.example.com", true)]
+    #[case("https://*.example.com", "https://a.b.example.com", true)]
+    #[case("https://*.example.com", "https://example.com", false)]
+    #[case("https://*.example.com", "https://evilexample.com", false)]
+    #[case("https://*.example.com", "https://example.com.evil.org", false)]
+    #[case("https://*.example.com", "http://tools.example.com", false)]
+    #[case("https://*.example.com:8443", "https://tools.example.com:8443", true)]
+    #[case("https://*.example.com", "https://evil.org:1@x.example.com", false)]
+    fn test_origin_matches(#[case] pattern: &str, #[case] origin: &str, #[case] expected: bool) {
+        assert_eq!(origin_matches(pattern, origin), expected);
+    }
+
+    #[test]
+    fn test_cors_config_default() {
+        assert!(std::env::var(CORS_ALLOWED_ORIGINS).is_err());
+        assert_eq!(*CORS_CONFIG, None);
+        assert!(cors_layer().is_none());
+    }
+
+    #[test]
+    fn test_cors_config_var() {
+        unsafe {
+            std::env::set_var(
+                CORS_ALLOWED_ORIGINS,
+                "https://a.example.com, https://*.example.org",
+            );
+            std::env::set_var(CORS_ALLOWED_METHODS, "post");
+            std::env::set_var(CORS_ALLOWED_HEADERS, "content-type");
+            std::env::set_var(CORS_ALLOW_CREDENTIALS, "true");
+            std::env::set_var(CORS_MAX_AGE, "60");
+        }
+        let expected = CorsConfig {
+            origins: vec![
+                "https://a.example.com".to_owned(),
+                "https://*.example.org".to_owned(),
+            ],
+            methods: vec![Method::POST],
+            headers: vec![HeaderName::from_static("content-type")],
+            credentials: true,
+            max_age: Duration::from_mins(1),
+        };
+        assert_eq!(CORS_CONFIG.as_ref(), Some(&expected));
+    }
+
+    #[rstest]
+    #[case(&[(CORS_ALLOWED_ORIGINS, "example.com")])]
+    #[case(&[(CORS_ALLOWED_METHODS, "GET,P OST")])]
+    #[case(&[(CORS_ALLOWED_HEADERS, "content type")])]
+    #[case(&[(CORS_ALLOW_CREDENTIALS, "yes")])]
+    #[case(&[(CORS_MAX_AGE, "-1")])]
+    #[case(&[(CORS_ALLOWED_ORIGINS, "https://example.com,*"), (CORS_ALLOW_CREDENTIALS, "true")])]
+    #[should_panic(expected = "CORS_")]
+    fn test_cors_config_var_panic(#[case] vars: &[(&str, &str)]) {
+        unsafe {
+            std::env::set_var(CORS_ALLOWED_ORIGINS, "https://example.com");
+            for (name, value) in vars {
+                std::env::set_var(name, value);
+            }
+        }
+        let _ = &*CORS_CONFIG;
+    }
+}
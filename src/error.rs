@@ -1,21 +1,149 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 
+/// Media types of request bodies which are accepted (see `crate::extract::Input`).
+const ACCEPTED_MEDIA_TYPES: &str =
+    "application/json, application/x-www-form-urlencoded, text/plain";
+
 /// A handy type alias for `Result<T, axum_demo::Error>`.
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Enumeration of errors that can occur in this crate.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    /// The provided TOTP code does not match the expected 6-digit format.
-    #[error("TOTP must be a 6-digit number")]
-    TotpInvalidFormat,
+    /// The provided TOTP code does not match the number of digits of the account (6 by default).
+    #[error("TOTP must be a {0}-digit number")]
+    TotpInvalidFormat(u8),
     /// The provided TOTP code is invalid or expired.
     #[error("invalid TOTP")]
     TotpInvalid,
+    /// The provided TOTP code (or a later one) has been used already.
+    #[error("TOTP has been used already")]
+    TotpReplayed,
+    /// The request cannot be parsed (e.g. malformed JSON or a missing field).
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    /// The body of the request is too large.
+    #[error("payload is too large")]
+    PayloadTooLarge,
+    /// The `Content-Type` of the request is missing or unsupported.
+    #[error("unsupported content type {0:?}, expected JSON, form data or plain text")]
+    UnsupportedMediaType(String),
+    /// The caller cannot be authenticated (see `API_KEYS` and `HMAC_KEYS`).
+    #[error("authentication failed: {0}")]
+    Unauthenticated(&'static str),
+    /// The caller isn't allowed to access the account.
+    #[error("access to account {account:?} is denied")]
+    Forbidden {
+        /// The account which the caller tried to access.
+        account: String,
+    },
+    /// No role of the caller grants the permission which the route requires (see `ROLE_BINDINGS`).
+    #[error("caller {caller:?} isn't granted the {permission:?} permission")]
+    PermissionDenied {
+        /// Key id (or `cert:<common name>`) of the caller.
+        caller: String,
+        /// The permission which the route requires.
+        permission: &'static str,
+    },
+    /// The session token is missing, malformed, forged or expired.
+    #[error("invalid session: {0}")]
+    SessionInvalid(&'static str),
+    /// The account doesn't exist.
+    #[error("account {account:?} doesn't exist")]
+    AccountNotFound {
+        /// Name of the account.
+        account: String,
+    },
+    /// The account already exists.
+    #[error("account {account:?} already exists")]
+    AccountExists {
+        /// Name of the account.
+        account: String,
+    },
+    /// The account is set by env var `RAW_SECRET`, thus it cannot be changed by requests.
+    #[error("account {account:?} is read-only")]
+    AccountReadOnly {
+        /// Name of the account.
+        account: String,
+    },
+    /// The account has been disabled by an admin.
+    #[error("account {account:?} is disabled")]
+    AccountDisabled {
+        /// Name of the account.
+        account: String,
+    },
+    /// The account is locked out after too many invalid codes (see `LOCKOUT_THRESHOLD`).
+    #[error("account {account:?} is locked out, retry after {retry_after}s")]
+    AccountLocked {
+        /// Name of the account.
+        account: String,
+        /// Seconds until the lockout expires.
+        retry_after: u64,
+    },
+    /// The account name is empty, too long or contains invalid characters.
+    #[error("invalid account name {account:?}")]
+    AccountNameInvalid {
+        /// Name of the account.
+        account: String,
+    },
+    /// The group doesn't exist.
+    #[error("group {group:?} doesn't exist")]
+    GroupNotFound {
+        /// Name of the group.
+        group: String,
+    },
+    /// The group cannot be removed while accounts are in it.
+    #[error("group {group:?} still has members")]
+    GroupInUse {
+        /// Name of the group.
+        group: String,
+    },
+    /// The approval doesn't exist, or has been forgotten after it expired.
+    #[error("approval {id:?} doesn't exist")]
+    ApprovalNotFound {
+        /// Id of the approval.
+        id: String,
+    },
+    /// The account cannot approve the approval (e.g. it has expired).
+    #[error("approval {id:?} is rejected: {reason}")]
+    ApprovalRejected {
+        /// Id of the approval.
+        id: String,
+        /// Why the account cannot approve it.
+        reason: &'static str,
+    },
+    /// The challenge doesn't exist, has expired or has been answered.
+    #[error("challenge {id:?} doesn't exist")]
+    ChallengeNotFound {
+        /// Id of the challenge.
+        id: String,
+    },
+    /// The client has sent too many requests (see `RATE_LIMIT_POLICIES`).
+    #[error("too many requests, retry after {retry_after}s")]
+    TooManyRequests {
+        /// The burst size of the rate-limit policy.
+        limit: u32,
+        /// Seconds until another request is allowed.
+        retry_after: u64,
+    },
     /// An error occurred while accessing system time.
     #[error(transparent)]
     SystemTime(#[from] std::time::SystemTimeError),
+    /// An I/O error occurred (e.g. while writing the audit log).
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// TLS certificates or keys cannot be loaded.
+    #[error("invalid TLS config: {0}")]
+    TlsConfig(String),
+    /// The hash chain of the audit log is broken at the given line.
+    #[error("invalid audit log at line {line}: {reason}")]
+    AuditLogInvalid {
+        /// The line number (starting from 1).
+        line: usize,
+        /// Why the record is invalid.
+        reason: String,
+    },
 }
 
 impl IntoResponse for Error {
@@ -24,9 +152,70 @@ impl IntoResponse for Error {
         tracing::error!("{self}");
         let msg = format!("Error: {self}");
         match self {
-            E::TotpInvalid => (StatusCode::UNAUTHORIZED, msg).into_response(),
-            E::TotpInvalidFormat => (StatusCode::BAD_REQUEST, msg).into_response(),
-            E::SystemTime(_) => (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response(),
+            E::TotpInvalid | E::TotpReplayed => (StatusCode::UNAUTHORIZED, msg).into_response(),
+            E::TotpInvalidFormat(_) | E::InvalidInput(_) | E::AccountNameInvalid { .. } => {
+                (StatusCode::BAD_REQUEST, msg).into_response()
+            }
+            E::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, msg).into_response(),
+            E::UnsupportedMediaType(_) => {
+                let headers = [("accept-post", ACCEPTED_MEDIA_TYPES)];
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, headers, msg).into_response()
+            }
+            E::Unauthenticated(_) => {
+                let headers = [("www-authenticate", "ApiKey, HMAC-SHA256")];
+                (StatusCode::UNAUTHORIZED, headers, msg).into_response()
+            }
+            E::SessionInvalid(_) => {
+                let headers = [("www-authenticate", "Bearer")];
+                (StatusCode::UNAUTHORIZED, headers, msg).into_response()
+            }
+            E::Forbidden { .. } | E::PermissionDenied { .. } | E::AccountDisabled { .. } => {
+                (StatusCode::FORBIDDEN, msg).into_response()
+            }
+            E::AccountLocked { retry_after, .. } => {
+                let headers = [("retry-after", retry_after.to_string())];
+                (StatusCode::LOCKED, headers, msg).into_response()
+            }
+            E::AccountNotFound { .. }
+            | E::GroupNotFound { .. }
+            | E::ApprovalNotFound { .. }
+            | E::ChallengeNotFound { .. } => (StatusCode::NOT_FOUND, msg).into_response(),
+            E::AccountExists { .. }
+            | E::AccountReadOnly { .. }
+            | E::GroupInUse { .. }
+            | E::ApprovalRejected { .. } => (StatusCode::CONFLICT, msg).into_response(),
+            E::TooManyRequests { limit, retry_after } => {
+                // Headers of draft-ietf-httpapi-ratelimit-headers.
+                let headers = [
+                    ("retry-after", retry_after.to_string()),
+                    ("ratelimit-limit", limit.to_string()),
+                    ("ratelimit-remaining", "0".to_owned()),
+                    ("ratelimit-reset", retry_after.to_string()),
+                ];
+                (StatusCode::TOO_MANY_REQUESTS, headers, msg).into_response()
+            }
+            E::SystemTime(_) | E::Io(_) | E::TlsConfig(_) | E::AuditLogInvalid { .. } => {
+                (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response()
+            }
         }
     }
 }
+
+impl<'r> utoipa::ToResponse<'r> for Error {
+    /// Error responses are plain text like `Error: invalid TOTP`.
+    fn response() -> (
+        &'r str,
+        utoipa::openapi::RefOr<utoipa::openapi::response::Response>,
+    ) {
+        use utoipa::openapi::{ContentBuilder, ObjectBuilder, ResponseBuilder, Type};
+        let content = ContentBuilder::new()
+            .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+            .example(Some("Error: invalid TOTP".into()))
+            .build();
+        let response = ResponseBuilder::new()
+            .description("Plain text starting with `Error: `.")
+            .content("text/plain", content)
+            .build();
+        ("Error", response.into())
+    }
+}
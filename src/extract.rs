@@ -0,0 +1,155 @@
+use axum::extract::{FromRequest, Query, Request};
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::{Form, Json};
+use serde::de::DeserializeOwned;
+
+/// Types which can be taken from a plain-text body (e.g. a bare TOTP code).
+pub(crate) trait FromPlainText: Sized {
+    /// Create the value from the text, which has been trimmed.
+    fn from_plain_text(text: &str) -> Self;
+}
+
+/// Extractor which negotiates the format of the request by its `Content-Type`.
+///
+/// - `application/json` (or `application/*+json`): a JSON object.
+/// - `application/x-www-form-urlencoded`: form data, e.g. `token=123456`.
+/// - `text/plain`: see [`FromPlainText`].
+/// - No `Content-Type`: the query string, e.g. `?token=123456`.
+///
+/// Every rejection is converted into [`crate::Error`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Input<T>(pub(crate) T);
+
+impl<T, S> FromRequest<S> for Input<T>
+where
+    T: DeserializeOwned + FromPlainText,
+    S: Send + Sync,
+{
+    type Rejection = crate::Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Some(content_type) = req.headers().get(CONTENT_TYPE) else {
+            if req.uri().query().is_none() {
+                return Err(crate::Error::UnsupportedMediaType(String::new()));
+            }
+            let Query(value) =
+                Query::try_from_uri(req.uri()).map_err(|e| rejection(e.status(), e.body_text()))?;
+            return Ok(Input(value));
+        };
+        let content_type = content_type.to_str().unwrap_or_default().to_owned();
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let value = match mime.as_str() {
+            "application/json" => Json::from_request(req, state)
+                .await
+                .map(|Json(value)| value)
+                .map_err(|e| rejection(e.status(), e.body_text()))?,
+            mime if mime.starts_with("application/") && mime.ends_with("+json") => {
+                Json::from_request(req, state)
+                    .await
+                    .map(|Json(value)| value)
+                    .map_err(|e| rejection(e.status(), e.body_text()))?
+            }
+            "application/x-www-form-urlencoded" => Form::from_request(req, state)
+                .await
+                .map(|Form(value)| value)
+                .map_err(|e| rejection(e.status(), e.body_text()))?,
+            "text/plain" => {
+                let text = String::from_request(req, state)
+                    .await
+                    .map_err(|e| rejection(e.status(), e.body_text()))?;
+                T::from_plain_text(text.trim())
+            }
+            _ => return Err(crate::Error::UnsupportedMediaType(content_type)),
+        };
+        Ok(Input(value))
+    }
+}
+
+/// Convert a rejection of axum extractors into [`crate::Error`], given its status and text.
+fn rejection(status: StatusCode, text: String) -> crate::Error {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => crate::Error::PayloadTooLarge,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => crate::Error::UnsupportedMediaType(String::new()),
+        _ => crate::Error::InvalidInput(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use rstest::rstest;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    struct Code {
+        token: String,
+    }
+
+    impl FromPlainText for Code {
+        fn from_plain_text(text: &str) -> Self {
+            Code {
+                token: text.to_owned(),
+            }
+        }
+    }
+
+    async fn extract(uri: &str, content_type: Option<&str>, body: &str) -> crate::Result<Code> {
+        let mut builder = Request::post(uri);
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        let request = builder.body(Body::from(body.to_owned())).unwrap();
+        Input::<Code>::from_request(request, &())
+            .await
+            .map(|Input(code)| code)
+    }
+
+    #[rstest]
+    #[case("/", Some("application/json"), r#"{"token":"123456"}"#)]
+    #[case("/", Some("application/json; charset=utf-8"), r#"{"token":"123456"}"#)]
+    #[case("/", Some("application/merge-patch+json"), r#"{"token":"123456"}"#)]
+    #[case("/", Some("application/x-www-form-urlencoded"), "token=123456")]
+    #[case("/", Some("text/plain"), "123456\n")]
+    #[case("/", Some("TEXT/PLAIN; charset=utf-8"), " 123456 ")]
+    #[case("/?token=123456", None, "")]
+    #[tokio::test]
+    async fn test_input(#[case] uri: &str, #[case] content_type: Option<&str>, #[case] body: &str) {
+        let code = extract(uri, content_type, body).await.unwrap();
+        assert_eq!(code.token, "123456");
+    }
+
+    #[rstest]
+    #[case("/", Some("application/json"), "{", 400)]
+    #[case("/", Some("application/json"), r#"{"code":"1"}"#, 400)]
+    #[case("/", Some("application/x-www-form-urlencoded"), "code=1", 400)]
+    #[case("/?code=1", None, "", 400)]
+    #[case("/", None, "", 415)]
+    #[case("/", Some("application/xml"), "<token/>", 415)]
+    #[tokio::test]
+    async fn test_input_rejection(
+        #[case] uri: &str,
+        #[case] content_type: Option<&str>,
+        #[case] body: &str,
+        #[case] expected: u16,
+    ) {
+        use axum::response::IntoResponse;
+        let err = extract(uri, content_type, body).await.unwrap_err();
+        assert_eq!(err.into_response().status().as_u16(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_input_too_large() {
+        let body = format!(r#"{{"token":"{}"}}"#, "1".repeat(3 * 1024 * 1024));
+        let err = extract("/", Some("application/json"), &body)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::PayloadTooLarge));
+    }
+}
@@ -0,0 +1,391 @@
+use crate::lockout::LockoutConfig;
+use crate::scope::ScopeRule;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Prefix of groups where they share a namespace with accounts,
+/// e.g. in the audit log and lockouts (account names never contain colons).
+pub(crate) const GROUP_PREFIX: &str = "group:";
+
+/// Default number of periods which codes may be behind or ahead of the current one.
+pub(crate) const DEFAULT_SKEW: u8 = 1;
+/// Max number of periods of skew.
+const MAX_SKEW: u8 = 5;
+
+/// Hash algorithms of TOTP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TotpAlgorithm {
+    /// HMAC-SHA-1, which every authenticator app supports.
+    #[default]
+    Sha1,
+    /// HMAC-SHA-256.
+    Sha256,
+    /// HMAC-SHA-512.
+    Sha512,
+}
+
+impl From<TotpAlgorithm> for totp_rs::Algorithm {
+    fn from(algorithm: TotpAlgorithm) -> Self {
+        match algorithm {
+            TotpAlgorithm::Sha1 => Self::SHA1,
+            TotpAlgorithm::Sha256 => Self::SHA256,
+            TotpAlgorithm::Sha512 => Self::SHA512,
+        }
+    }
+}
+
+impl std::str::FromStr for TotpAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "SHA1" => Ok(Self::Sha1),
+            "SHA256" => Ok(Self::Sha256),
+            "SHA512" => Ok(Self::Sha512),
+            _ => Err(format!("unknown algorithm {s:?}")),
+        }
+    }
+}
+
+impl std::fmt::Display for TotpAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Sha1 => "SHA1",
+            Self::Sha256 => "SHA256",
+            Self::Sha512 => "SHA512",
+        })
+    }
+}
+
+/// Parameters of TOTP, which are fixed when the secret of an account is generated,
+/// since authenticator apps keep them along with the secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TotpParams {
+    /// Hash algorithm.
+    pub algorithm: TotpAlgorithm,
+    /// Number of digits of codes (6 to 8).
+    #[schema(minimum = 6, maximum = 8, example = 6)]
+    pub digits: u8,
+    /// Seconds for which a code is valid (10 to 300).
+    #[schema(minimum = 10, maximum = 300, example = 30)]
+    pub period: u64,
+}
+
+impl Default for TotpParams {
+    fn default() -> Self {
+        TotpParams {
+            algorithm: TotpAlgorithm::Sha1,
+            digits: 6,
+            period: 30,
+        }
+    }
+}
+
+impl TotpParams {
+    /// Check if the parameters are the default ones (SHA-1, 6 digits and 30 seconds).
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check if the number of digits and the period are in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if either of them is out of range.
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        check_digits(self.digits)?;
+        check_period(self.period)
+    }
+}
+
+fn check_digits(digits: u8) -> crate::Result<()> {
+    if (6..=8).contains(&digits) {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidInput(
+            "digits must be between 6 and 8".to_owned(),
+        ))
+    }
+}
+
+fn check_period(period: u64) -> crate::Result<()> {
+    if (10..=300).contains(&period) {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidInput(
+            "period must be between 10 and 300 seconds".to_owned(),
+        ))
+    }
+}
+
+/// Settings which accounts inherit from their group, and which either of them may set.
+///
+/// Unset fields fall back to the group of the account, and then to the deployment-wide
+/// defaults. `algorithm`, `digits` and `period` take effect when the secret is
+/// generated (enrollment or rotation), while the others take effect immediately.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Policy {
+    /// Hash algorithm of new secrets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<TotpAlgorithm>,
+    /// Number of digits of new secrets (6 to 8).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(minimum = 6, maximum = 8)]
+    pub digits: Option<u8>,
+    /// Period (in seconds) of new secrets (10 to 300).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(minimum = 10, maximum = 300)]
+    pub period: Option<u64>,
+    /// Number of periods which codes may be behind or ahead of the current one (0 to 5).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(maximum = 5)]
+    pub skew: Option<u8>,
+    /// Consecutive invalid codes which lock an account out (0 never locks it out).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockout_threshold: Option<u32>,
+    /// Seconds for which accounts are locked out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockout_duration: Option<u64>,
+    /// Seconds which session tokens are valid for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(minimum = 1)]
+    pub session_ttl: Option<u64>,
+    /// Ids of keys which may verify codes, or any caller if it's unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!(["game"]))]
+    pub allowed_callers: Option<Vec<String>>,
+    /// Scopes which verified codes may be granted, by their requirements.
+    ///
+    /// The scopes of an account replace (rather than extend) the ones of its group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!({ "log_upload": {}, "cheats": { "fresh": true, "second_code": true } }))]
+    pub scopes: Option<BTreeMap<String, ScopeRule>>,
+}
+
+impl Policy {
+    /// Check if no field has been set.
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check if every field which has been set is in range.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if any field is out of range.
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        let invalid = |reason: &str| Err(crate::Error::InvalidInput(reason.to_owned()));
+        self.digits.map_or(Ok(()), check_digits)?;
+        self.period.map_or(Ok(()), check_period)?;
+        if self.skew.is_some_and(|skew| skew > MAX_SKEW) {
+            return invalid("skew must be between 0 and 5");
+        }
+        if self.session_ttl == Some(0) {
+            return invalid("session_ttl must be positive");
+        }
+        if let Some(callers) = &self.allowed_callers
+            && callers.iter().any(|caller| caller.trim().is_empty())
+        {
+            return invalid("allowed_callers must not contain empty key ids");
+        }
+        for scope in self.scopes.iter().flat_map(BTreeMap::keys) {
+            crate::scope::validate_scope_name(scope)?;
+        }
+        Ok(())
+    }
+
+    /// Fill fields which haven't been set with the ones of `fallback`.
+    #[must_use]
+    pub(crate) fn or(self, fallback: &Policy) -> Policy {
+        Policy {
+            algorithm: self.algorithm.or(fallback.algorithm),
+            digits: self.digits.or(fallback.digits),
+            period: self.period.or(fallback.period),
+            skew: self.skew.or(fallback.skew),
+            lockout_threshold: self.lockout_threshold.or(fallback.lockout_threshold),
+            lockout_duration: self.lockout_duration.or(fallback.lockout_duration),
+            session_ttl: self.session_ttl.or(fallback.session_ttl),
+            allowed_callers: self
+                .allowed_callers
+                .or_else(|| fallback.allowed_callers.clone()),
+            scopes: self.scopes.or_else(|| fallback.scopes.clone()),
+        }
+    }
+
+    /// Resolve the policy, falling back to the deployment-wide defaults.
+    pub(crate) fn resolve(&self) -> EffectivePolicy {
+        let defaults = TotpParams::default();
+        let lockout = &*crate::lockout::LOCKOUT_CONFIG;
+        EffectivePolicy {
+            totp: TotpParams {
+                algorithm: self.algorithm.unwrap_or(defaults.algorithm),
+                digits: self.digits.unwrap_or(defaults.digits),
+                period: self.period.unwrap_or(defaults.period),
+            },
+            skew: self.skew.unwrap_or(DEFAULT_SKEW),
+            lockout: LockoutConfig {
+                threshold: self.lockout_threshold.unwrap_or(lockout.threshold),
+                duration: self.lockout_duration.unwrap_or(lockout.duration),
+            },
+            session_ttl: self
+                .session_ttl
+                .unwrap_or(crate::session::SESSION_CONFIG.ttl),
+            allowed_callers: self.allowed_callers.clone(),
+            scopes: self.scopes.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// A policy whose every field has been resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EffectivePolicy {
+    /// Parameters of new secrets.
+    pub(crate) totp: TotpParams,
+    pub(crate) skew: u8,
+    pub(crate) lockout: LockoutConfig,
+    pub(crate) session_ttl: u64,
+    pub(crate) allowed_callers: Option<Vec<String>>,
+    pub(crate) scopes: BTreeMap<String, ScopeRule>,
+}
+
+impl EffectivePolicy {
+    /// Check if the caller may verify codes of the account.
+    ///
+    /// Callers without a key id (i.e. when authentication is disabled) are denied
+    /// if the policy restricts callers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Forbidden`] if the caller isn't allowed.
+    pub(crate) fn authorize(&self, caller: &crate::Caller, account: &str) -> crate::Result<()> {
+        let Some(allowed) = &self.allowed_callers else {
+            return Ok(());
+        };
+        let is_allowed = caller
+            .key_id
+            .as_ref()
+            .is_some_and(|key_id| allowed.contains(key_id));
+        if is_allowed {
+            Ok(())
+        } else {
+            Err(crate::Error::Forbidden {
+                account: account.to_owned(),
+            })
+        }
+    }
+}
+
+/// A group of accounts, which share its policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Group {
+    /// Name of the group, in the same format as account names.
+    #[schema(example = "game-x")]
+    pub name: String,
+    /// Policy which members inherit.
+    #[serde(flatten)]
+    pub policy: Policy,
+}
+
+/// A group with its members.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub(crate) struct GroupInfo {
+    /// The group.
+    #[serde(flatten)]
+    pub(crate) group: Group,
+    /// Names of the accounts in the group.
+    #[schema(example = json!(["alice", "bob"]))]
+    pub(crate) members: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_policy_inheritance() {
+        let group = Policy {
+            digits: Some(8),
+            skew: Some(2),
+            session_ttl: Some(600),
+            allowed_callers: Some(vec!["game".to_owned()]),
+            ..Policy::default()
+        };
+        let account = Policy {
+            skew: Some(0),
+            lockout_threshold: Some(3),
+            ..Policy::default()
+        };
+        let policy = account.or(&group).resolve();
+        assert_eq!(
+            policy.totp,
+            TotpParams {
+                algorithm: TotpAlgorithm::Sha1,
+                digits: 8,
+                period: 30,
+            }
+        );
+        assert_eq!(policy.skew, 0);
+        assert_eq!(policy.lockout.threshold, 3);
+        assert_eq!(policy.lockout.duration, 900);
+        assert_eq!(policy.session_ttl, 600);
+        assert_eq!(policy.allowed_callers, Some(vec!["game".to_owned()]));
+        // Nothing is set, so the defaults apply.
+        let policy = Policy::default().resolve();
+        assert!(policy.totp.is_default());
+        assert_eq!(policy.skew, DEFAULT_SKEW);
+        assert_eq!(policy.allowed_callers, None);
+    }
+
+    #[rstest]
+    #[case(r#"{"digits":9}"#)]
+    #[case(r#"{"digits":5}"#)]
+    #[case(r#"{"period":5}"#)]
+    #[case(r#"{"skew":6}"#)]
+    #[case(r#"{"session_ttl":0}"#)]
+    #[case(r#"{"allowed_callers":[""]}"#)]
+    #[case(r#"{"scopes":{"god mode":{}}}"#)]
+    fn test_policy_invalid(#[case] json: &str) {
+        let policy: Policy = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            policy.validate(),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_policy_serde() {
+        let json = r#"{"name":"game-x","algorithm":"SHA256","period":60}"#;
+        let group: Group = serde_json::from_str(json).unwrap();
+        assert_eq!(group.policy.algorithm, Some(TotpAlgorithm::Sha256));
+        assert_eq!(group.policy.period, Some(60));
+        assert!(group.policy.validate().is_ok());
+        assert_eq!(serde_json::to_string(&group).unwrap(), json);
+        assert!(serde_json::from_str::<Policy>(r#"{"algorithm":"MD5"}"#).is_err());
+    }
+
+    #[test]
+    fn test_allowed_callers() {
+        let policy = Policy {
+            allowed_callers: Some(vec!["game".to_owned()]),
+            ..Policy::default()
+        }
+        .resolve();
+        let caller = |key_id: Option<&str>| {
+            let mut caller = crate::Caller::anonymous();
+            caller.key_id = key_id.map(ToOwned::to_owned);
+            caller
+        };
+        assert!(policy.authorize(&caller(Some("game")), "alice").is_ok());
+        assert!(policy.authorize(&caller(Some("other")), "alice").is_err());
+        assert!(policy.authorize(&caller(None), "alice").is_err());
+        assert!(
+            Policy::default()
+                .resolve()
+                .authorize(&caller(None), "alice")
+                .is_ok()
+        );
+    }
+}
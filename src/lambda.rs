@@ -4,31 +4,43 @@
 ///
 /// It panics if fails to start the Lambda Rust runtime.
 pub async fn start_server_aws_lambda() {
+    use lambda_http::lambda_runtime::{Runtime, layers};
+
     tracing::info!("App version: {}.", crate::PKG_VERSION);
     // Check if required env vars have been set correctly.
     let _ = crate::VEC_SECRET.clone();
     // Print the base32-encoded secret.
     crate::print_secret_base32();
-    // Start the server by `lambda_http::run`, which differs from `axum::serve`.
-    lambda_http::run(app_aws_lambda())
+    // Start the Lambda runtime, which differs from `axum::serve`.
+    // Telemetry is flushed at the end of every invocation,
+    // since the execution environment may be frozen afterwards.
+    Runtime::new(lambda_http::Adapter::from(app_aws_lambda()))
+        .layer(layers::TracingLayer::new())
+        .layer(layers::OpenTelemetryLayer::new(crate::flush_telemetry))
+        .run()
         .await
         .unwrap_or_else(|e| panic!("Failed to start lambda_http server. Error: {e}."));
 }
 
 pub(crate) fn app_aws_lambda() -> axum::Router {
-    use crate::{check_current, handler_404, handler_405, health, timeout_error_handler};
+    use crate::{handler_404, health, timeout_error_handler};
     use axum::error_handling::HandleErrorLayer;
     use axum::routing::get;
+    use tower_http::ServiceBuilderExt;
+    use tower_http::request_id::MakeRequestUuid;
 
-    axum::Router::new()
-        .route("/", get(handler_405).post(check_current))
+    crate::api::router(false, crate::api::Routes::public())
         .route("/health", get(health))
         .fallback(handler_404)
         .layer(
             tower::ServiceBuilder::new()
+                // Set and propagate `x-request-id` headers.
+                .set_x_request_id(MakeRequestUuid)
+                .propagate_x_request_id()
                 .layer(HandleErrorLayer::new(timeout_error_handler))
                 .timeout(std::time::Duration::from_secs(1)),
-        )
+        ) // Answer CORS preflight requests before rate limiting and authentication.
+        .layer(tower::util::option_layer(crate::cors::cors_layer()))
 }
 
 #[cfg(test)]
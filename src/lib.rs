@@ -12,31 +12,109 @@
     deny(clippy::print_stdout, clippy::dbg_macro)
 )]
 
+/// Admin API (`/v1/admin/...`) managing the lifecycle of accounts.
+mod admin;
+/// Versioned REST API (`/v1/...`) and its `OpenAPI` document.
+mod api;
+/// Approvals which codes of several members of a group must approve.
+mod approval;
+/// Tamper-evident audit log of verification attempts.
+mod audit;
+/// Authenticates callers by API keys or HMAC-signed requests.
+mod auth;
+/// Versioned (and encrypted) JSON/CSV bundles of accounts, for backups and bulk imports.
+mod bundle;
+/// Challenges bound to nonces of relying parties, answered by codes for signed assertions.
+mod challenge;
+/// Extracts information about the client from requests.
+mod client;
 /// Defines constants and utilities for server configuration.
 mod config;
+/// Cross-origin resource sharing (CORS) for browser-based clients.
+mod cors;
 /// Defines custom error types and their implementations.
 mod error;
+/// Content-negotiating extractor of request bodies.
+mod extract;
+/// Account groups and the policies which accounts inherit from them.
+mod group;
 /// AWS Lambda
 mod lambda;
+/// Listen addresses (TCP, Unix domain sockets and systemd socket activation).
+mod listen;
+/// Locks accounts out after too many consecutive invalid codes.
+mod lockout;
+/// Migration payloads (`otpauth-migration://`) of authenticator apps.
+mod migration;
+/// Offline verification of unlock tokens, e.g. by game clients without network access.
+pub mod offline;
+/// Issuers, labels and images of otpauth URLs.
+mod otpauth;
+/// QR codes of otpauth URLs (Unicode, PNG and SVG).
+mod qr;
+/// Rate-limit policies per route.
+mod rate_limit;
+/// Role-based access control of routes.
+mod rbac;
+/// Scopes which verified codes are granted, e.g. to unlock features.
+mod scope;
 /// The entry point of [`totp_server`] library.
 mod server;
 /// Converts [`tower::Service`] inner errors into [`axum::response::IntoResponse`].
 mod service;
+/// Session tokens issued after TOTP codes are verified.
+mod session;
+/// Graceful shutdown on `SIGTERM` and `SIGINT`.
+mod shutdown;
+/// Enrolled accounts and their secrets.
+mod store;
+/// Logs, metrics and traces (OpenTelemetry).
+mod telemetry;
+/// Native TLS (rustls) and mutual TLS for the standalone server.
+mod tls;
 /// Core module for Time-based One-time Password (TOTP).
 mod totp;
+/// Signed unlock tokens issued after TOTP codes are verified, which clients verify offline.
+mod unlock;
 /// Utility routers for fallback and health checks.
 mod utils;
+/// Outbound webhook notifications on suspicious activity.
+mod webhook;
 
 #[cfg(test)]
 mod tests;
 
-pub(crate) use config::{BIND_PORT, RATE_LIMIT, env_var_check};
+pub(crate) use auth::{Caller, authenticate};
+pub(crate) use client::ClientInfo;
+pub(crate) use config::{
+    BIND_PORT, DRAIN_TIMEOUT, PRE_STOP_DELAY, RATE_LIMIT, TRUSTED_PROXIES, TRUSTED_PROXY_HEADER,
+};
 pub(crate) use service::timeout_error_handler;
+pub(crate) use shutdown::{is_draining, shutdown_signal};
 pub(crate) use totp::{VEC_SECRET, check_current, print_qr_code, print_secret_base32};
 pub(crate) use utils::{handler_404, handler_405, health};
 
-pub use config::{CRATE_NAME, PKG_NAME, PKG_VERSION};
+pub use audit::{AuditAction, AuditOutcome, AuditRecord, GENESIS_HASH, verify_audit_log};
+pub use bundle::{BundleFormat, ConflictPolicy, ImportDiff, export_bundle, import_bundle};
+pub use config::{CRATE_NAME, PKG_NAME, PKG_VERSION, env_var_check};
 pub use error::{Error, Result};
+pub use group::{Group, Policy, TotpAlgorithm, TotpParams};
 pub use lambda::start_server_aws_lambda;
+pub use lockout::LockoutStatus;
+pub use migration::{
+    MigrationAlgorithm, MigrationDigits, MigrationOtpType, MigrationPayload, OtpParameters,
+};
+pub use otpauth::OtpauthProfile;
+pub use qr::{QrEcLevel, QrFormat, QrOptions, decode_qr_image, render_qr_code, render_qr_image};
+pub use scope::ScopeRule;
 pub use server::start_server;
-pub use totp::{InputToken, try_get_token};
+pub use store::{
+    AccountInfo, Enrollment, ImportReport, add_account, export_accounts, import_accounts,
+    list_accounts, remove_account,
+};
+pub use telemetry::{flush_telemetry, init_telemetry, shutdown_telemetry};
+pub use totp::{
+    InputToken, SecretEncoding, current_token, generate_secret, get_otpauth_url, try_get_token,
+    verify_token,
+};
+pub use unlock::generate_unlock_key;
@@ -0,0 +1,406 @@
+use axum::serve::Listener;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+/// Env var used to set the addresses to listen on.
+///
+/// Addresses are comma-separated, each of which is either a socket address
+/// (e.g. `127.0.0.1:7392` or `[::]:7392`) or a Unix domain socket (e.g. `unix:/run/totp.sock`).
+const LISTEN_ADDRS_VAR: &str = "LISTEN_ADDRS";
+/// Env var used to set the addresses the admin API listens on, in the format of `LISTEN_ADDRS`.
+const ADMIN_LISTEN_ADDRS_VAR: &str = "ADMIN_LISTEN_ADDRS";
+
+/// An address to listen on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ListenAddr {
+    /// A TCP socket address (IPv4 or IPv6).
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket.
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("the path of unix socket is empty".to_owned());
+            }
+            return Ok(Self::Unix(path.into()));
+        }
+        s.parse()
+            .map(Self::Tcp)
+            .map_err(|_| format!("{s:?} is neither a socket address nor unix:/path"))
+    }
+}
+
+impl std::fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Addresses to listen on.
+///
+/// If env var `LISTEN_ADDRS` hasn't been set, the server listens on
+/// `0.0.0.0` at [`BIND_PORT`](crate::BIND_PORT).
+/// It's ignored when sockets are passed by systemd (`LISTEN_FDS`).
+///
+/// # Panics
+///
+/// Panics when env var `LISTEN_ADDRS` cannot be parsed.
+pub(crate) static LISTEN_ADDRS: LazyLock<Vec<ListenAddr>> = LazyLock::new(init_listen_addrs);
+
+fn init_listen_addrs() -> Vec<ListenAddr> {
+    let Ok(value) = std::env::var(LISTEN_ADDRS_VAR) else {
+        let default_value = ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], *crate::BIND_PORT)));
+        tracing::info!(
+            "Env var {} hasn't been set. Using default value: {}.",
+            LISTEN_ADDRS_VAR,
+            default_value
+        );
+        return vec![default_value];
+    };
+    parse_listen_addrs(LISTEN_ADDRS_VAR, &value)
+}
+
+/// Addresses the admin API (`/v1/admin/...`) listens on.
+///
+/// If env var `ADMIN_LISTEN_ADDRS` has been set, the admin API is served on these
+/// addresses only and never on [`LISTEN_ADDRS`]. Otherwise, it's served with
+/// the rest of the API.
+///
+/// # Panics
+///
+/// Panics when env var `ADMIN_LISTEN_ADDRS` cannot be parsed.
+pub(crate) static ADMIN_LISTEN_ADDRS: LazyLock<Option<Vec<ListenAddr>>> = LazyLock::new(|| {
+    std::env::var(ADMIN_LISTEN_ADDRS_VAR)
+        .ok()
+        .map(|value| parse_listen_addrs(ADMIN_LISTEN_ADDRS_VAR, &value))
+});
+
+/// Parse comma-separated addresses of the env var `key`.
+fn parse_listen_addrs(key: &str, value: &str) -> Vec<ListenAddr> {
+    let addrs: Vec<ListenAddr> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse()
+                .unwrap_or_else(|e| panic!("{key} is invalid: {e}."))
+        })
+        .collect();
+    assert!(!addrs.is_empty(), "{key} must not be empty.");
+    addrs
+}
+
+/// A listener which is ready to accept connections.
+#[derive(Debug)]
+pub(crate) enum BoundListener {
+    /// A TCP listener.
+    Tcp(tokio::net::TcpListener),
+    /// A Unix domain socket listener.
+    #[cfg(unix)]
+    Unix(UnixSocketListener),
+}
+
+/// Bind to every address of [`LISTEN_ADDRS`], or take over the sockets
+/// passed by systemd socket activation (`LISTEN_FDS`).
+///
+/// # Panics
+///
+/// Panics if any address cannot be bound,
+/// or if any passed socket is neither a TCP nor a Unix stream socket.
+pub(crate) async fn bind_listeners() -> Vec<BoundListener> {
+    let mut listen_fds = listenfd::ListenFd::from_env();
+    if listen_fds.len() > 0 {
+        tracing::info!("Using {} socket(s) passed by systemd.", listen_fds.len());
+        return (0..listen_fds.len())
+            .map(|index| take_listener(&mut listen_fds, index))
+            .collect();
+    }
+    bind_all(&LISTEN_ADDRS).await
+}
+
+/// Bind to every address of [`ADMIN_LISTEN_ADDRS`], if any.
+///
+/// # Panics
+///
+/// Panics if any address cannot be bound.
+pub(crate) async fn bind_admin_listeners() -> Vec<BoundListener> {
+    match ADMIN_LISTEN_ADDRS.as_deref() {
+        Some(addrs) => bind_all(addrs).await,
+        None => Vec::new(),
+    }
+}
+
+/// Bind the plain HTTP listeners which redirect to HTTPS, on the hosts of the
+/// TCP addresses of [`LISTEN_ADDRS`] at the given `port`.
+///
+/// # Panics
+///
+/// Panics if any address cannot be bound.
+pub(crate) async fn bind_redirect_listeners(port: u16) -> Vec<tokio::net::TcpListener> {
+    let mut listeners = Vec::new();
+    for addr in redirect_addrs(&LISTEN_ADDRS, port) {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind {addr}. Error: {e}."));
+        listeners.push(listener);
+    }
+    listeners
+}
+
+/// The TCP addresses of `listen_addrs` with their port replaced by `port`.
+fn redirect_addrs(listen_addrs: &[ListenAddr], port: u16) -> Vec<SocketAddr> {
+    let mut addrs = Vec::new();
+    for addr in listen_addrs {
+        if let ListenAddr::Tcp(addr) = addr {
+            let addr = SocketAddr::new(addr.ip(), port);
+            if !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    addrs
+}
+
+/// Bind to every given address.
+async fn bind_all(addrs: &[ListenAddr]) -> Vec<BoundListener> {
+    let mut listeners = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        let listener = bind(addr)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind {addr}. Error: {e}."));
+        listeners.push(listener);
+    }
+    listeners
+}
+
+/// Bind to the given address.
+async fn bind(addr: &ListenAddr) -> std::io::Result<BoundListener> {
+    match addr {
+        ListenAddr::Tcp(addr) => Ok(BoundListener::Tcp(
+            tokio::net::TcpListener::bind(addr).await?,
+        )),
+        #[cfg(unix)]
+        ListenAddr::Unix(path) => {
+            use std::os::unix::fs::FileTypeExt;
+            // Remove the socket file left by the previous run, but nothing else.
+            if let Ok(metadata) = std::fs::symlink_metadata(path) {
+                if !metadata.file_type().is_socket() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        "the path exists and isn't a socket",
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            let listener = tokio::net::UnixListener::bind(path)?;
+            Ok(BoundListener::Unix(UnixSocketListener {
+                listener,
+                path: path.clone(),
+            }))
+        }
+        #[cfg(not(unix))]
+        ListenAddr::Unix(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "unix domain sockets aren't supported on this platform",
+        )),
+    }
+}
+
+/// Take the socket passed by systemd at the given index.
+fn take_listener(listen_fds: &mut listenfd::ListenFd, index: usize) -> BoundListener {
+    let invalid = |e: std::io::Error| -> ! {
+        panic!("Failed to take socket {index} passed by systemd. Error: {e}.")
+    };
+    let into_tokio = |listener: std::net::TcpListener| {
+        listener.set_nonblocking(true)?;
+        tokio::net::TcpListener::from_std(listener)
+    };
+    match listen_fds.take_tcp_listener(index) {
+        Ok(Some(listener)) => {
+            BoundListener::Tcp(into_tokio(listener).unwrap_or_else(|e| invalid(e)))
+        }
+        #[cfg(unix)]
+        Err(_) => {
+            let listener = listen_fds
+                .take_unix_listener(index)
+                .and_then(|listener| {
+                    let listener = listener.ok_or(std::io::ErrorKind::NotFound)?;
+                    listener.set_nonblocking(true)?;
+                    tokio::net::UnixListener::from_std(listener)
+                })
+                .unwrap_or_else(|e| invalid(e));
+            let path = listener
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(PathBuf::from))
+                .unwrap_or_default();
+            BoundListener::Unix(UnixSocketListener { listener, path })
+        }
+        #[cfg(not(unix))]
+        Err(e) => invalid(e),
+        Ok(None) => invalid(std::io::ErrorKind::NotFound.into()),
+    }
+}
+
+/// A [`Listener`] of a Unix domain socket.
+///
+/// Peers of Unix domain sockets are local processes, thus their address
+/// is reported as `127.0.0.1` (e.g. for rate limiting and audit), which can
+/// be added to `TRUSTED_PROXIES` for a sidecar proxy.
+#[cfg(unix)]
+#[derive(Debug)]
+pub(crate) struct UnixSocketListener {
+    listener: tokio::net::UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl std::fmt::Display for UnixSocketListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unix:{}", self.path.display())
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixSocketListener {
+    type Io = tokio::net::UnixStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (stream, _) = Listener::accept(&mut self.listener).await;
+        (stream, SocketAddr::from(([127, 0, 0, 1], 0)))
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(SocketAddr::from(([127, 0, 0, 1], 0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("127.0.0.1:7392", ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 7392))))]
+    #[case("[::]:7392", ListenAddr::Tcp("[::]:7392".parse().unwrap()))]
+    #[case("[::1]:80", ListenAddr::Tcp("[::1]:80".parse().unwrap()))]
+    #[case("unix:/run/totp.sock", ListenAddr::Unix("/run/totp.sock".into()))]
+    fn test_listen_addr_parse(#[case] value: &str, #[case] expected: ListenAddr) {
+        let addr: ListenAddr = value.parse().unwrap();
+        assert_eq!(addr, expected);
+        assert_eq!(addr.to_string(), value);
+    }
+
+    #[rstest]
+    #[case("7392")]
+    #[case("localhost:7392")]
+    #[case("::1:7392")]
+    #[case("unix:")]
+    fn test_listen_addr_parse_err(#[case] value: &str) {
+        assert!(value.parse::<ListenAddr>().is_err());
+    }
+
+    #[test]
+    fn test_listen_addrs_default() {
+        assert!(std::env::var(LISTEN_ADDRS_VAR).is_err());
+        let expected = ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], *crate::BIND_PORT)));
+        assert_eq!(*LISTEN_ADDRS, [expected]);
+    }
+
+    #[test]
+    fn test_listen_addrs_var() {
+        unsafe { std::env::set_var(LISTEN_ADDRS_VAR, "127.0.0.1:80, [::1]:80,unix:/tmp/a.sock") }
+        let expected = [
+            ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 80))),
+            ListenAddr::Tcp("[::1]:80".parse().unwrap()),
+            ListenAddr::Unix("/tmp/a.sock".into()),
+        ];
+        assert_eq!(*LISTEN_ADDRS, expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("127.0.0.1:80,localhost")]
+    #[should_panic(expected = "LISTEN_ADDRS")]
+    fn test_listen_addrs_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(LISTEN_ADDRS_VAR, value) }
+        let _ = &*LISTEN_ADDRS;
+    }
+
+    #[test]
+    fn test_admin_listen_addrs() {
+        assert_eq!(*ADMIN_LISTEN_ADDRS, None);
+    }
+
+    #[test]
+    fn test_admin_listen_addrs_var() {
+        unsafe { std::env::set_var(ADMIN_LISTEN_ADDRS_VAR, "127.0.0.1:7393") }
+        let expected = [ListenAddr::Tcp(SocketAddr::from(([127, 0, 0, 1], 7393)))];
+        assert_eq!(ADMIN_LISTEN_ADDRS.as_deref(), Some(expected.as_slice()));
+    }
+
+    #[test]
+    fn test_redirect_addrs() {
+        let listen_addrs: Vec<ListenAddr> = [
+            "127.0.0.1:7392",
+            "[::1]:7392",
+            "unix:/run/totp.sock",
+            "127.0.0.1:7393",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let expected: Vec<SocketAddr> = vec![
+            "127.0.0.1:8080".parse().unwrap(),
+            "[::1]:8080".parse().unwrap(),
+        ];
+        assert_eq!(redirect_addrs(&listen_addrs, 8080), expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        use axum::serve::ListenerExt;
+
+        let path = std::env::temp_dir().join(format!("totp-{}.sock", rand::random::<u64>()));
+        // A stale socket file is replaced.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let Ok(BoundListener::Unix(listener)) = bind(&ListenAddr::Unix(path.clone())).await else {
+            panic!("failed to bind unix socket");
+        };
+        assert_eq!(listener.to_string(), format!("unix:{}", path.display()));
+        let app = crate::server::app().into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener.tap_io(|_| {}), app).await });
+
+        let client = reqwest::Client::builder()
+            .unix_socket(path.as_path())
+            .build()
+            .unwrap();
+        let response = client.get("http://localhost/health").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_non_socket() {
+        let path = std::env::temp_dir().join(format!("totp-{}.sock", rand::random::<u64>()));
+        std::fs::write(&path, "").unwrap();
+        assert!(bind(&ListenAddr::Unix(path.clone())).await.is_err());
+        // The existing file is left untouched.
+        assert!(path.exists());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// Env var used to set [`LockoutConfig::threshold`].
+const LOCKOUT_THRESHOLD: &str = "LOCKOUT_THRESHOLD";
+/// Env var used to set [`LockoutConfig::duration`] in seconds.
+const LOCKOUT_DURATION: &str = "LOCKOUT_DURATION";
+
+/// Lockout configuration read from env vars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LockoutConfig {
+    /// Number of consecutive invalid codes which locks an account out,
+    /// or 0 if accounts are never locked out.
+    pub(crate) threshold: u32,
+    /// Seconds for which accounts are locked out.
+    pub(crate) duration: u64,
+}
+
+fn init_lockout_config() -> LockoutConfig {
+    let parse_var = |key: &str, default_value: u64| {
+        std::env::var(key).map_or(default_value, |value| {
+            value
+                .parse::<u64>()
+                .unwrap_or_else(|_| panic!("{key} must be an unsigned integer!"))
+        })
+    };
+    LockoutConfig {
+        threshold: u32::try_from(parse_var(LOCKOUT_THRESHOLD, 0)).unwrap_or(u32::MAX),
+        duration: parse_var(LOCKOUT_DURATION, 900),
+    }
+}
+
+/// Lockout configuration.
+///
+/// Accounts are never locked out if env var `LOCKOUT_THRESHOLD` hasn't been set,
+/// while their failures are still counted.
+///
+/// # Panics
+///
+/// Panics when any of the related env vars isn't an unsigned integer.
+pub(crate) static LOCKOUT_CONFIG: LazyLock<LockoutConfig> = LazyLock::new(init_lockout_config);
+
+/// Failures of an account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct LockoutStatus {
+    /// Number of consecutive invalid codes since the last valid one (or reset).
+    pub failures: u32,
+    /// Until when the account is locked out (seconds since the Unix epoch).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<u64>,
+}
+
+/// Tracks consecutive failures per account, locking accounts out
+/// once their failures reach the threshold.
+#[derive(Debug, Default)]
+pub(crate) struct LockoutTracker {
+    accounts: Mutex<HashMap<String, LockoutStatus>>,
+}
+
+impl LockoutTracker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, LockoutStatus>> {
+        self.accounts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Get the failures of the account, forgetting an expired lockout.
+    pub(crate) fn status(&self, account: &str, now: u64) -> LockoutStatus {
+        let mut accounts = self.lock();
+        let Some(status) = accounts.get_mut(account) else {
+            return LockoutStatus::default();
+        };
+        if status.locked_until.is_some_and(|until| until <= now) {
+            status.locked_until = None;
+        }
+        *status
+    }
+
+    /// Check if the account isn't locked out.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::AccountLocked`] if the account is locked out.
+    pub(crate) fn check(&self, account: &str, now: u64) -> crate::Result<()> {
+        match self.status(account, now).locked_until {
+            Some(until) => Err(crate::Error::AccountLocked {
+                account: account.to_owned(),
+                retry_after: until - now,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a verification result, and return true if the account has just been locked out.
+    pub(crate) fn record(
+        &self,
+        account: &str,
+        success: bool,
+        now: u64,
+        config: &LockoutConfig,
+    ) -> bool {
+        let mut accounts = self.lock();
+        if success {
+            accounts.remove(account);
+            return false;
+        }
+        let status = accounts.entry(account.to_owned()).or_default();
+        status.failures = status.failures.saturating_add(1);
+        if config.threshold == 0 || status.failures < config.threshold {
+            return false;
+        }
+        // Another round of attempts is allowed once the lockout expires.
+        status.failures = 0;
+        status.locked_until = Some(now.saturating_add(config.duration));
+        true
+    }
+
+    /// Clear the failures and the lockout of the account, returning what they were.
+    pub(crate) fn reset(&self, account: &str) -> LockoutStatus {
+        self.lock().remove(account).unwrap_or_default()
+    }
+}
+
+/// Failures and lockouts of accounts, which are kept in memory only.
+pub(crate) static LOCKOUTS: LazyLock<LockoutTracker> = LazyLock::new(LockoutTracker::default);
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+
+    const CONFIG: LockoutConfig = LockoutConfig {
+        threshold: 3,
+        duration: 60,
+    };
+
+    #[test]
+    fn test_lockout() {
+        let tracker = LockoutTracker::default();
+        assert!(!tracker.record("alice", false, 100, &CONFIG));
+        assert!(!tracker.record("alice", false, 101, &CONFIG));
+        assert_eq!(tracker.status("alice", 101).failures, 2);
+        // A valid code clears failures.
+        assert!(!tracker.record("alice", true, 102, &CONFIG));
+        assert_eq!(tracker.status("alice", 102), LockoutStatus::default());
+        for now in 103..105 {
+            assert!(!tracker.record("alice", false, now, &CONFIG));
+        }
+        assert!(tracker.record("alice", false, 105, &CONFIG));
+        let err = tracker.check("alice", 110).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::AccountLocked {
+                retry_after: 55,
+                ..
+            }
+        ));
+        assert!(tracker.check("bob", 110).is_ok());
+        // The lockout expires.
+        assert!(tracker.check("alice", 165).is_ok());
+        assert_eq!(tracker.status("alice", 165), LockoutStatus::default());
+    }
+
+    #[test]
+    fn test_lockout_reset() {
+        let tracker = LockoutTracker::default();
+        for now in 0..3 {
+            tracker.record("alice", false, now, &CONFIG);
+        }
+        let status = tracker.reset("alice");
+        assert_eq!(status.locked_until, Some(62));
+        assert!(tracker.check("alice", 10).is_ok());
+    }
+
+    #[test]
+    fn test_lockout_disabled() {
+        let config = LockoutConfig {
+            threshold: 0,
+            ..CONFIG
+        };
+        let tracker = LockoutTracker::default();
+        for now in 0..100 {
+            assert!(!tracker.record("alice", false, now, &config));
+        }
+        assert_eq!(tracker.status("alice", 100).failures, 100);
+        assert!(tracker.check("alice", 100).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "LOCKOUT_THRESHOLD must be an unsigned integer!")]
+    fn test_lockout_config_panic() {
+        unsafe { std::env::set_var(LOCKOUT_THRESHOLD, "-1") }
+        let _ = &*LOCKOUT_CONFIG;
+    }
+}
@@ -12,31 +12,462 @@
     deny(clippy::print_stdout, clippy::dbg_macro)
 )]
 
-use opentelemetry_sdk::error::OTelSdkError;
-use opentelemetry_sdk::logs::SdkLoggerProvider;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
-use opentelemetry_sdk::trace::SdkTracerProvider;
-use std::sync::LazyLock;
-use tracing_subscriber::filter::LevelFilter;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use totp_server::{BundleFormat, ConflictPolicy, QrEcLevel, QrFormat, QrOptions, SecretEncoding};
+
+/// Env var used to set the passphrase of encrypted bundles.
+const BUNDLE_PASSPHRASE: &str = "BUNDLE_PASSPHRASE";
+
+/// Time-based One-time Password (TOTP) server.
+///
+/// Without a subcommand, it starts the server (or the AWS Lambda runtime
+/// if env var `AWS_LAMBDA_FUNCTION_NAME` has been set).
+#[derive(Debug, clap::Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Start the standalone server.
+    Serve,
+    /// Start the AWS Lambda runtime.
+    Lambda,
+    /// Generate a random secret.
+    GenSecret {
+        /// Bit length of the secret (a multiple of 8, at least 128).
+        #[arg(long, default_value_t = 160)]
+        bits: usize,
+        /// How the secret is encoded (`raw` can be used as `RAW_SECRET`).
+        #[arg(long, value_enum, default_value_t)]
+        encoding: SecretEncoding,
+    },
+    /// Generate a signing key of unlock tokens (to be set as `UNLOCK_SIGNING_KEY`),
+    /// writing its public key to a file, which game clients compile in.
+    GenUnlockKey {
+        /// Path of the public key file (32 raw bytes).
+        #[arg(long, short, default_value = "unlock.pub")]
+        output: PathBuf,
+    },
+    /// Print the current code of an account.
+    Token {
+        #[command(flatten)]
+        account: AccountArg,
+    },
+    /// Check if a code of an account is valid (exits with 1 if it isn't).
+    Verify {
+        /// The 6-digit code.
+        code: String,
+        #[command(flatten)]
+        account: AccountArg,
+    },
+    /// Print the otpauth URL and QR code of an account.
+    Qr {
+        #[command(flatten)]
+        account: AccountArg,
+        /// Print the otpauth URL only (e.g. to be piped into other tools).
+        #[arg(long, conflicts_with = "output")]
+        url_only: bool,
+        /// Write the QR code to a PNG or SVG file (by its extension) instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Minimum width and height (in pixels) of the image file.
+        #[arg(long, default_value_t = QrOptions::default().size, requires = "output")]
+        size: u32,
+        /// Error-correction level of the QR code.
+        #[arg(long, value_enum, default_value_t, requires = "output")]
+        ec_level: QrEcLevel,
+    },
+    /// Manage accounts in the file at env var `ACCOUNTS_PATH`.
+    ///
+    /// A running server doesn't see changes until it's restarted.
+    #[command(subcommand)]
+    Accounts(AccountsCommand),
+    /// Export enrolled accounts (with their secrets) as a bundle, which is
+    /// encrypted with the passphrase at env var `BUNDLE_PASSPHRASE`.
+    Export {
+        /// Format of the bundle.
+        #[arg(long, value_enum, default_value_t)]
+        format: BundleFormat,
+        /// Write the bundle to a file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Don't encrypt the bundle.
+        #[arg(long)]
+        plain: bool,
+    },
+    /// Import accounts from a bundle into the file at env var `ACCOUNTS_PATH`.
+    ///
+    /// Encrypted bundles are decrypted with the passphrase at env var `BUNDLE_PASSPHRASE`.
+    /// A running server doesn't see changes until it's restarted.
+    Import {
+        /// Path of the bundle (`-` for stdin).
+        path: PathBuf,
+        /// What to do with existing accounts which differ from the imported ones.
+        #[arg(long, value_enum, default_value_t)]
+        policy: ConflictPolicy,
+        /// Only print what would be changed.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Configuration utilities.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Audit log utilities.
+    #[command(subcommand)]
+    Audit(AuditCommand),
+}
+
+#[derive(Debug, clap::Args)]
+struct AccountArg {
+    /// Name of the account (`default` is the one set by `RAW_SECRET`).
+    #[arg(long, default_value = "default")]
+    account: String,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum AccountsCommand {
+    /// List accounts.
+    List,
+    /// Enroll an account with a random secret.
+    Add {
+        /// Name of the account.
+        name: String,
+        /// Issuer shown by authenticator apps (defaults to env var `OTPAUTH_ISSUER`).
+        #[arg(long)]
+        issuer: Option<String>,
+        /// Label shown by authenticator apps (defaults to the name of the account).
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Remove an account.
+    Remove {
+        /// Name of the account.
+        name: String,
+    },
+    /// Import TOTP accounts exported by authenticator apps (e.g. Google Authenticator).
+    Import {
+        /// A migration URI (`otpauth-migration://offline?data=...`), or a file
+        /// containing such URIs (one per line) or a PNG image of their QR codes.
+        source: String,
+    },
+    /// Export accounts as a migration URI, which authenticator apps can import.
+    Export {
+        /// Names of the accounts (every one by default).
+        names: Vec<String>,
+        /// Write the QR code to a PNG or SVG file (by its extension) instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum ConfigCommand {
+    /// Check if env vars have been set correctly.
+    Check,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum AuditCommand {
+    /// Verify the hash chain of an audit log file.
+    Verify {
+        /// Path of the audit log file (JSON Lines).
+        path: PathBuf,
+    },
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    use clap::Parser;
     setup_panic_hook();
 
-    if is_on_lambda() {
-        init_tracing_subscriber_lambda();
+    let command = match Cli::parse().command {
+        None => {
+            serve(is_on_lambda()).await;
+            return ExitCode::SUCCESS;
+        }
+        Some(Command::Serve) => {
+            serve(false).await;
+            return ExitCode::SUCCESS;
+        }
+        Some(Command::Lambda) => {
+            serve(true).await;
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => command,
+    };
+    // Logs of other subcommands go to stderr, leaving stdout to their output.
+    init_cli_logs(matches!(command, Command::Config(ConfigCommand::Check)));
+    let result = match command {
+        Command::GenSecret { bits, encoding } => {
+            totp_server::generate_secret(bits, encoding).map(print_line)
+        }
+        Command::GenUnlockKey { output } => gen_unlock_key(&output),
+        Command::Token { account } => totp_server::current_token(&account.account).map(print_line),
+        Command::Verify { code, account } => {
+            totp_server::verify_token(&account.account, &code).map(|()| print_line("OK"))
+        }
+        Command::Qr {
+            account,
+            output: Some(path),
+            size,
+            ec_level,
+            ..
+        } => write_qr_code(&account.account, &path, QrOptions { size, ec_level }),
+        Command::Qr {
+            account, url_only, ..
+        } => print_qr_code(&account.account, url_only),
+        Command::Accounts(command) => run_accounts_command(command),
+        Command::Export {
+            format,
+            output,
+            plain,
+        } => export_bundle(format, output.as_deref(), plain),
+        Command::Import {
+            path,
+            policy,
+            dry_run,
+        } => import_bundle(&path, policy, dry_run),
+        Command::Config(ConfigCommand::Check) => {
+            totp_server::env_var_check();
+            print_line("OK: env vars have been set correctly.");
+            Ok(())
+        }
+        Command::Audit(AuditCommand::Verify { path }) => verify_audit_log(&path),
+        Command::Serve | Command::Lambda => unreachable!("servers have been started"),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn serve(on_lambda: bool) {
+    totp_server::init_telemetry(on_lambda);
+    if on_lambda {
         totp_server::start_server_aws_lambda().await;
     } else {
-        init_tracing_subscriber();
         totp_server::start_server().await;
-        OTEL_SDK_PROVIDER.force_flush().unwrap_or_else(|e| {
-            panic!("failed to force_flush opentelemetry sdk providers. error: {e}")
-        });
+        totp_server::shutdown_telemetry();
+    }
+}
+
+/// Write logs to stderr, at the `info` level if `verbose` and otherwise at the `warn` level.
+fn init_cli_logs(verbose: bool) {
+    let level = if verbose { "info" } else { "warn" };
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| format!("{}={level}", totp_server::CRATE_NAME).into()),
+        )
+        .init();
+}
+
+#[expect(clippy::print_stdout)]
+fn print_line(line: impl std::fmt::Display) {
+    println!("{line}");
+}
+
+fn print_qr_code(account: &str, url_only: bool) -> totp_server::Result<()> {
+    let url = totp_server::get_otpauth_url(account)?;
+    if !url_only {
+        print_line(totp_server::render_qr_code(&url)?);
+    }
+    print_line(url);
+    Ok(())
+}
+
+fn write_qr_code(
+    account: &str,
+    path: &std::path::Path,
+    options: QrOptions,
+) -> totp_server::Result<()> {
+    let url = totp_server::get_otpauth_url(account)?;
+    write_qr_image(&url, path, options)
+}
+
+fn write_qr_image(
+    text: &str,
+    path: &std::path::Path,
+    options: QrOptions,
+) -> totp_server::Result<()> {
+    let format = QrFormat::from_path(path).ok_or_else(|| {
+        totp_server::Error::InvalidInput(format!("{} should end with .png or .svg", path.display()))
+    })?;
+    let image = totp_server::render_qr_image(text, format, options)?;
+    std::fs::write(path, image)?;
+    print_line(format_args!(
+        "QR code has been written to {}.",
+        path.display()
+    ));
+    Ok(())
+}
+
+fn gen_unlock_key(output: &std::path::Path) -> totp_server::Result<()> {
+    let (signing_key, public_key) = totp_server::generate_unlock_key();
+    std::fs::write(output, public_key)?;
+    print_line(format_args!("UNLOCK_SIGNING_KEY={signing_key}"));
+    print_line(format_args!(
+        "Public key has been written to {}.",
+        output.display()
+    ));
+    Ok(())
+}
+
+fn run_accounts_command(command: AccountsCommand) -> totp_server::Result<()> {
+    match command {
+        AccountsCommand::List => {
+            for account in totp_server::list_accounts() {
+                print_line(format_args!("{}\t{}", account.name, account.created_at));
+            }
+        }
+        AccountsCommand::Add {
+            name,
+            issuer,
+            label,
+        } => {
+            let profile = totp_server::OtpauthProfile { issuer, label };
+            let enrollment = totp_server::add_account(&name, profile)?;
+            print_line(totp_server::render_qr_code(&enrollment.otpauth_url)?);
+            print_line(format_args!("Secret (base32): {}", enrollment.secret));
+            print_line(enrollment.otpauth_url);
+        }
+        AccountsCommand::Remove { name } => {
+            totp_server::remove_account(&name)?;
+            print_line(format_args!("Account {name:?} has been removed."));
+        }
+        AccountsCommand::Import { source } => {
+            let report = totp_server::import_accounts(&read_migration_payload(&source)?)?;
+            for account in report.imported {
+                print_line(format_args!("Imported: {}", account.name));
+            }
+            for (name, reason) in report.skipped {
+                print_line(format_args!("Skipped: {name} ({reason})"));
+            }
+        }
+        AccountsCommand::Export { names, output } => {
+            let uri = totp_server::export_accounts(&names)?.to_uri();
+            if let Some(path) = output {
+                write_qr_image(&uri, &path, QrOptions::default())?;
+            } else {
+                print_line(totp_server::render_qr_code(&uri)?);
+                print_line(uri);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the passphrase of encrypted bundles from env var `BUNDLE_PASSPHRASE`.
+fn bundle_passphrase() -> totp_server::Result<Option<String>> {
+    match std::env::var(BUNDLE_PASSPHRASE) {
+        Ok(passphrase) if !passphrase.is_empty() => Ok(Some(passphrase)),
+        Ok(_) | Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(_)) => Err(totp_server::Error::InvalidInput(format!(
+            "{BUNDLE_PASSPHRASE} must be valid Unicode"
+        ))),
     }
 }
 
+fn export_bundle(
+    format: BundleFormat,
+    output: Option<&std::path::Path>,
+    plain: bool,
+) -> totp_server::Result<()> {
+    use std::io::Write;
+    let passphrase = if plain {
+        None
+    } else {
+        Some(bundle_passphrase()?.ok_or_else(|| {
+            totp_server::Error::InvalidInput(format!(
+                "{BUNDLE_PASSPHRASE} must be set, unless --plain is given"
+            ))
+        })?)
+    };
+    let bundle = totp_server::export_bundle(format, passphrase.as_deref())?;
+    if let Some(path) = output {
+        std::fs::write(path, bundle)?;
+        print_line(format_args!(
+            "Bundle has been written to {}.",
+            path.display()
+        ));
+    } else {
+        std::io::stdout().write_all(&bundle)?;
+    }
+    Ok(())
+}
+
+fn import_bundle(
+    path: &std::path::Path,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> totp_server::Result<()> {
+    use std::io::Read;
+    let bundle = if path.as_os_str() == "-" {
+        let mut bundle = Vec::new();
+        std::io::stdin().read_to_end(&mut bundle)?;
+        bundle
+    } else {
+        std::fs::read(path)?
+    };
+    let passphrase = bundle_passphrase()?;
+    let diff = totp_server::import_bundle(&bundle, passphrase.as_deref(), policy, dry_run)?;
+    for (change, names) in [
+        ("Added", diff.added),
+        ("Updated", diff.updated),
+        ("Unchanged", diff.unchanged),
+        ("Skipped", diff.skipped),
+    ] {
+        for name in names {
+            print_line(format_args!("{change}: {name}"));
+        }
+    }
+    if diff.dry_run {
+        print_line("Dry run: nothing has been changed.");
+    }
+    Ok(())
+}
+
+/// Read migration payloads from a URI or a file, merging them into one.
+fn read_migration_payload(source: &str) -> totp_server::Result<totp_server::MigrationPayload> {
+    let uris = if source.starts_with("otpauth-migration:") {
+        vec![source.to_owned()]
+    } else {
+        let content = std::fs::read(source)?;
+        match String::from_utf8(content) {
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(ToOwned::to_owned)
+                .collect(),
+            Err(e) => totp_server::decode_qr_image(e.as_bytes())?,
+        }
+    };
+    let mut merged = totp_server::MigrationPayload::default();
+    for uri in uris {
+        let payload = totp_server::MigrationPayload::from_uri(&uri)?;
+        merged.otp_parameters.extend(payload.otp_parameters);
+    }
+    Ok(merged)
+}
+
+fn verify_audit_log(path: &std::path::Path) -> totp_server::Result<()> {
+    let count = totp_server::verify_audit_log(path)?;
+    print_line(format_args!(
+        "OK: {count} records verified in {}.",
+        path.display()
+    ));
+    Ok(())
+}
+
 /// Call this function in `main()` to setup panic hook.
 fn setup_panic_hook() {
     use std::panic::{PanicHookInfo, set_hook};
@@ -62,126 +493,3 @@ fn setup_panic_hook() {
 fn is_on_lambda() -> bool {
     std::env::var("AWS_LAMBDA_FUNCTION_NAME").is_ok()
 }
-
-fn init_tracing_subscriber_lambda() {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or(format!("{}={}", totp_server::CRATE_NAME, LevelFilter::INFO).into()),
-        )
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_ansi(false) // There's decoding issue if ansi is enabled.
-                .without_time(), // Time info already exists in AWS CloudWatch Logs.
-        )
-        .init();
-}
-
-static OTEL_SDK_PROVIDER: LazyLock<OtelSdkProviders> = LazyLock::new(init_opentelemetry);
-
-fn init_opentelemetry() -> OtelSdkProviders {
-    use opentelemetry::KeyValue;
-    use opentelemetry_semantic_conventions as semcon;
-    let resource = opentelemetry_sdk::Resource::builder()
-        .with_attribute(KeyValue::new(
-            semcon::resource::SERVICE_NAME,
-            totp_server::CRATE_NAME,
-        ))
-        .with_attribute(KeyValue::new(
-            semcon::resource::SERVICE_VERSION,
-            totp_server::PKG_VERSION,
-        ))
-        .build();
-    init_propagator();
-    OtelSdkProviders {
-        logger: init_logger(&resource),
-        meter: init_meter(&resource),
-        tracer: init_tracer(&resource),
-    }
-}
-
-struct OtelSdkProviders {
-    logger: SdkLoggerProvider,
-    meter: SdkMeterProvider,
-    tracer: SdkTracerProvider,
-}
-
-impl OtelSdkProviders {
-    fn force_flush(&self) -> Result<(), OTelSdkError> {
-        self.logger.force_flush()?;
-        self.meter.force_flush()?;
-        self.tracer.force_flush()?;
-        Ok(())
-    }
-}
-
-fn init_logger(resource: &opentelemetry_sdk::Resource) -> SdkLoggerProvider {
-    let log_exporter = opentelemetry_otlp::LogExporter::builder()
-        .with_tonic()
-        .build()
-        .unwrap_or_else(|e| panic!("failed to build LogExporter. error: {e}"));
-    SdkLoggerProvider::builder()
-        .with_batch_exporter(log_exporter)
-        .with_resource(resource.clone())
-        .build()
-}
-
-fn init_meter(resource: &opentelemetry_sdk::Resource) -> SdkMeterProvider {
-    let exporter = opentelemetry_otlp::MetricExporter::builder()
-        .with_tonic()
-        .build()
-        .unwrap_or_else(|e| panic!("failed to build MetricExporter. error: {e}"));
-    let meter_provider = SdkMeterProvider::builder()
-        .with_periodic_exporter(exporter)
-        .with_resource(resource.clone())
-        .build();
-    opentelemetry::global::set_meter_provider(meter_provider.clone());
-    meter_provider
-}
-
-fn init_tracer(resource: &opentelemetry_sdk::Resource) -> SdkTracerProvider {
-    let span_exporter = opentelemetry_otlp::SpanExporter::builder()
-        .with_tonic()
-        .build()
-        .unwrap_or_else(|e| panic!("failed to build SpanExporter. error: {e}"));
-    let tracer_provider = SdkTracerProvider::builder()
-        .with_batch_exporter(span_exporter)
-        .with_resource(resource.clone())
-        .build();
-    opentelemetry::global::set_tracer_provider(tracer_provider.clone());
-    tracer_provider
-}
-
-fn init_propagator() {
-    use opentelemetry::propagation::{TextMapCompositePropagator, TextMapPropagator};
-    let propagators: Vec<Box<dyn TextMapPropagator + Send + Sync>> = vec![
-        Box::new(opentelemetry_sdk::propagation::TraceContextPropagator::new()),
-        Box::new(opentelemetry_sdk::propagation::BaggagePropagator::new()),
-    ];
-    let composite_propagator = TextMapCompositePropagator::new(propagators);
-    opentelemetry::global::set_text_map_propagator(composite_propagator);
-}
-
-fn init_tracing_subscriber() {
-    use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
-    use tracing_opentelemetry::MetricsLayer;
-
-    let otel_log_layer = OpenTelemetryTracingBridge::new(&OTEL_SDK_PROVIDER.logger);
-    let otel_metrics_layer = MetricsLayer::new(OTEL_SDK_PROVIDER.meter.clone());
-    let otel_trace_layer = {
-        use opentelemetry::trace::TracerProvider;
-        let tracer = OTEL_SDK_PROVIDER.tracer.tracer(totp_server::CRATE_NAME);
-        tracing_opentelemetry::layer().with_tracer(tracer)
-    };
-
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or(format!("{}={}", totp_server::CRATE_NAME, LevelFilter::INFO).into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .with(otel_log_layer)
-        .with(otel_metrics_layer)
-        .with(otel_trace_layer)
-        .init();
-}
@@ -0,0 +1,620 @@
+use crate::group::{TotpAlgorithm, TotpParams};
+use base64::Engine;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+/// Prefix of migration URIs, which are followed by `?data=<base64 protobuf>`.
+const URI_PREFIX: &str = "otpauth-migration://offline";
+
+/// Standard base64, whose padding is optional when decoding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Hash algorithms of one-time passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MigrationAlgorithm {
+    /// Unspecified, which authenticator apps treat as SHA-1.
+    #[default]
+    Unspecified,
+    /// SHA-1.
+    Sha1,
+    /// SHA-256.
+    Sha256,
+    /// SHA-512.
+    Sha512,
+    /// MD5.
+    Md5,
+    /// A value unknown to this version, which is kept as it is.
+    Other(u64),
+}
+
+/// Number of digits of one-time passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MigrationDigits {
+    /// Unspecified, which authenticator apps treat as 6 digits.
+    #[default]
+    Unspecified,
+    /// 6 digits.
+    Six,
+    /// 8 digits.
+    Eight,
+    /// A value unknown to this version, which is kept as it is.
+    Other(u64),
+}
+
+/// Types of one-time passwords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MigrationOtpType {
+    /// Unspecified, which authenticator apps treat as TOTP.
+    #[default]
+    Unspecified,
+    /// Counter-based (RFC 4226).
+    Hotp,
+    /// Time-based (RFC 6238).
+    Totp,
+    /// A value unknown to this version, which is kept as it is.
+    Other(u64),
+}
+
+/// Convert protobuf enum values from and to the enums above.
+macro_rules! proto_enum {
+    ($name:ident { $($value:literal => $variant:ident),* $(,)? }) => {
+        impl From<u64> for $name {
+            fn from(value: u64) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    value => $name::Other(value),
+                }
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Other(value) => value,
+                }
+            }
+        }
+    };
+}
+
+proto_enum!(MigrationAlgorithm { 0 => Unspecified, 1 => Sha1, 2 => Sha256, 3 => Sha512, 4 => Md5 });
+proto_enum!(MigrationDigits { 0 => Unspecified, 1 => Six, 2 => Eight });
+proto_enum!(MigrationOtpType { 0 => Unspecified, 1 => Hotp, 2 => Totp });
+
+/// An account in a migration payload (`MigrationPayload.OtpParameters`).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OtpParameters {
+    /// The raw secret.
+    pub secret: Vec<u8>,
+    /// The label, which is sometimes prefixed by `<issuer>:`.
+    pub name: String,
+    /// The issuer, which may be empty.
+    pub issuer: String,
+    /// Hash algorithm.
+    pub algorithm: MigrationAlgorithm,
+    /// Number of digits.
+    pub digits: MigrationDigits,
+    /// Counter- or time-based.
+    pub otp_type: MigrationOtpType,
+    /// The counter of HOTP accounts.
+    pub counter: i64,
+}
+
+impl OtpParameters {
+    /// Check if the account can be served, i.e. it's a TOTP account with
+    /// SHA-1, SHA-256 or SHA-512, 6 or 8 digits and a secret of at least 128 bits.
+    ///
+    /// # Errors
+    ///
+    /// Returns the reason if the account isn't supported.
+    pub fn check_supported(&self) -> Result<(), &'static str> {
+        use MigrationAlgorithm as A;
+        use MigrationDigits as D;
+        if self.otp_type != MigrationOtpType::Totp {
+            Err("only TOTP is supported")
+        } else if !matches!(
+            self.algorithm,
+            A::Unspecified | A::Sha1 | A::Sha256 | A::Sha512
+        ) {
+            Err("only SHA-1, SHA-256 and SHA-512 are supported")
+        } else if !matches!(self.digits, D::Unspecified | D::Six | D::Eight) {
+            Err("only 6 and 8 digits are supported")
+        } else if self.secret.len() < 16 {
+            Err("the secret is shorter than 128 bits")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Convert the parameters into an account, given that it's supported.
+    ///
+    /// The name of the account is the label (without the `<issuer>:` prefix),
+    /// whose invalid characters are replaced by `_`. The original label is
+    /// kept in the profile if it has been changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if the account isn't supported,
+    /// or [`crate::Error::AccountNameInvalid`] if the label is empty.
+    pub(crate) fn to_account(&self, created_at: u64) -> crate::Result<crate::store::Account> {
+        self.check_supported()
+            .map_err(|reason| invalid(format!("{:?}: {reason}", self.name)))?;
+        let (prefix, label) = match self.name.split_once(':') {
+            Some((prefix, label)) => (Some(prefix.trim()), label.trim()),
+            None => (None, self.name.trim()),
+        };
+        let name: String = label
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "-_.@".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(crate::store::MAX_NAME_LEN)
+            .collect();
+        crate::store::validate_name(&name)?;
+        let issuer = Some(self.issuer.trim())
+            .filter(|issuer| !issuer.is_empty())
+            .or(prefix)
+            .map(ToOwned::to_owned);
+        let label = (name != label).then(|| label.to_owned());
+        let totp = TotpParams {
+            algorithm: match self.algorithm {
+                MigrationAlgorithm::Sha256 => TotpAlgorithm::Sha256,
+                MigrationAlgorithm::Sha512 => TotpAlgorithm::Sha512,
+                _ => TotpAlgorithm::Sha1,
+            },
+            digits: if self.digits == MigrationDigits::Eight {
+                8
+            } else {
+                6
+            },
+            period: TotpParams::default().period,
+        };
+        let profile = crate::OtpauthProfile { issuer, label };
+        Ok(crate::store::Account {
+            totp,
+            ..crate::store::Account::new(&name, self.secret.clone(), created_at, profile)
+        })
+    }
+}
+
+impl TryFrom<&crate::store::Account> for OtpParameters {
+    type Error = crate::Error;
+
+    /// Export the account with its effective issuer and label.
+    ///
+    /// Payloads have no period, which authenticator apps take as 30 seconds,
+    /// nor 7 digits, thus accounts with either of them cannot be exported.
+    fn try_from(account: &crate::store::Account) -> crate::Result<Self> {
+        let (issuer, label) = crate::otpauth::OTPAUTH_CONFIG.issuer_and_label(account);
+        let digits = match account.totp.digits {
+            6 => MigrationDigits::Six,
+            8 => MigrationDigits::Eight,
+            _ => {
+                return Err(invalid(format!(
+                    "{:?}: only 6 and 8 digits can be exported",
+                    account.name
+                )));
+            }
+        };
+        if account.totp.period != TotpParams::default().period {
+            return Err(invalid(format!(
+                "{:?}: only a period of 30 seconds can be exported",
+                account.name
+            )));
+        }
+        Ok(OtpParameters {
+            secret: account.secret.clone(),
+            name: label.to_owned(),
+            issuer: issuer.to_owned(),
+            algorithm: match account.totp.algorithm {
+                TotpAlgorithm::Sha1 => MigrationAlgorithm::Sha1,
+                TotpAlgorithm::Sha256 => MigrationAlgorithm::Sha256,
+                TotpAlgorithm::Sha512 => MigrationAlgorithm::Sha512,
+            },
+            digits,
+            otp_type: MigrationOtpType::Totp,
+            counter: 0,
+        })
+    }
+}
+
+/// The payload of `otpauth-migration://` URIs, which authenticator apps
+/// (e.g. Google Authenticator) use to transfer accounts.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MigrationPayload {
+    /// The accounts.
+    pub otp_parameters: Vec<OtpParameters>,
+    /// Version of the payload format.
+    pub version: i32,
+    /// Number of payloads which the accounts are split into.
+    pub batch_size: i32,
+    /// Index of this payload in the batch.
+    pub batch_index: i32,
+    /// Identifier shared by payloads in the same batch.
+    pub batch_id: i32,
+}
+
+impl MigrationPayload {
+    /// Parse a migration URI, i.e. `otpauth-migration://offline?data=<base64 protobuf>`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if the URI or its payload is malformed.
+    pub fn from_uri(uri: &str) -> crate::Result<Self> {
+        let data = uri
+            .trim()
+            .strip_prefix(URI_PREFIX)
+            .and_then(|rest| rest.strip_prefix('?'))
+            .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("data=")))
+            .ok_or_else(|| invalid(format!("a URI like {URI_PREFIX}?data=... is expected")))?;
+        let data = urlencoding::decode(data).map_err(|e| invalid(e.to_string()))?;
+        // `+` may have been decoded as a space by tools which aren't aware of base64.
+        let data = data.replace(' ', "+");
+        let bytes = BASE64
+            .decode(data)
+            .map_err(|e| invalid(format!("invalid base64: {e}")))?;
+        Self::decode(&bytes)
+    }
+
+    /// Format the payload as a migration URI.
+    #[must_use]
+    pub fn to_uri(&self) -> String {
+        let data = BASE64.encode(self.encode());
+        format!("{URI_PREFIX}?data={}", urlencoding::encode(&data))
+    }
+
+    /// Decode the payload from protobuf.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if the bytes aren't a valid payload.
+    pub fn decode(bytes: &[u8]) -> crate::Result<Self> {
+        let mut payload = MigrationPayload::default();
+        let mut reader = Reader(bytes);
+        while let Some((field, wire_type)) = reader.key()? {
+            match (field, wire_type) {
+                (1, LEN) => payload
+                    .otp_parameters
+                    .push(decode_otp_parameters(reader.bytes()?)?),
+                (2, VARINT) => payload.version = reader.int32()?,
+                (3, VARINT) => payload.batch_size = reader.int32()?,
+                (4, VARINT) => payload.batch_index = reader.int32()?,
+                (5, VARINT) => payload.batch_id = reader.int32()?,
+                _ => reader.skip(wire_type)?,
+            }
+        }
+        Ok(payload)
+    }
+
+    /// Encode the payload into protobuf, omitting fields with default values.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for parameters in &self.otp_parameters {
+            put_bytes(&mut buf, 1, &encode_otp_parameters(parameters));
+        }
+        put_int(&mut buf, 2, i64::from(self.version));
+        put_int(&mut buf, 3, i64::from(self.batch_size));
+        put_int(&mut buf, 4, i64::from(self.batch_index));
+        put_int(&mut buf, 5, i64::from(self.batch_id));
+        buf
+    }
+}
+
+fn invalid(reason: impl std::fmt::Display) -> crate::Error {
+    crate::Error::InvalidInput(format!("migration payload: {reason}"))
+}
+
+fn decode_otp_parameters(bytes: &[u8]) -> crate::Result<OtpParameters> {
+    let mut parameters = OtpParameters::default();
+    let mut reader = Reader(bytes);
+    while let Some((field, wire_type)) = reader.key()? {
+        match (field, wire_type) {
+            (1, LEN) => parameters.secret = reader.bytes()?.to_vec(),
+            (2, LEN) => parameters.name = reader.string()?,
+            (3, LEN) => parameters.issuer = reader.string()?,
+            (4, VARINT) => parameters.algorithm = reader.varint()?.into(),
+            (5, VARINT) => parameters.digits = reader.varint()?.into(),
+            (6, VARINT) => parameters.otp_type = reader.varint()?.into(),
+            (7, VARINT) => parameters.counter = reader.int64()?,
+            _ => reader.skip(wire_type)?,
+        }
+    }
+    Ok(parameters)
+}
+
+fn encode_otp_parameters(parameters: &OtpParameters) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_bytes(&mut buf, 1, &parameters.secret);
+    put_bytes(&mut buf, 2, parameters.name.as_bytes());
+    put_bytes(&mut buf, 3, parameters.issuer.as_bytes());
+    put_varint_field(&mut buf, 4, parameters.algorithm.into());
+    put_varint_field(&mut buf, 5, parameters.digits.into());
+    put_varint_field(&mut buf, 6, parameters.otp_type.into());
+    put_int(&mut buf, 7, parameters.counter);
+    buf
+}
+
+/// Protobuf wire types.
+const VARINT: u8 = 0;
+const I64: u8 = 1;
+const LEN: u8 = 2;
+const I32: u8 = 5;
+
+/// Reader of protobuf messages.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> crate::Result<u64> {
+        let mut value = 0_u64;
+        for (i, &byte) in self.0.iter().enumerate().take(10) {
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.0 = &self.0[i + 1..];
+                return Ok(value);
+            }
+        }
+        Err(invalid("malformed varint"))
+    }
+
+    fn int64(&mut self) -> crate::Result<i64> {
+        Ok(i64::from_ne_bytes(self.varint()?.to_ne_bytes()))
+    }
+
+    /// Negative `int32` values are encoded as 10-byte varints, like `int64` ones.
+    fn int32(&mut self) -> crate::Result<i32> {
+        i32::try_from(self.int64()?).map_err(invalid)
+    }
+
+    /// Read the next field number and wire type, or `None` at the end.
+    fn key(&mut self) -> crate::Result<Option<(u64, u8)>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let wire_type = u8::try_from(key & 0x07).map_err(invalid)?;
+        Ok(Some((key >> 3, wire_type)))
+    }
+
+    fn take(&mut self, len: usize) -> crate::Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(invalid("unexpected end of data"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn bytes(&mut self) -> crate::Result<&'a [u8]> {
+        let len = usize::try_from(self.varint()?).map_err(invalid)?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> crate::Result<String> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(invalid)
+    }
+
+    /// Skip the value of an unknown field.
+    fn skip(&mut self, wire_type: u8) -> crate::Result<()> {
+        match wire_type {
+            VARINT => self.varint().map(drop),
+            I64 => self.take(8).map(drop),
+            LEN => self.bytes().map(drop),
+            I32 => self.take(4).map(drop),
+            _ => Err(invalid(format!("unsupported wire type {wire_type}"))),
+        }
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(u8::try_from(value & 0x7f).unwrap_or_default() | 0x80);
+        value >>= 7;
+    }
+    buf.push(u8::try_from(value).unwrap_or_default());
+}
+
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    if value != 0 {
+        put_varint(buf, (field << 3) | u64::from(VARINT));
+        put_varint(buf, value);
+    }
+}
+
+fn put_int(buf: &mut Vec<u8>, field: u64, value: i64) {
+    put_varint_field(buf, field, u64::from_ne_bytes(value.to_ne_bytes()));
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        put_varint(buf, (field << 3) | u64::from(LEN));
+        put_varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    /// An account exported by Google Authenticator.
+    const SINGLE: &str = "otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU6YWxpY2VAZ29vZ2xlLmNvbRoHRXhhbXBsZTAC";
+    /// A TOTP account with an issuer, one without, and an HOTP one,
+    /// in a batch whose id is negative.
+    const BATCH: &str = "otpauth-migration://offline?data=CkgKFDEyMzQ1Njc4OTAxMjM0NTY3ODkwEh1HYW1lIFN0dWRpbzphbGljZUBleGFtcGxlLmNvbRoLR2FtZSBTdHVkaW8gASgBMAIKHQoQAAECAwQFBgcICQoLDA0ODxIDYm9iIAEoATACCjEKFDEyMzQ1Njc4OTAxMjM0NTY3ODkwEglob3RwLXVzZXIaBkxlZ2FjeSABKAIwATgqEAEYASj50rT%2F%2F%2F%2F%2F%2F%2F8B";
+
+    #[test]
+    fn test_decode_single() {
+        let payload = MigrationPayload::from_uri(SINGLE).unwrap();
+        let expected = OtpParameters {
+            secret: b"Hello!\xde\xad\xbe\xef".to_vec(),
+            name: "Example:alice@google.com".to_owned(),
+            issuer: "Example".to_owned(),
+            otp_type: MigrationOtpType::Totp,
+            ..OtpParameters::default()
+        };
+        assert_eq!(payload.otp_parameters, [expected]);
+        assert_eq!(
+            payload.otp_parameters[0].check_supported(),
+            Err("the secret is shorter than 128 bits")
+        );
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let payload = MigrationPayload::from_uri(BATCH).unwrap();
+        assert_eq!(payload.version, 1);
+        assert_eq!(payload.batch_size, 1);
+        assert_eq!(payload.batch_index, 0);
+        assert_eq!(payload.batch_id, -1_234_567);
+        let [alice, bob, hotp] = payload.otp_parameters.as_slice() else {
+            panic!("3 accounts are expected");
+        };
+        assert_eq!(alice.secret, b"12345678901234567890");
+        assert_eq!(alice.name, "Game Studio:alice@example.com");
+        assert_eq!(alice.issuer, "Game Studio");
+        assert_eq!(alice.check_supported(), Ok(()));
+        assert_eq!(bob.issuer, "");
+        assert_eq!(bob.check_supported(), Ok(()));
+        assert_eq!(hotp.otp_type, MigrationOtpType::Hotp);
+        assert_eq!(hotp.digits, MigrationDigits::Eight);
+        assert_eq!(hotp.counter, 42);
+        assert!(hotp.check_supported().is_err());
+    }
+
+    #[rstest]
+    #[case(
+        "Game Studio:alice@example.com",
+        "Game Studio",
+        "alice@example.com",
+        Some("Game Studio"),
+        None
+    )]
+    #[case("Game Studio:alice", "", "alice", Some("Game Studio"), None)]
+    #[case("bob", "", "bob", None, None)]
+    #[case(
+        "Acme: Alice Smith",
+        "Acme",
+        "Alice_Smith",
+        Some("Acme"),
+        Some("Alice Smith")
+    )]
+    fn test_to_account(
+        #[case] name: &str,
+        #[case] issuer: &str,
+        #[case] expected_name: &str,
+        #[case] expected_issuer: Option<&str>,
+        #[case] expected_label: Option<&str>,
+    ) {
+        let parameters = OtpParameters {
+            secret: vec![7; 20],
+            name: name.to_owned(),
+            issuer: issuer.to_owned(),
+            otp_type: MigrationOtpType::Totp,
+            ..OtpParameters::default()
+        };
+        let account = parameters.to_account(0).unwrap();
+        assert_eq!(account.name, expected_name);
+        assert_eq!(account.profile.issuer.as_deref(), expected_issuer);
+        assert_eq!(account.profile.label.as_deref(), expected_label);
+
+        // Exported accounts keep their issuers and labels.
+        let exported = OtpParameters::try_from(&account).unwrap();
+        assert_eq!(exported.secret, parameters.secret);
+        assert_eq!(exported.issuer, expected_issuer.unwrap_or(crate::PKG_NAME));
+        assert_eq!(exported.to_account(0).unwrap().name, expected_name);
+    }
+
+    #[test]
+    fn test_to_account_err() {
+        let parameters = OtpParameters {
+            secret: vec![7; 20],
+            name: "Acme:".to_owned(),
+            otp_type: MigrationOtpType::Totp,
+            ..OtpParameters::default()
+        };
+        assert!(parameters.to_account(0).is_err());
+        let parameters = OtpParameters {
+            name: "alice".to_owned(),
+            otp_type: MigrationOtpType::Hotp,
+            ..parameters
+        };
+        assert!(parameters.to_account(0).is_err());
+    }
+
+    #[test]
+    fn test_to_account_params() {
+        let parameters = OtpParameters {
+            secret: vec![7; 20],
+            name: "alice".to_owned(),
+            algorithm: MigrationAlgorithm::Sha256,
+            digits: MigrationDigits::Eight,
+            otp_type: MigrationOtpType::Totp,
+            ..OtpParameters::default()
+        };
+        let mut account = parameters.to_account(0).unwrap();
+        assert_eq!(account.totp.algorithm, TotpAlgorithm::Sha256);
+        assert_eq!(account.totp.digits, 8);
+        let exported = OtpParameters::try_from(&account).unwrap();
+        assert_eq!(exported.algorithm, MigrationAlgorithm::Sha256);
+        assert_eq!(exported.digits, MigrationDigits::Eight);
+        // Payloads cannot carry other periods or 7 digits.
+        account.totp.period = 60;
+        assert!(OtpParameters::try_from(&account).is_err());
+        account.totp = TotpParams {
+            digits: 7,
+            ..TotpParams::default()
+        };
+        assert!(OtpParameters::try_from(&account).is_err());
+    }
+
+    #[rstest]
+    #[case(SINGLE)]
+    #[case(BATCH)]
+    fn test_round_trip(#[case] uri: &str) {
+        let payload = MigrationPayload::from_uri(uri).unwrap();
+        assert_eq!(payload.to_uri(), uri);
+        assert_eq!(
+            MigrationPayload::from_uri(&payload.to_uri()).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_unknown_values_are_kept() {
+        let payload = MigrationPayload {
+            otp_parameters: vec![OtpParameters {
+                secret: vec![1; 20],
+                name: "x".to_owned(),
+                algorithm: MigrationAlgorithm::Other(9),
+                ..OtpParameters::default()
+            }],
+            ..MigrationPayload::default()
+        };
+        let mut bytes = payload.encode();
+        // An unknown field (number 15, fixed64) is skipped.
+        bytes.extend_from_slice(&[0x79, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(MigrationPayload::decode(&bytes).unwrap(), payload);
+    }
+
+    #[rstest]
+    #[case("otpauth://totp/x?secret=AAAA")]
+    #[case("otpauth-migration://offline?data=!!!")]
+    #[case("otpauth-migration://offline?data=CgUKAw")]
+    #[case("otpauth-migration://offline?data=Cv8")]
+    fn test_invalid_uri(#[case] uri: &str) {
+        assert!(matches!(
+            MigrationPayload::from_uri(uri),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+}
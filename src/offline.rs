@@ -0,0 +1,234 @@
+//! Game clients verify unlock tokens with the public key of the server compiled in,
+//! e.g. `include_bytes!("unlock.pub")`, without reaching the server.
+//!
+//! This module only depends on `core`, `base64` and `ed25519-dalek` (all of which
+//! work without default features), so it can be copied into `no_std` clients as it is.
+//!
+//! Tokens are sent as base64url (without padding), whose decoded bytes are:
+//!
+//! | Field        | Size                                                    |
+//! | ------------ | ------------------------------------------------------- |
+//! | `version`    | 1 byte, which is [`UNLOCK_TOKEN_VERSION`]               |
+//! | `issued_at`  | 8 bytes, big-endian seconds since the Unix epoch        |
+//! | `expires_at` | 8 bytes, big-endian seconds since the Unix epoch        |
+//! | `account`    | 1 byte of length, then UTF-8                            |
+//! | `device_id`  | 1 byte of length, then UTF-8                            |
+//! | `features`   | 1 byte of count, then each as 1 byte of length + UTF-8  |
+//! | `signature`  | 64 bytes, Ed25519 of every byte above                   |
+//!
+//! [`UNLOCK_TOKEN_VERSION`]: crate::offline::UNLOCK_TOKEN_VERSION
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+/// Version of the layout of unlock tokens.
+pub const UNLOCK_TOKEN_VERSION: u8 = 1;
+/// Max length (in bytes) of the account, the device id and each feature.
+pub const MAX_FIELD_LEN: usize = 64;
+/// Max number of features of an unlock token.
+pub const MAX_FEATURES: usize = 16;
+/// Size of Ed25519 signatures.
+const SIGNATURE_LEN: usize = 64;
+/// Max size of decoded unlock tokens, e.g. of buffers passed to [`decode_unlock_token`].
+pub const MAX_UNLOCK_TOKEN_LEN: usize =
+    1 + 8 + 8 + (1 + MAX_FIELD_LEN) * (2 + MAX_FEATURES) + 1 + SIGNATURE_LEN;
+
+/// Why an unlock token has been rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnlockError {
+    /// The token isn't base64url, is truncated, or has fields out of range.
+    Malformed,
+    /// The token has a layout which this verifier doesn't know.
+    UnsupportedVersion(u8),
+    /// The public key isn't a valid Ed25519 key.
+    InvalidKey,
+    /// The token hasn't been signed by the server, or has been changed since.
+    InvalidSignature,
+    /// The token has been issued for another device.
+    WrongDevice,
+    /// The token has expired.
+    Expired,
+}
+
+impl core::fmt::Display for UnlockError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Malformed => f.write_str("malformed unlock token"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {version} of unlock token")
+            }
+            Self::InvalidKey => f.write_str("invalid public key"),
+            Self::InvalidSignature => f.write_str("invalid signature of unlock token"),
+            Self::WrongDevice => f.write_str("unlock token of another device"),
+            Self::Expired => f.write_str("unlock token has expired"),
+        }
+    }
+}
+
+impl core::error::Error for UnlockError {}
+
+/// Claims of an unlock token, which borrow the bytes of the token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnlockClaims<'a> {
+    /// When the TOTP code was verified (seconds since the Unix epoch).
+    pub issued_at: u64,
+    /// When the token expires (seconds since the Unix epoch).
+    pub expires_at: u64,
+    /// The account whose code was verified.
+    pub account: &'a str,
+    /// The device which the token is bound to.
+    pub device_id: &'a str,
+    features: Features<'a>,
+}
+
+impl<'a> UnlockClaims<'a> {
+    /// Iterate over the features which the token unlocks.
+    #[must_use]
+    pub fn features(&self) -> Features<'a> {
+        self.features.clone()
+    }
+
+    /// Check if the token unlocks the feature.
+    #[must_use]
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features().any(|unlocked| unlocked == feature)
+    }
+}
+
+/// Iterator over the features of an unlock token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Features<'a> {
+    bytes: &'a [u8],
+    remaining: u8,
+}
+
+impl<'a> Iterator for Features<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut reader = Reader(self.bytes);
+        // Features have been checked while parsing the token.
+        let feature = reader.str().ok()?;
+        self.bytes = reader.0;
+        Some(feature)
+    }
+}
+
+/// Cursor over the bytes of a token.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], UnlockError> {
+        if self.0.len() < len {
+            return Err(UnlockError::Malformed);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, UnlockError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64, UnlockError> {
+        let bytes = self.take(8)?;
+        let mut buf = [0; 8];
+        buf.copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    /// Read a string prefixed by its length, which is at most [`MAX_FIELD_LEN`].
+    fn str(&mut self) -> Result<&'a str, UnlockError> {
+        let len = usize::from(self.u8()?);
+        if len > MAX_FIELD_LEN {
+            return Err(UnlockError::Malformed);
+        }
+        core::str::from_utf8(self.take(len)?).map_err(|_| UnlockError::Malformed)
+    }
+}
+
+/// Decode a base64url-encoded unlock token into `buf`, returning the decoded bytes.
+///
+/// # Errors
+///
+/// Returns [`UnlockError::Malformed`] if the token isn't base64url or is too large.
+pub fn decode_unlock_token<'b>(
+    token: &str,
+    buf: &'b mut [u8; MAX_UNLOCK_TOKEN_LEN],
+) -> Result<&'b [u8], UnlockError> {
+    use base64::Engine;
+    let len = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode_slice(token.trim(), buf)
+        .map_err(|_| UnlockError::Malformed)?;
+    Ok(&buf[..len])
+}
+
+/// Verify a decoded unlock token (see [`decode_unlock_token`]) with the public key
+/// of the server, given the id of this device and the current Unix time.
+///
+/// # Errors
+///
+/// Returns Err if the token is malformed, forged, expired, or bound to another device.
+pub fn verify_unlock_token<'a>(
+    public_key: &[u8; 32],
+    token: &'a [u8],
+    device_id: &str,
+    now: u64,
+) -> Result<UnlockClaims<'a>, UnlockError> {
+    let payload_len = token
+        .len()
+        .checked_sub(SIGNATURE_LEN)
+        .ok_or(UnlockError::Malformed)?;
+    let (payload, signature) = token.split_at(payload_len);
+    let key = VerifyingKey::from_bytes(public_key).map_err(|_| UnlockError::InvalidKey)?;
+    let signature = Signature::from_slice(signature).map_err(|_| UnlockError::Malformed)?;
+    key.verify_strict(payload, &signature)
+        .map_err(|_| UnlockError::InvalidSignature)?;
+    let claims = parse_payload(payload)?;
+    if claims.device_id != device_id {
+        return Err(UnlockError::WrongDevice);
+    }
+    if claims.expires_at <= now {
+        return Err(UnlockError::Expired);
+    }
+    Ok(claims)
+}
+
+/// Parse the payload (i.e. the token without its signature), whose signature
+/// has been verified.
+fn parse_payload(payload: &[u8]) -> Result<UnlockClaims<'_>, UnlockError> {
+    let mut reader = Reader(payload);
+    let version = reader.u8()?;
+    if version != UNLOCK_TOKEN_VERSION {
+        return Err(UnlockError::UnsupportedVersion(version));
+    }
+    let issued_at = reader.u64()?;
+    let expires_at = reader.u64()?;
+    let account = reader.str()?;
+    let device_id = reader.str()?;
+    let count = reader.u8()?;
+    if usize::from(count) > MAX_FEATURES {
+        return Err(UnlockError::Malformed);
+    }
+    let features = Features {
+        bytes: reader.0,
+        remaining: count,
+    };
+    for _ in 0..count {
+        reader.str()?;
+    }
+    if !reader.0.is_empty() {
+        return Err(UnlockError::Malformed);
+    }
+    Ok(UnlockClaims {
+        issued_at,
+        expires_at,
+        account,
+        device_id,
+        features,
+    })
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Env var used to set the issuer shown by authenticator apps (defaults to the package name).
+const OTPAUTH_ISSUER: &str = "OTPAUTH_ISSUER";
+/// Env var used to set the label of the default account (defaults to `incognito`).
+const OTPAUTH_LABEL: &str = "OTPAUTH_LABEL";
+/// Env var used to set the URL of an image (logo) shown by some authenticator apps.
+const OTPAUTH_IMAGE: &str = "OTPAUTH_IMAGE";
+
+/// Label of the default account if env var `OTPAUTH_LABEL` hasn't been set.
+const DEFAULT_LABEL: &str = "incognito";
+/// Max length (in characters) of issuers and labels.
+const MAX_LEN: usize = 128;
+
+/// Issuer and label which override the deployment-wide ones for an account.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct OtpauthProfile {
+    /// Issuer shown by authenticator apps, e.g. the name of a team.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Game Studio")]
+    pub issuer: Option<String>,
+    /// Label shown by authenticator apps, which defaults to the name of the account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Alice <alice@example.com>")]
+    pub label: Option<String>,
+}
+
+impl OtpauthProfile {
+    /// Check if the issuer and the label are valid (see [`validate`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if either of them is invalid.
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        for (field, value) in [("issuer", &self.issuer), ("label", &self.label)] {
+            if let Some(value) = value {
+                validate(value)
+                    .map_err(|reason| crate::Error::InvalidInput(format!("{field} {reason}")))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if `value` can be used as an issuer or a label.
+///
+/// Values are 1 to 128 characters, without colons (which separate issuers
+/// from labels) or control characters. Others are percent-encoded in URLs.
+fn validate(value: &str) -> Result<(), &'static str> {
+    if value.trim().is_empty() || value.chars().count() > MAX_LEN {
+        Err("must be 1 to 128 characters")
+    } else if value.contains(':') {
+        Err("must not contain colons")
+    } else if value.chars().any(char::is_control) {
+        Err("must not contain control characters")
+    } else {
+        Ok(())
+    }
+}
+
+/// Deployment-wide settings of otpauth URLs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OtpauthConfig {
+    pub(crate) issuer: String,
+    /// Label of the default account.
+    pub(crate) label: String,
+    pub(crate) image: Option<String>,
+}
+
+/// Deployment-wide settings of otpauth URLs.
+///
+/// # Panics
+///
+/// Panics when `OTPAUTH_ISSUER` or `OTPAUTH_LABEL` is invalid,
+/// or when `OTPAUTH_IMAGE` isn't an HTTP(S) URL.
+pub(crate) static OTPAUTH_CONFIG: LazyLock<OtpauthConfig> = LazyLock::new(init_otpauth_config);
+
+fn init_otpauth_config() -> OtpauthConfig {
+    let var = |name: &str, default: &str| {
+        let value = std::env::var(name).unwrap_or_else(|_| default.to_owned());
+        if let Err(reason) = validate(&value) {
+            panic!("{name} {reason}!");
+        }
+        value
+    };
+    let issuer = var(OTPAUTH_ISSUER, crate::PKG_NAME);
+    let label = var(OTPAUTH_LABEL, DEFAULT_LABEL);
+    let image = std::env::var(OTPAUTH_IMAGE).ok();
+    if let Some(image) = &image {
+        let scheme = image.split_once("://").map(|(scheme, _)| scheme);
+        assert!(
+            matches!(scheme, Some("https" | "http")),
+            "{OTPAUTH_IMAGE} must be an HTTP(S) URL!"
+        );
+    }
+    OtpauthConfig {
+        issuer,
+        label,
+        image,
+    }
+}
+
+impl OtpauthConfig {
+    /// Get the issuer and the label of the account, whose profile overrides the deployment-wide one.
+    pub(crate) fn issuer_and_label<'a>(
+        &'a self,
+        account: &'a crate::store::Account,
+    ) -> (&'a str, &'a str) {
+        let issuer = account.profile.issuer.as_deref().unwrap_or(&self.issuer);
+        let label = account.profile.label.as_deref().unwrap_or_else(|| {
+            if account.name == crate::totp::DEFAULT_ACCOUNT {
+                &self.label
+            } else {
+                &account.name
+            }
+        });
+        (issuer, label)
+    }
+
+    /// Get the otpauth URL of the account, whose profile overrides the deployment-wide one.
+    ///
+    /// The URL looks like `otpauth://totp/<issuer>:<label>?secret=<base32>&issuer=<issuer>`,
+    /// with the algorithm, digits and period of the account unless they're the default ones,
+    /// followed by `&image=<url>` if an image has been set.
+    pub(crate) fn url(&self, account: &crate::store::Account) -> String {
+        let (issuer, label) = self.issuer_and_label(account);
+        let url = crate::totp::new_totp_with_label(
+            account.secret.clone(),
+            &account.totp,
+            crate::group::DEFAULT_SKEW,
+            issuer,
+            label,
+        )
+        .get_url();
+        match &self.image {
+            Some(image) => format!("{url}&image={}", urlencoding::encode(image)),
+            None => url,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use crate::store::Account;
+    use rstest::rstest;
+    use std::collections::HashMap;
+
+    fn account(name: &str, profile: OtpauthProfile) -> Account {
+        Account::new(name, b"12345678901234567890".to_vec(), 0, profile)
+    }
+
+    fn test_config() -> OtpauthConfig {
+        OtpauthConfig {
+            issuer: "totp-server".to_owned(),
+            label: "incognito".to_owned(),
+            image: None,
+        }
+    }
+
+    /// Parse the otpauth URL into its label (decoded) and query pairs.
+    fn parse(url: &str) -> (String, HashMap<String, String>) {
+        let url = reqwest::Url::parse(url).unwrap();
+        assert_eq!(url.scheme(), "otpauth");
+        assert_eq!(url.host_str(), Some("totp"));
+        let label = urlencoding::decode(&url.path()[1..]).unwrap().into_owned();
+        let query = url.query_pairs().into_owned().collect();
+        (label, query)
+    }
+
+    #[rstest]
+    #[case("default", None, None, "totp-server:incognito", "totp-server")]
+    #[case("alice", None, None, "totp-server:alice", "totp-server")]
+    #[case("alice", Some("Game Studio"), None, "Game Studio:alice", "Game Studio")]
+    #[case(
+        "bob",
+        None,
+        Some("Bob <bob@example.com>"),
+        "totp-server:Bob <bob@example.com>",
+        "totp-server"
+    )]
+    #[case(
+        "bob",
+        Some("R&D / QA?"),
+        Some("bob#1 100% +"),
+        "R&D / QA?:bob#1 100% +",
+        "R&D / QA?"
+    )]
+    #[case(
+        "carol",
+        Some("開発チーム"),
+        Some("Кэрол"),
+        "開発チーム:Кэрол",
+        "開発チーム"
+    )]
+    fn test_otpauth_url(
+        #[case] name: &str,
+        #[case] issuer: Option<&str>,
+        #[case] label: Option<&str>,
+        #[case] expected_label: &str,
+        #[case] expected_issuer: &str,
+    ) {
+        let profile = OtpauthProfile {
+            issuer: issuer.map(ToOwned::to_owned),
+            label: label.map(ToOwned::to_owned),
+        };
+        let url = test_config().url(&account(name, profile));
+        let (label, query) = parse(&url);
+        assert_eq!(label, expected_label);
+        assert_eq!(query["issuer"], expected_issuer);
+        assert_eq!(query["secret"], "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert!(!query.contains_key("image"));
+        // Every reserved character is percent-encoded.
+        assert!(!url.contains(' '));
+        assert_eq!(url.matches('?').count(), 1);
+        assert!(!url.contains('#'));
+    }
+
+    #[test]
+    fn test_otpauth_url_image() {
+        let config = OtpauthConfig {
+            image: Some("https://example.com/logo.png?size=64&v=2".to_owned()),
+            ..test_config()
+        };
+        let url = config.url(&account("alice", OtpauthProfile::default()));
+        let (_, query) = parse(&url);
+        assert_eq!(query["image"], "https://example.com/logo.png?size=64&v=2");
+        assert!(!query.contains_key("v"));
+    }
+
+    #[rstest]
+    #[case(Some("a:b"), None)]
+    #[case(None, Some(""))]
+    #[case(None, Some(" "))]
+    #[case(None, Some("line\nbreak"))]
+    #[case(Some(&"x".repeat(129)[..]), None)]
+    fn test_otpauth_profile_invalid(#[case] issuer: Option<&str>, #[case] label: Option<&str>) {
+        let profile = OtpauthProfile {
+            issuer: issuer.map(ToOwned::to_owned),
+            label: label.map(ToOwned::to_owned),
+        };
+        assert!(matches!(
+            profile.validate(),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_otpauth_config_default() {
+        assert!(std::env::var(OTPAUTH_ISSUER).is_err());
+        assert_eq!(*OTPAUTH_CONFIG, test_config());
+    }
+
+    #[rstest]
+    #[case(OTPAUTH_ISSUER, "a:b")]
+    #[case(OTPAUTH_LABEL, "")]
+    #[case(OTPAUTH_IMAGE, "data:image/png;base64,AAAA")]
+    #[should_panic(expected = "OTPAUTH_")]
+    fn test_otpauth_config_var_panic(#[case] name: &str, #[case] value: &str) {
+        unsafe { std::env::set_var(name, value) }
+        let _ = &*OTPAUTH_CONFIG;
+    }
+}
@@ -0,0 +1,237 @@
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+
+/// Default width and height (in pixels) of QR code images.
+pub(crate) const DEFAULT_QR_SIZE: u32 = 256;
+/// Minimum width and height (in pixels) of QR code images.
+const MIN_QR_SIZE: u32 = 64;
+/// Maximum width and height (in pixels) of QR code images.
+const MAX_QR_SIZE: u32 = 2048;
+
+/// Image formats of QR codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QrFormat {
+    /// PNG image.
+    Png,
+    /// SVG image.
+    Svg,
+}
+
+impl QrFormat {
+    /// Guess the format by the extension of `path` (case-insensitive).
+    #[must_use]
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(QrFormat::Png),
+            "svg" => Some(QrFormat::Svg),
+            _ => None,
+        }
+    }
+
+    /// The media type of images in this format.
+    pub(crate) const fn content_type(self) -> &'static str {
+        match self {
+            QrFormat::Png => "image/png",
+            QrFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// Error-correction levels of QR codes, i.e. how much of the code can be
+/// damaged (or covered) while it's still readable.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum, utoipa::ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum QrEcLevel {
+    /// About 7% can be restored.
+    #[serde(alias = "L")]
+    L,
+    /// About 15% can be restored.
+    #[default]
+    #[serde(alias = "M")]
+    M,
+    /// About 25% can be restored.
+    #[serde(alias = "Q")]
+    Q,
+    /// About 30% can be restored.
+    #[serde(alias = "H")]
+    H,
+}
+
+impl From<QrEcLevel> for EcLevel {
+    fn from(level: QrEcLevel) -> Self {
+        match level {
+            QrEcLevel::L => EcLevel::L,
+            QrEcLevel::M => EcLevel::M,
+            QrEcLevel::Q => EcLevel::Q,
+            QrEcLevel::H => EcLevel::H,
+        }
+    }
+}
+
+/// Options of QR code images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QrOptions {
+    /// Minimum width and height in pixels (64 to 2048), which is rounded up
+    /// to fit the modules of the code.
+    pub size: u32,
+    /// Error-correction level.
+    pub ec_level: QrEcLevel,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            size: DEFAULT_QR_SIZE,
+            ec_level: QrEcLevel::default(),
+        }
+    }
+}
+
+fn new_qr_code(text: &str, ec_level: QrEcLevel) -> crate::Result<QrCode> {
+    QrCode::with_error_correction_level(text, ec_level.into())
+        .map_err(|e| crate::Error::InvalidInput(e.to_string()))
+}
+
+/// Render the QR code of `text` with Unicode block characters, which can be printed in terminals.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if the text is too long for a QR code.
+pub fn render_qr_code(text: &str) -> crate::Result<String> {
+    use qrcode::render::unicode;
+
+    let image = new_qr_code(text, QrEcLevel::default())?
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build();
+    Ok(image)
+}
+
+/// Render the QR code of `text` as an image file in the given format.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if the size is out of range,
+/// or if the text is too long for a QR code.
+pub fn render_qr_image(text: &str, format: QrFormat, options: QrOptions) -> crate::Result<Vec<u8>> {
+    use qrcode::render::svg;
+
+    if !(MIN_QR_SIZE..=MAX_QR_SIZE).contains(&options.size) {
+        return Err(crate::Error::InvalidInput(format!(
+            "size must be between {MIN_QR_SIZE} and {MAX_QR_SIZE}"
+        )));
+    }
+    let code = new_qr_code(text, options.ec_level)?;
+    let size = options.size;
+    match format {
+        QrFormat::Png => {
+            let image = code
+                .render::<image::Luma<u8>>()
+                .min_dimensions(size, size)
+                .build();
+            let mut bytes = std::io::Cursor::new(Vec::new());
+            image
+                .write_to(&mut bytes, image::ImageFormat::Png)
+                .map_err(std::io::Error::other)?;
+            Ok(bytes.into_inner())
+        }
+        QrFormat::Svg => {
+            let image = code
+                .render::<svg::Color>()
+                .min_dimensions(size, size)
+                .build();
+            Ok(image.into_bytes())
+        }
+    }
+}
+
+/// Decode every QR code found in a PNG image, e.g. a screenshot of an authenticator app.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if the image cannot be decoded,
+/// or if no QR code can be read in it.
+pub fn decode_qr_image(png: &[u8]) -> crate::Result<Vec<String>> {
+    let image = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+        .map_err(|e| crate::Error::InvalidInput(format!("invalid PNG image: {e}")))?;
+    let mut prepared = rqrr::PreparedImage::prepare(image.to_luma8());
+    let texts: Vec<String> = prepared
+        .detect_grids()
+        .iter()
+        .filter_map(|grid| grid.decode().ok())
+        .map(|(_, text)| text)
+        .collect();
+    if texts.is_empty() {
+        return Err(crate::Error::InvalidInput(
+            "no QR code is found in the image".to_owned(),
+        ));
+    }
+    Ok(texts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const URL: &str =
+        "otpauth://totp/totp-server:default?secret=GEZDGNBVGY3TQOJQ&issuer=totp-server";
+
+    #[test]
+    fn test_render_png() {
+        let bytes = render_qr_image(URL, QrFormat::Png, QrOptions::default()).unwrap();
+        let image = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).unwrap();
+        assert!(image.width() >= DEFAULT_QR_SIZE);
+        assert_eq!(image.width(), image.height());
+    }
+
+    #[test]
+    fn test_render_svg() {
+        let options = QrOptions {
+            size: 512,
+            ec_level: QrEcLevel::H,
+        };
+        let bytes = render_qr_image(URL, QrFormat::Svg, options).unwrap();
+        let svg = String::from_utf8(bytes).unwrap();
+        assert!(svg.contains("<svg"));
+        // A higher error-correction level needs more modules.
+        let low = render_qr_image(URL, QrFormat::Svg, QrOptions::default()).unwrap();
+        assert_ne!(svg.into_bytes(), low);
+    }
+
+    #[test]
+    fn test_decode_png() {
+        let bytes = render_qr_image(URL, QrFormat::Png, QrOptions::default()).unwrap();
+        assert_eq!(decode_qr_image(&bytes).unwrap(), [URL]);
+        let blank = image::GrayImage::from_pixel(64, 64, image::Luma([255]));
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        blank.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        assert!(decode_qr_image(bytes.get_ref()).is_err());
+        assert!(decode_qr_image(b"not an image").is_err());
+    }
+
+    #[rstest]
+    #[case(MIN_QR_SIZE - 1)]
+    #[case(MAX_QR_SIZE + 1)]
+    fn test_render_size_err(#[case] size: u32) {
+        let options = QrOptions {
+            size,
+            ..QrOptions::default()
+        };
+        let err = render_qr_image(URL, QrFormat::Png, options).unwrap_err();
+        assert!(matches!(err, crate::Error::InvalidInput(_)));
+    }
+
+    #[rstest]
+    #[case("qr.png", Some(QrFormat::Png))]
+    #[case("dir/QR.SVG", Some(QrFormat::Svg))]
+    #[case("qr.jpg", None)]
+    #[case("qr", None)]
+    fn test_format_from_path(#[case] path: &str, #[case] expected: Option<QrFormat>) {
+        assert_eq!(QrFormat::from_path(path.as_ref()), expected);
+    }
+}
@@ -0,0 +1,512 @@
+use axum::http::Request;
+use axum::routing::MethodRouter;
+use governor::clock::{Clock, DefaultClock};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tower_governor::GovernorError;
+use tower_governor::key_extractor::KeyExtractor;
+
+/// Env var used to define named rate-limit policies.
+///
+/// Policies are separated by `;`, and each one is defined as
+/// `name:interval=30s,burst=25,key=ip`, where `key` is one of `ip`, `account`
+/// and `api-key`. Omitted fields fall back to those of the `default` policy.
+const RATE_LIMIT_POLICIES: &str = "RATE_LIMIT_POLICIES";
+/// Env var used to attach policies to routes, e.g. `/=strict,/v1/verify=default`.
+///
+/// Routes that aren't listed use the `default` policy,
+/// and the policy `none` exempts a route from rate limiting.
+const RATE_LIMIT_ROUTES: &str = "RATE_LIMIT_ROUTES";
+
+/// Name of the policy which is used when a route isn't listed in `RATE_LIMIT_ROUTES`.
+const DEFAULT_POLICY: &str = "default";
+/// Name of the policy which exempts a route from rate limiting.
+const NO_POLICY: &str = "none";
+
+/// Header of the account, which is used if the body doesn't name one.
+pub(crate) const ACCOUNT_HEADER: &str = "x-totp-account";
+
+/// Routes which verify codes, and so can be limited by [`RateLimitKey::Account`].
+const ACCOUNT_ROUTES: &[&str] = &[
+    "/",
+    "/v1/verify",
+    "/v1/sessions",
+    "/v1/sessions/step-up",
+    "/v1/groups/{group}/verify",
+    "/v1/approvals/{id}",
+    "/v1/challenges/{id}",
+    "/v1/unlocks",
+];
+
+/// What requests are grouped by when counting against a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RateLimitKey {
+    /// The client IP (see [`crate::client::client_ip`]).
+    Ip,
+    /// The account whose code is verified (see [`KeyedLimit`]).
+    Account,
+    /// The authenticated key of the caller, or the client IP if it's unauthenticated.
+    ApiKey,
+}
+
+impl std::str::FromStr for RateLimitKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(Self::Ip),
+            "account" => Ok(Self::Account),
+            "api-key" => Ok(Self::ApiKey),
+            other => Err(format!(
+                "rate limit key must be one of ip, account, api-key (the given one is {other:?})"
+            )),
+        }
+    }
+}
+
+/// A named rate-limit policy.
+///
+/// Each key may send `burst` requests at once, and one more request
+/// is allowed after every `interval`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RateLimitPolicy {
+    pub(crate) interval: Duration,
+    pub(crate) burst: u32,
+    pub(crate) key: RateLimitKey,
+}
+
+impl RateLimitPolicy {
+    /// Parse fields like `interval=30s,burst=25,key=ip` on top of `base`.
+    fn parse_fields(fields: &str, base: &Self) -> Result<Self, String> {
+        let mut policy = base.clone();
+        for field in fields.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("field {field:?} should be like key=value"))?;
+            match key.trim() {
+                "interval" => policy.interval = parse_duration(value.trim())?,
+                "burst" => {
+                    policy.burst = value
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|&v| v != 0)
+                        .ok_or_else(|| format!("burst must be a positive integer ({value:?})"))?;
+                }
+                "key" => policy.key = value.trim().parse()?,
+                other => return Err(format!("unknown field {other:?}")),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// Parse durations like `500ms`, `30s`, `1m` or `30` (seconds).
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid interval {value:?}");
+    let (number, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, "s"), |i| value.split_at(i));
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "ms" => Duration::from_millis(number),
+        "s" => Duration::from_secs(number),
+        "m" => Duration::from_mins(number),
+        _ => return Err(invalid()),
+    };
+    if duration.is_zero() {
+        return Err(invalid());
+    }
+    Ok(duration)
+}
+
+/// Named rate-limit policies, which always contain the `default` policy.
+///
+/// # Panics
+///
+/// Panics when env var `RATE_LIMIT_POLICIES` cannot be parsed.
+pub(crate) static POLICIES: LazyLock<HashMap<String, RateLimitPolicy>> =
+    LazyLock::new(init_policies);
+
+fn init_policies() -> HashMap<String, RateLimitPolicy> {
+    let default_policy = RateLimitPolicy {
+        interval: Duration::from_secs(30),
+        burst: *crate::RATE_LIMIT,
+        key: RateLimitKey::Ip,
+    };
+    let mut policies = HashMap::from([(DEFAULT_POLICY.to_owned(), default_policy)]);
+    let Ok(value) = std::env::var(RATE_LIMIT_POLICIES) else {
+        return policies;
+    };
+    for definition in value.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, fields) = definition.split_once(':').unwrap_or((definition, ""));
+        let name = name.trim();
+        assert!(
+            name != NO_POLICY,
+            "{RATE_LIMIT_POLICIES}: policy name {NO_POLICY:?} is reserved."
+        );
+        let policy = RateLimitPolicy::parse_fields(fields, &policies[DEFAULT_POLICY])
+            .unwrap_or_else(|e| panic!("{RATE_LIMIT_POLICIES}: policy {name:?}: {e}."));
+        policies.insert(name.to_owned(), policy);
+    }
+    policies
+}
+
+/// Names of the policies attached to routes.
+///
+/// # Panics
+///
+/// Panics when env var `RATE_LIMIT_ROUTES` cannot be parsed,
+/// or when it refers to an undefined policy.
+pub(crate) static ROUTES: LazyLock<HashMap<String, String>> = LazyLock::new(init_routes);
+
+fn init_routes() -> HashMap<String, String> {
+    let Ok(value) = std::env::var(RATE_LIMIT_ROUTES) else {
+        return HashMap::new();
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|item| {
+            let (route, policy) = item.split_once('=').unwrap_or_else(|| {
+                panic!("{RATE_LIMIT_ROUTES}: {item:?} should be like /route=policy.")
+            });
+            let (route, policy) = (route.trim(), policy.trim());
+            assert!(
+                policy == NO_POLICY || POLICIES.contains_key(policy),
+                "{RATE_LIMIT_ROUTES}: policy {policy:?} of route {route:?} isn't defined."
+            );
+            (route.to_owned(), policy.to_owned())
+        })
+        .collect()
+}
+
+/// Get the policy attached to the given route, or `None` if it's exempt.
+pub(crate) fn policy_for(route: &str) -> Option<&'static RateLimitPolicy> {
+    let name = ROUTES.get(route).map_or(DEFAULT_POLICY, String::as_str);
+    POLICIES.get(name)
+}
+
+/// Get the key which requests to `route` are grouped by under `policy`.
+///
+/// Routes which don't verify codes have no account to be limited by,
+/// so they are limited by client IP instead.
+fn key_for(route: &str, policy: &RateLimitPolicy) -> RateLimitKey {
+    if policy.key == RateLimitKey::Account && !ACCOUNT_ROUTES.contains(&route) {
+        RateLimitKey::Ip
+    } else {
+        policy.key
+    }
+}
+
+/// Get the key of the client IP of `req`, e.g. `ip:192.0.2.1`.
+fn ip_key<T>(req: &Request<T>) -> Option<String> {
+    crate::client::client_ip(
+        req.headers(),
+        req.extensions(),
+        &crate::TRUSTED_PROXIES,
+        *crate::TRUSTED_PROXY_HEADER,
+    )
+    .map(|ip| format!("ip:{ip}"))
+}
+
+/// A [`KeyExtractor`] which groups requests by [`RateLimitKey`].
+///
+/// It must run after [`crate::authenticate`], so that [`RateLimitKey::ApiKey`]
+/// is keyed on the authenticated [`crate::Caller`] rather than on the
+/// credentials the client claims.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PolicyKeyExtractor(pub(crate) RateLimitKey);
+
+impl KeyExtractor for PolicyKeyExtractor {
+    type Key = String;
+
+    fn extract<T>(&self, req: &Request<T>) -> Result<Self::Key, GovernorError> {
+        let ip = || ip_key(req);
+        let key = match self.0 {
+            // Accounts are limited once they are known (see `KeyedLimit`).
+            RateLimitKey::Ip | RateLimitKey::Account => ip(),
+            RateLimitKey::ApiKey => req
+                .extensions()
+                .get::<crate::Caller>()
+                .and_then(|caller| caller.key_id.as_deref())
+                .map_or_else(ip, |key_id| Some(format!("key:{key_id}"))),
+        };
+        key.ok_or(GovernorError::UnableToExtractKey)
+    }
+}
+
+/// A rate limit which is checked by keys known in code rather than by a layer,
+/// e.g. by the account which [`crate::totp::verify`] checks once the account
+/// of the request is known, since it may be named by the body.
+#[derive(Clone)]
+pub(crate) struct KeyedLimit {
+    limiter: Arc<governor::DefaultKeyedRateLimiter<String>>,
+    burst: u32,
+}
+
+impl std::fmt::Debug for KeyedLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyedLimit")
+            .field("burst", &self.burst)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyedLimit {
+    fn new(policy: &RateLimitPolicy) -> Self {
+        let burst = NonZeroU32::new(policy.burst).expect("burst is a positive integer");
+        let quota = governor::Quota::with_period(policy.interval)
+            .expect("interval isn't zero")
+            .allow_burst(burst);
+        KeyedLimit {
+            limiter: Arc::new(governor::RateLimiter::keyed(quota)),
+            burst: policy.burst,
+        }
+    }
+
+    /// Clean up the storage of the limit in a separate background task.
+    fn clean_up_in_background(&self) {
+        let limiter = self.limiter.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_mins(1)).await;
+                limiter.retain_recent();
+            }
+        });
+    }
+
+    /// Count a request against the limit of `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::TooManyRequests`] if the limit has been reached.
+    pub(crate) fn check(&self, key: &str) -> crate::Result<()> {
+        self.limiter
+            .check_key(&key.to_owned())
+            .map_err(|not_until| crate::Error::TooManyRequests {
+                limit: self.burst,
+                retry_after: not_until
+                    .wait_time_from(DefaultClock::default().now())
+                    .as_secs(),
+            })
+    }
+}
+
+/// Rate limits of routes by client IP, which are checked before requests are
+/// authenticated, so that requests failing authentication are limited as well.
+///
+/// Routes are limited by their policy if it's keyed by IP, and otherwise
+/// by the interval and burst of the `default` policy.
+#[derive(Debug, Clone)]
+pub(crate) struct IpLimits(Arc<HashMap<String, KeyedLimit>>);
+
+impl IpLimits {
+    /// Create the limits of `routes`, leaving out those exempt from rate limiting.
+    pub(crate) fn new<'a>(routes: impl IntoIterator<Item = &'a str>) -> Self {
+        let limits = routes
+            .into_iter()
+            .filter_map(|route| {
+                let policy = policy_for(route)?;
+                let policy = match key_for(route, policy) {
+                    RateLimitKey::Ip => policy,
+                    RateLimitKey::Account | RateLimitKey::ApiKey => &POLICIES[DEFAULT_POLICY],
+                };
+                let limit = KeyedLimit::new(policy);
+                limit.clean_up_in_background();
+                Some((route.to_owned(), limit))
+            })
+            .collect();
+        IpLimits(Arc::new(limits))
+    }
+}
+
+/// Middleware which applies [`IpLimits`] to the matched route.
+///
+/// It must be layered outside [`crate::authenticate`].
+pub(crate) async fn limit_by_ip(
+    axum::extract::State(limits): axum::extract::State<IpLimits>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::response::IntoResponse;
+
+    let limit = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .and_then(|route| limits.0.get(route.as_str()));
+    if let Some(limit) = limit {
+        let Some(key) = ip_key(&request) else {
+            let msg = format!("Error: {}", GovernorError::UnableToExtractKey);
+            tracing::error!("{msg}");
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg).into_response();
+        };
+        if let Err(e) = limit.check(&key) {
+            return e.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Apply the rate-limit policy attached to `route` (see [`policy_for`])
+/// to authenticated requests.
+///
+/// Policies keyed by IP are left to [`IpLimits`], and policies keyed by account
+/// are passed to handlers as a [`KeyedLimit`].
+/// Rejections are converted into [`crate::Error::TooManyRequests`].
+pub(crate) fn rate_limited(route: &str, method_router: MethodRouter) -> MethodRouter {
+    use axum::response::IntoResponse;
+
+    let Some(policy) = policy_for(route) else {
+        return method_router;
+    };
+    match key_for(route, policy) {
+        RateLimitKey::Ip => return method_router,
+        RateLimitKey::Account => {
+            let account_limit = KeyedLimit::new(policy);
+            account_limit.clean_up_in_background();
+            return method_router.layer(axum::Extension(account_limit));
+        }
+        RateLimitKey::ApiKey => {}
+    }
+    let governor_conf = tower_governor::governor::GovernorConfigBuilder::default()
+        .period(policy.interval)
+        .burst_size(policy.burst)
+        .key_extractor(PolicyKeyExtractor(policy.key))
+        .finish()
+        .expect("Failed to configure tower_governor.");
+
+    let governor_limiter = governor_conf.limiter().clone();
+    let interval = Duration::from_mins(1);
+    // A separate background task to clean up.
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            tracing::trace!("Rate limiting storage size: {}.", governor_limiter.len());
+            governor_limiter.retain_recent();
+        }
+    });
+
+    let limit = policy.burst;
+    let layer = tower_governor::GovernorLayer::new(governor_conf).error_handler(move |e| match e {
+        GovernorError::TooManyRequests { wait_time, .. } => crate::Error::TooManyRequests {
+            limit,
+            retry_after: wait_time,
+        }
+        .into_response(),
+        e => {
+            let msg = format!("Error: {e}");
+            tracing::error!("{msg}");
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg).into_response()
+        }
+    });
+    method_router.layer(layer)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_policies_default() {
+        assert!(std::env::var(RATE_LIMIT_POLICIES).is_err());
+        let default_policy = &POLICIES[DEFAULT_POLICY];
+        assert_eq!(default_policy.interval, Duration::from_secs(30));
+        assert_eq!(default_policy.burst, *crate::RATE_LIMIT);
+        assert_eq!(default_policy.key, RateLimitKey::Ip);
+        assert_eq!(policy_for("/"), Some(default_policy));
+    }
+
+    #[test]
+    fn test_policies_var() {
+        unsafe {
+            std::env::set_var(
+                RATE_LIMIT_POLICIES,
+                "default:burst=10; strict:interval=1m,burst=3,key=account; keys:key=api-key,interval=500ms",
+            );
+            std::env::set_var(RATE_LIMIT_ROUTES, "/=strict, /other=none");
+        }
+        let strict = RateLimitPolicy {
+            interval: Duration::from_mins(1),
+            burst: 3,
+            key: RateLimitKey::Account,
+        };
+        let keys = RateLimitPolicy {
+            interval: Duration::from_millis(500),
+            burst: 10,
+            key: RateLimitKey::ApiKey,
+        };
+        assert_eq!(POLICIES["default"].burst, 10);
+        assert_eq!(POLICIES["strict"], strict);
+        assert_eq!(POLICIES["keys"], keys);
+        assert_eq!(policy_for("/"), Some(&strict));
+        assert_eq!(policy_for("/other"), None);
+        assert_eq!(policy_for("/unlisted"), Some(&POLICIES["default"]));
+    }
+
+    #[rstest]
+    #[case("strict:burst=0")]
+    #[case("strict:interval=0s")]
+    #[case("strict:interval=1h")]
+    #[case("strict:key=user")]
+    #[case("strict:size=3")]
+    #[case("none:burst=3")]
+    #[should_panic(expected = "RATE_LIMIT_POLICIES")]
+    fn test_policies_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(RATE_LIMIT_POLICIES, value) }
+        let _ = &*POLICIES;
+    }
+
+    #[test]
+    fn test_account_limit() {
+        let policy = RateLimitPolicy {
+            interval: Duration::from_mins(1),
+            burst: 2,
+            key: RateLimitKey::Account,
+        };
+        let limit = KeyedLimit::new(&policy);
+        for _ in 0..2 {
+            limit.check("alice").unwrap();
+        }
+        assert!(matches!(
+            limit.check("alice"),
+            Err(crate::Error::TooManyRequests { limit: 2, .. })
+        ));
+        limit.check("bob").unwrap();
+    }
+
+    #[test]
+    fn test_account_routes_exist() {
+        let openapi = crate::api::openapi();
+        for route in ACCOUNT_ROUTES.iter().filter(|&&route| route != "/") {
+            assert!(openapi.paths.paths.contains_key(*route), "{route}");
+        }
+    }
+
+    #[rstest]
+    #[case("/v1/verify", RateLimitKey::Account)]
+    #[case("/v1/groups/{group}/verify", RateLimitKey::Account)]
+    #[case("/v1/challenges", RateLimitKey::Ip)]
+    #[case("/v1/admin/accounts", RateLimitKey::Ip)]
+    fn test_key_for_account_policy(#[case] route: &str, #[case] key: RateLimitKey) {
+        let policy = RateLimitPolicy {
+            interval: Duration::from_mins(1),
+            burst: 2,
+            key: RateLimitKey::Account,
+        };
+        assert_eq!(key_for(route, &policy), key);
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't defined")]
+    fn test_routes_var_panic() {
+        unsafe { std::env::set_var(RATE_LIMIT_ROUTES, "/=missing") }
+        let _ = &*ROUTES;
+    }
+}
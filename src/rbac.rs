@@ -0,0 +1,321 @@
+use axum::extract::rejection::RawPathParamsRejection;
+use axum::extract::{MatchedPath, RawPathParams, Request};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Env var used to bind roles to callers.
+///
+/// Bindings are comma-separated, each of which is defined as `subject=role[|role...]`,
+/// where the subject is either the id of an API or HMAC key, or `cert:<common name>`
+/// of a TLS client certificate.
+const ROLE_BINDINGS: &str = "ROLE_BINDINGS";
+
+/// Prefix of subjects which are identified by TLS client certificates.
+pub(crate) const CERT_SUBJECT_PREFIX: &str = "cert:";
+
+/// A role, which grants a set of permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Role {
+    /// May verify codes and create sessions of its accounts.
+    Verifier,
+    /// May enroll accounts and get their QR codes.
+    Enroller,
+    /// May read the audit log and the state of accounts.
+    Auditor,
+    /// May do anything.
+    Admin,
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verifier" => Ok(Self::Verifier),
+            "enroller" => Ok(Self::Enroller),
+            "auditor" => Ok(Self::Auditor),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!(
+                "unknown role {s:?}, expected verifier, enroller, auditor or admin"
+            )),
+        }
+    }
+}
+
+/// What a route does, which is granted by roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Permission {
+    /// Verify codes and create or check sessions.
+    Verify,
+    /// Read accounts (without secrets).
+    Read,
+    /// Enroll accounts and read their secrets (e.g. QR codes).
+    Enroll,
+    /// Read the audit log and the state of accounts.
+    Audit,
+    /// Change or remove accounts, and anything else.
+    Manage,
+}
+
+impl Permission {
+    /// Name of the permission in error messages.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Verify => "verify",
+            Self::Read => "read",
+            Self::Enroll => "enroll",
+            Self::Audit => "audit",
+            Self::Manage => "manage",
+        }
+    }
+}
+
+impl Role {
+    /// Check if the role grants the given permission.
+    pub(crate) const fn grants(self, permission: Permission) -> bool {
+        use Permission as P;
+        matches!(
+            (self, permission),
+            (Self::Admin, _)
+                | (Self::Verifier, P::Verify | P::Read)
+                | (Self::Enroller, P::Enroll | P::Read)
+                | (Self::Auditor, P::Audit | P::Read)
+        )
+    }
+}
+
+/// The permission which the route (given by its method and matched path) requires.
+///
+/// Routes which aren't listed require [`Permission::Manage`].
+pub(crate) fn required_permission(method: &Method, path: &str) -> Permission {
+    match (method.as_str(), path) {
+        (
+            "POST",
+            "/v1/verify"
+            | "/v1/sessions"
+            | "/v1/sessions/step-up"
+            | "/v1/unlocks"
+            | "/v1/groups/{group}/verify"
+            | "/v1/groups/{group}/approvals"
+            | "/v1/approvals/{id}"
+            | "/v1/challenges"
+            | "/v1/challenges/{id}",
+        )
+        | ("GET", "/v1/sessions" | "/v1/approvals/{id}")
+        | (_, "/") => Permission::Verify,
+        ("GET", "/v1/accounts" | "/v1/accounts/{id}" | "/v1/unlocks/key") => Permission::Read,
+        ("GET", "/v1/accounts/{id}/qr.png" | "/v1/accounts/{id}/qr.svg")
+        | ("POST", "/v1/enrollment" | "/v1/admin/accounts") => Permission::Enroll,
+        ("GET", "/v1/admin/accounts" | "/v1/admin/audit" | "/v1/admin/groups") => Permission::Audit,
+        _ => Permission::Manage,
+    }
+}
+
+/// Roles bound to subjects, which are either key ids or `cert:<common name>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RbacConfig {
+    bindings: HashMap<String, Vec<Role>>,
+}
+
+impl RbacConfig {
+    /// Roles bound to the subject (a key id or `cert:<common name>`).
+    pub(crate) fn roles(&self, subject: &str) -> &[Role] {
+        self.bindings.get(subject).map_or(&[], Vec::as_slice)
+    }
+
+    /// Check if any role is bound to the common name of a TLS client certificate.
+    pub(crate) fn binds_cert(&self, common_name: &str) -> bool {
+        self.bindings
+            .contains_key(&format!("{CERT_SUBJECT_PREFIX}{common_name}"))
+    }
+
+    /// Check if the caller has a role which grants the permission.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::PermissionDenied`] if no role of the caller grants it.
+    pub(crate) fn authorize(
+        &self,
+        caller: &crate::Caller,
+        permission: Permission,
+    ) -> crate::Result<()> {
+        let roles = caller
+            .key_id
+            .as_deref()
+            .map_or(&[][..], |id| self.roles(id));
+        if roles.iter().any(|role| role.grants(permission)) {
+            return Ok(());
+        }
+        Err(crate::Error::PermissionDenied {
+            caller: caller
+                .key_id
+                .clone()
+                .unwrap_or_else(|| "anonymous".to_owned()),
+            permission: permission.name(),
+        })
+    }
+}
+
+/// Roles bound to callers.
+///
+/// Role-based access control is disabled (every caller may call every route)
+/// if env var `ROLE_BINDINGS` hasn't been set. Otherwise, callers without
+/// any role are denied.
+///
+/// # Panics
+///
+/// Panics when env var `ROLE_BINDINGS` cannot be parsed.
+pub(crate) static RBAC_CONFIG: LazyLock<Option<RbacConfig>> = LazyLock::new(init_rbac_config);
+
+fn init_rbac_config() -> Option<RbacConfig> {
+    let Ok(value) = std::env::var(ROLE_BINDINGS) else {
+        tracing::info!(
+            "Env var {ROLE_BINDINGS} hasn't been set. Role-based access control is disabled."
+        );
+        return None;
+    };
+    let mut bindings = HashMap::new();
+    for item in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((subject, roles)) = item.rsplit_once('=') else {
+            panic!("{ROLE_BINDINGS}: {item:?} should be like subject=role[|role...].");
+        };
+        let roles = roles
+            .split('|')
+            .map(|role| {
+                role.trim()
+                    .parse()
+                    .unwrap_or_else(|e| panic!("{ROLE_BINDINGS} is invalid: {e}."))
+            })
+            .collect();
+        let subject = subject.trim();
+        assert!(
+            bindings.insert(subject.to_owned(), roles).is_none(),
+            "{ROLE_BINDINGS}: subject {subject:?} is duplicated."
+        );
+    }
+    Some(RbacConfig { bindings })
+}
+
+/// Middleware which checks if the [`Caller`](crate::Caller) has a role
+/// granting the permission which the matched route requires.
+///
+/// It must be layered inside [`authenticate`](crate::authenticate).
+/// Denials are recorded in the audit log against the account of the path (if any).
+pub(crate) async fn authorize_role(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    matched_path: MatchedPath,
+    params: Result<RawPathParams, RawPathParamsRejection>,
+    request: Request,
+    next: Next,
+) -> Response {
+    use crate::audit::{AuditAction, record};
+
+    let Some(config) = RBAC_CONFIG.as_ref() else {
+        return next.run(request).await;
+    };
+    let permission = required_permission(request.method(), matched_path.as_str());
+    let result = config.authorize(&caller, permission);
+    if result.is_err() {
+        let account = params
+            .as_ref()
+            .ok()
+            .and_then(|params| params.iter().find(|(key, _)| *key == "id"))
+            .map_or("*", |(_, value)| value);
+        record(AuditAction::Deny, account, &client, &result);
+    }
+    match result {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Method::POST, "/v1/verify", Permission::Verify)]
+    #[case(Method::POST, "/", Permission::Verify)]
+    #[case(Method::GET, "/v1/sessions", Permission::Verify)]
+    #[case(Method::POST, "/v1/groups/{group}/verify", Permission::Verify)]
+    #[case(Method::POST, "/v1/unlocks", Permission::Verify)]
+    #[case(Method::POST, "/v1/groups/{group}/approvals", Permission::Verify)]
+    #[case(Method::POST, "/v1/approvals/{id}", Permission::Verify)]
+    #[case(Method::GET, "/v1/approvals/{id}", Permission::Verify)]
+    #[case(Method::POST, "/v1/challenges", Permission::Verify)]
+    #[case(Method::POST, "/v1/challenges/{id}", Permission::Verify)]
+    #[case(Method::POST, "/v1/sessions/step-up", Permission::Verify)]
+    #[case(Method::GET, "/v1/unlocks/key", Permission::Read)]
+    #[case(Method::GET, "/v1/accounts/{id}", Permission::Read)]
+    #[case(Method::GET, "/v1/accounts/{id}/qr.svg", Permission::Enroll)]
+    #[case(Method::POST, "/v1/admin/accounts", Permission::Enroll)]
+    #[case(Method::GET, "/v1/admin/audit", Permission::Audit)]
+    #[case(Method::GET, "/v1/admin/groups", Permission::Audit)]
+    #[case(Method::PUT, "/v1/admin/groups/{group}", Permission::Manage)]
+    #[case(Method::POST, "/v1/admin/accounts/{id}/disable", Permission::Manage)]
+    #[case(Method::DELETE, "/v1/admin/accounts/{id}", Permission::Manage)]
+    #[case(Method::GET, "/v1/admin/export", Permission::Manage)]
+    fn test_required_permission(
+        #[case] method: Method,
+        #[case] path: &str,
+        #[case] expected: Permission,
+    ) {
+        assert_eq!(required_permission(&method, path), expected);
+    }
+
+    #[rstest]
+    #[case(Role::Verifier, Permission::Verify, true)]
+    #[case(Role::Verifier, Permission::Enroll, false)]
+    #[case(Role::Enroller, Permission::Enroll, true)]
+    #[case(Role::Enroller, Permission::Verify, false)]
+    #[case(Role::Auditor, Permission::Audit, true)]
+    #[case(Role::Auditor, Permission::Manage, false)]
+    #[case(Role::Admin, Permission::Manage, true)]
+    fn test_role_grants(
+        #[case] role: Role,
+        #[case] permission: Permission,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(role.grants(permission), expected);
+    }
+
+    #[test]
+    fn test_rbac_config_default() {
+        assert!(std::env::var(ROLE_BINDINGS).is_err());
+        assert_eq!(*RBAC_CONFIG, None);
+    }
+
+    #[test]
+    fn test_rbac_config_var() {
+        unsafe {
+            std::env::set_var(
+                ROLE_BINDINGS,
+                "game=verifier, ci=enroller|auditor,cert:ops.example.com=admin",
+            );
+        }
+        let config = RBAC_CONFIG.as_ref().unwrap();
+        assert_eq!(config.roles("game"), [Role::Verifier]);
+        assert_eq!(config.roles("ci"), [Role::Enroller, Role::Auditor]);
+        assert_eq!(config.roles("cert:ops.example.com"), [Role::Admin]);
+        assert_eq!(config.roles("other"), []);
+        assert!(config.binds_cert("ops.example.com"));
+        assert!(!config.binds_cert("game"));
+    }
+
+    #[rstest]
+    #[case("game")]
+    #[case("game=root")]
+    #[case("game=verifier,game=admin")]
+    #[should_panic(expected = "ROLE_BINDINGS")]
+    fn test_rbac_config_var_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(ROLE_BINDINGS, value) }
+        let _ = &*RBAC_CONFIG;
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Max length of scope names.
+const MAX_SCOPE_LEN: usize = 64;
+
+/// Requirements of a scope beyond a valid code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ScopeRule {
+    /// Whether the code must be the one of the current period, i.e. skew doesn't apply.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fresh: bool,
+    /// Whether a second, different code of the account must be sent along
+    /// (`second_token`), e.g. the next one shown by the authenticator app.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub second_code: bool,
+}
+
+impl ScopeRule {
+    fn is_met(self, proof: Proof) -> bool {
+        (!self.fresh || proof.fresh) && (!self.second_code || proof.second_code)
+    }
+}
+
+/// How strongly a verified code proves possession of the secret.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Proof {
+    /// The code is the one of the current period.
+    pub(crate) fresh: bool,
+    /// A second, different code has been verified along.
+    pub(crate) second_code: bool,
+}
+
+/// Requested scopes, which are either granted or denied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub(crate) struct ScopeGrant {
+    /// Scopes which have been granted.
+    #[schema(example = json!(["log_upload"]))]
+    pub(crate) scopes: Vec<String>,
+    /// Scopes which the policy doesn't define, or whose requirements aren't met.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(example = json!(["cheats"]))]
+    pub(crate) denied: Vec<String>,
+}
+
+/// Grant the requested scopes which are defined by `rules` and whose requirements
+/// are met by the proof.
+pub(crate) fn grant(
+    rules: &BTreeMap<String, ScopeRule>,
+    requested: &[String],
+    proof: Proof,
+) -> ScopeGrant {
+    let mut grant = ScopeGrant::default();
+    for scope in requested {
+        let granted = rules.get(scope).is_some_and(|rule| rule.is_met(proof));
+        let scopes = if granted {
+            &mut grant.scopes
+        } else {
+            &mut grant.denied
+        };
+        if !scopes.contains(scope) {
+            scopes.push(scope.clone());
+        }
+    }
+    grant
+}
+
+/// Split scopes separated by spaces (like `scope` of OAuth 2.0).
+pub(crate) fn parse_scope(scope: &str) -> Vec<String> {
+    scope.split_whitespace().map(ToOwned::to_owned).collect()
+}
+
+/// Check if the name of the scope is valid.
+///
+/// Names are 1 to 64 characters of ASCII letters, digits, `-`, `_`, `.` and `:`.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if the name is invalid.
+pub(crate) fn validate_scope_name(name: &str) -> crate::Result<()> {
+    let valid = (1..=MAX_SCOPE_LEN).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b));
+    if valid {
+        Ok(())
+    } else {
+        Err(crate::Error::InvalidInput(format!(
+            "invalid scope {name:?}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn rules() -> BTreeMap<String, ScopeRule> {
+        let privileged = ScopeRule {
+            fresh: true,
+            second_code: true,
+        };
+        BTreeMap::from([
+            ("log_upload".to_owned(), ScopeRule::default()),
+            ("cheats".to_owned(), privileged),
+        ])
+    }
+
+    #[rstest]
+    #[case(Proof::default(), &["log_upload"], &["cheats", "console"])]
+    #[case(Proof { fresh: true, second_code: false }, &["log_upload"], &["cheats", "console"])]
+    #[case(Proof { fresh: true, second_code: true }, &["log_upload", "cheats"], &["console"])]
+    fn test_grant(#[case] proof: Proof, #[case] granted: &[&str], #[case] denied: &[&str]) {
+        let requested = parse_scope(" log_upload  cheats console log_upload");
+        let grant = grant(&rules(), &requested, proof);
+        assert_eq!(grant.scopes, granted);
+        assert_eq!(grant.denied, denied);
+    }
+
+    #[rstest]
+    #[case("console", true)]
+    #[case("game:cheats.v2", true)]
+    #[case("", false)]
+    #[case("god mode", false)]
+    #[case(&"s".repeat(65), false)]
+    fn test_validate_scope_name(#[case] name: &str, #[case] valid: bool) {
+        assert_eq!(validate_scope_name(name).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_scope_rule_serde() {
+        let json = r#"{"cheats":{"fresh":true,"second_code":true},"log_upload":{}}"#;
+        let rules: BTreeMap<String, ScopeRule> = serde_json::from_str(json).unwrap();
+        assert_eq!(rules, self::rules());
+        assert_eq!(serde_json::to_string(&rules).unwrap(), json);
+    }
+}
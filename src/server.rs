@@ -1,17 +1,26 @@
 /// Starts the HTTP server for the TOTP service.
 ///
 /// This function:
-/// - Binds the server to the address specified by `BIND_PORT`.
+/// - Listens on the addresses specified by `LISTEN_ADDRS` (TCP or Unix domain
+///   sockets), or on the sockets passed by systemd (`LISTEN_FDS`).
+/// - Serves the admin API on the addresses specified by `ADMIN_LISTEN_ADDRS`
+///   only, if it's set.
+/// - Serves HTTPS on TCP listeners if `TLS_CERT_PATH` is set, optionally
+///   requiring client certificates and redirecting plain HTTP to HTTPS.
 /// - Initializes logging with the app version and environment variable checks.
 /// - Starts serving requests using the `axum::serve` framework.
+/// - Fails the health check once `SIGTERM` or `SIGINT` is received, and after
+///   `SIGTERM` keeps accepting connections for `SHUTDOWN_PRE_STOP_DELAY` seconds.
+/// - Returns after in-flight requests are drained (or
+///   `SHUTDOWN_DRAIN_TIMEOUT` seconds elapse).
 ///
 /// # Panics
 ///
 /// This function will panic if:
-/// - The server fails to bind to the specified [`SocketAddr`](std::net::SocketAddr).
+/// - The server fails to bind to any of the listen addresses.
 /// - The server fails to start serving requests ([`axum::serve()`]).
 pub async fn start_server() {
-    use std::net::SocketAddr;
+    use std::time::Duration;
 
     tracing::info!("App version: {}.", crate::PKG_VERSION);
     // Check if required env vars have been set correctly.
@@ -19,62 +28,206 @@ pub async fn start_server() {
     // Print the URL and QR Code to stdout.
     crate::print_qr_code();
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], *crate::BIND_PORT));
-    let listener = tokio::net::TcpListener::bind(addr)
+    let tls = crate::tls::TLS_CONFIG.as_ref().map(|tls_config| {
+        let server_config = crate::tls::ReloadableServerConfig::watch(tls_config)
+            .unwrap_or_else(|e| panic!("Failed to load TLS certificates. Error: {e}."));
+        (tls_config, server_config)
+    });
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    // The router is shared by listeners, and so are rate limits.
+    let app = app();
+    let mut servers = tokio::task::JoinSet::new();
+    let listeners = crate::listen::bind_listeners().await;
+    let https_port = spawn_servers(
+        &mut servers,
+        listeners,
+        &app,
+        tls.as_ref(),
+        &shutdown_rx,
+        "",
+    );
+    let admin_listeners = crate::listen::bind_admin_listeners().await;
+    if !admin_listeners.is_empty() {
+        let admin_app = admin_app();
+        spawn_servers(
+            &mut servers,
+            admin_listeners,
+            &admin_app,
+            tls.as_ref(),
+            &shutdown_rx,
+            "Admin API: ",
+        );
+    }
+    let redirect_port = tls.and_then(|(tls_config, _)| tls_config.redirect_port);
+    if let (Some(redirect_port), Some(https_port)) = (redirect_port, https_port) {
+        tokio::spawn(start_redirect_server(redirect_port, https_port));
+    }
+
+    let all_servers_done = async {
+        while let Some(result) = servers.join_next().await {
+            result
+                .unwrap_or_else(|e| panic!("Server task failed. Error: {e}."))
+                .unwrap_or_else(|e| panic!("Failed to start axum server. Error: {e}."));
+        }
+    };
+    let drain_deadline = async {
+        crate::shutdown_signal().await;
+        shutdown_tx.send_replace(true);
+        tokio::time::sleep(Duration::from_secs(*crate::DRAIN_TIMEOUT)).await;
+    };
+
+    tokio::select! {
+        () = all_servers_done => {
+            tracing::info!("Server has been shut down gracefully.");
+        }
+        () = drain_deadline => {
+            tracing::warn!("Drain timeout elapsed, in-flight requests are dropped.");
+        }
+    }
+}
+
+/// Spawn a server of the app on every listener, and return the port of the first HTTPS listener.
+fn spawn_servers(
+    servers: &mut tokio::task::JoinSet<std::io::Result<()>>,
+    listeners: Vec<crate::listen::BoundListener>,
+    app: &axum::Router,
+    tls: Option<&(&crate::tls::TlsConfig, crate::tls::ReloadableServerConfig)>,
+    shutdown_rx: &tokio::sync::watch::Receiver<bool>,
+    log_prefix: &str,
+) -> Option<u16> {
+    use crate::listen::BoundListener;
+
+    let mut https_port = None;
+    for listener in listeners {
+        match listener {
+            BoundListener::Tcp(listener) => {
+                let addr = listener
+                    .local_addr()
+                    .unwrap_or_else(|e| panic!("Failed to get local address. Error: {e}."));
+                if let Some((tls_config, server_config)) = tls {
+                    https_port.get_or_insert(addr.port());
+                    let mtls = if tls_config.client_ca_path.is_some() {
+                        " (client certificates required)"
+                    } else {
+                        ""
+                    };
+                    tracing::info!("{log_prefix}Listening at https://{addr}{mtls}.");
+                    let listener = crate::tls::TlsListener::new(listener, server_config.clone());
+                    servers.spawn(serve(listener, app.clone(), shutdown_rx.clone()));
+                } else {
+                    tracing::info!("{log_prefix}Listening at http://{addr}.");
+                    servers.spawn(serve(listener, app.clone(), shutdown_rx.clone()));
+                }
+            }
+            #[cfg(unix)]
+            BoundListener::Unix(listener) => {
+                tracing::info!("{log_prefix}Listening at {listener}.");
+                servers.spawn(serve(listener, app.clone(), shutdown_rx.clone()));
+            }
+        }
+    }
+    https_port
+}
+
+/// Serve the app on the given listener until `shutdown` turns true.
+///
+/// The peer address ([`ConnectInfo`](axum::extract::ConnectInfo)) and the TLS client
+/// certificate (if any) of each connection are inserted into request extensions.
+pub(crate) async fn serve<L>(
+    listener: L,
+    app: axum::Router,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) -> std::io::Result<()>
+where
+    L: axum::serve::Listener<Addr = std::net::SocketAddr>,
+    L::Io: crate::tls::PeerCertificate,
+{
+    use crate::tls::PeerCertificate;
+    use axum::extract::{ConnectInfo, Request};
+    use axum::serve::IncomingStream;
+    use tower::ServiceExt;
+
+    let make_service = tower::service_fn(move |incoming: IncomingStream<'_, L>| {
+        let addr = *incoming.remote_addr();
+        let client_cert = incoming.io().client_cert();
+        let app = app.clone().map_request(move |mut request: Request| {
+            request.extensions_mut().insert(ConnectInfo(addr));
+            if let Some(client_cert) = &client_cert {
+                request.extensions_mut().insert(client_cert.clone());
+            }
+            request
+        });
+        std::future::ready(Ok::<_, std::convert::Infallible>(app))
+    });
+    axum::serve(listener, make_service)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.wait_for(|&draining| draining).await;
+        })
         .await
-        .unwrap_or_else(|e| panic!("Failed to bind SocketAddr: {addr}. Error: {e}."));
-    tracing::info!("Listening at http://localhost:{}.", addr.port());
+}
 
-    axum::serve(
-        listener,
-        app().into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await
-    .unwrap_or_else(|e| panic!("Failed to start axum server. Error: {e}."));
+/// Serve the plain HTTP listeners which redirect every request to HTTPS,
+/// on the hosts of `LISTEN_ADDRS` (see [`crate::listen::bind_redirect_listeners`]).
+///
+/// # Panics
+///
+/// Panics if any listener fails to bind or serve.
+async fn start_redirect_server(port: u16, https_port: u16) {
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in crate::listen::bind_redirect_listeners(port).await {
+        let addr = listener
+            .local_addr()
+            .unwrap_or_else(|e| panic!("Failed to get local address. Error: {e}."));
+        tracing::info!("Redirecting http://{addr} to HTTPS.");
+        servers.spawn(axum::serve(listener, crate::tls::redirect_app(https_port)).into_future());
+    }
+    while let Some(result) = servers.join_next().await {
+        result
+            .unwrap_or_else(|e| panic!("Redirect server task failed. Error: {e}."))
+            .unwrap_or_else(|e| panic!("Failed to start redirect server. Error: {e}."));
+    }
 }
 
 /// Configures and returns the Axum router for the TOTP service.
 ///
+/// The admin API is left out if it's served on its own listeners (`ADMIN_LISTEN_ADDRS`),
+/// or if no caller can be authenticated (see [`Routes::public`](crate::api::Routes::public)).
+///
 /// # Returns
 ///
 /// An [`axum::Router`] configured with routes and middleware for the TOTP service.
 pub(crate) fn app() -> axum::Router {
-    use crate::{check_current, handler_404, handler_405, health, timeout_error_handler};
+    with_layers(crate::api::router(true, crate::api::Routes::public()))
+}
+
+/// Configures and returns the Axum router of the admin API,
+/// which is served on the listeners of `ADMIN_LISTEN_ADDRS`.
+pub(crate) fn admin_app() -> axum::Router {
+    with_layers(crate::api::router(true, crate::api::Routes::Admin))
+}
+
+/// Add health checks, the fallback and middleware to the router.
+fn with_layers(router: axum::Router) -> axum::Router {
+    use crate::{handler_404, health, timeout_error_handler};
     use axum::error_handling::HandleErrorLayer;
     use axum::routing::get;
     use std::time::Duration;
+    use tower_http::ServiceBuilderExt;
+    use tower_http::request_id::MakeRequestUuid;
 
-    // Configure the rate limiter.
-    let governor_conf = std::sync::Arc::new(
-        tower_governor::governor::GovernorConfigBuilder::default()
-            .per_second(30)
-            .burst_size(*crate::RATE_LIMIT)
-            .finish()
-            .expect("Failed to configure tower_governor."),
-    );
-
-    let governor_limiter = governor_conf.limiter().clone();
-    let interval = Duration::from_mins(1);
-    // A separate background task to clean up.
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(interval).await;
-            tracing::trace!("Rate limiting storage size: {}.", governor_limiter.len());
-            governor_limiter.retain_recent();
-        }
-    });
-
-    axum::Router::new()
-        .route("/", get(handler_405).post(check_current))
+    router
+        // Health checks are never rate limited.
         .route("/health", get(health))
         .fallback(handler_404)
         .layer(
             tower::ServiceBuilder::new()
+                // Set and propagate `x-request-id` headers.
+                .set_x_request_id(MakeRequestUuid)
+                .propagate_x_request_id()
                 // Handle timeout error.
                 .layer(HandleErrorLayer::new(timeout_error_handler))
                 // Handle timeout.
-                .timeout(Duration::from_secs(1))
-                // Handle request rate limits.
-                .layer(tower_governor::GovernorLayer::new(governor_conf)),
-        )
+                .timeout(Duration::from_secs(1)),
+        ) // Answer CORS preflight requests before rate limiting and authentication.
+        .layer(tower::util::option_layer(crate::cors::cors_layer()))
 }
@@ -0,0 +1,268 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Env var used to set the secret which session tokens are signed with.
+const SESSION_SECRET: &str = "SESSION_SECRET";
+/// Env var used to set how long (in seconds) session tokens are valid.
+const SESSION_TTL: &str = "SESSION_TTL";
+
+/// Claims of a session, which are signed into session tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct SessionClaims {
+    /// The account which the session belongs to.
+    #[schema(example = "alice")]
+    pub(crate) sub: String,
+    /// When the TOTP code was verified (seconds since the Unix epoch).
+    pub(crate) auth_time: u64,
+    /// The time step (i.e. the counter of RFC 6238) of the TOTP code,
+    /// which codes of step-ups must be later than.
+    #[serde(default)]
+    pub(crate) totp_step: u64,
+    /// When the session expires (seconds since the Unix epoch).
+    pub(crate) exp: u64,
+    /// Scopes which have been granted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(example = json!(["log_upload"]))]
+    pub(crate) scopes: Vec<String>,
+}
+
+/// Settings of session tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SessionConfig {
+    secret: Vec<u8>,
+    /// Seconds which session tokens are valid for, unless policies say otherwise.
+    pub(crate) ttl: u64,
+}
+
+impl SessionConfig {
+    fn mac(&self) -> Hmac<sha2::Sha256> {
+        Hmac::<sha2::Sha256>::new_from_slice(&self.secret)
+            .unwrap_or_else(|e| panic!("HMAC accepts keys of any size. Error: {e}."))
+    }
+
+    /// Issue a session token of the account with the granted scopes, whose code
+    /// (valid for the time step `totp_step`) has been verified at `now`,
+    /// which is valid for `ttl` seconds (see the policy of the account).
+    ///
+    /// Tokens are formatted as `<claims>.<signature>`, both of which are base64url-encoded.
+    pub(crate) fn issue(
+        &self,
+        account: &str,
+        now: u64,
+        ttl: u64,
+        scopes: Vec<String>,
+        totp_step: u64,
+    ) -> (String, SessionClaims) {
+        let claims = SessionClaims {
+            sub: account.to_owned(),
+            auth_time: now,
+            totp_step,
+            exp: now.saturating_add(ttl),
+            scopes,
+        };
+        let payload = serde_json::to_vec(&claims).unwrap_or_else(|e| {
+            panic!("Failed to serialize session claims. Error: {e}.");
+        });
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        (format!("{payload}.{signature}"), claims)
+    }
+
+    /// Re-issue the session after another code of its account (valid for the time step
+    /// `totp_step`) has been verified at `now`, adding the newly granted scopes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::TotpReplayed`] unless the code is later than the one
+    /// which the session has been issued for.
+    pub(crate) fn step_up(
+        &self,
+        claims: SessionClaims,
+        now: u64,
+        ttl: u64,
+        scopes: Vec<String>,
+        totp_step: u64,
+    ) -> crate::Result<(String, SessionClaims)> {
+        if totp_step <= claims.totp_step {
+            return Err(crate::Error::TotpReplayed);
+        }
+        let mut elevated = claims.scopes;
+        for scope in scopes {
+            if !elevated.contains(&scope) {
+                elevated.push(scope);
+            }
+        }
+        Ok(self.issue(&claims.sub, now, ttl, elevated, totp_step))
+    }
+
+    /// Verify the session token, given the current Unix time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::SessionInvalid`] if the token is malformed, forged or expired.
+    pub(crate) fn verify(&self, token: &str, now: u64) -> crate::Result<SessionClaims> {
+        type E = crate::Error;
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or(E::SessionInvalid("malformed token"))?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| E::SessionInvalid("malformed token"))?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| E::SessionInvalid("invalid signature"))?;
+        let claims: SessionClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+            .ok_or(E::SessionInvalid("malformed token"))?;
+        if claims.exp <= now {
+            return Err(E::SessionInvalid("token has expired"));
+        }
+        Ok(claims)
+    }
+}
+
+/// Settings of session tokens.
+///
+/// If env var `SESSION_SECRET` hasn't been set, a random secret is used,
+/// thus sessions don't survive restarts and aren't shared by replicas.
+///
+/// # Panics
+///
+/// Panics when the bitsize of `SESSION_SECRET` is smaller than 256,
+/// or when `SESSION_TTL` isn't a positive integer.
+pub(crate) static SESSION_CONFIG: LazyLock<SessionConfig> = LazyLock::new(init_session_config);
+
+fn init_session_config() -> SessionConfig {
+    let secret = if let Ok(value) = std::env::var(SESSION_SECRET) {
+        let bitsize = value.len() * 8;
+        assert!(
+            bitsize >= 256,
+            "The bitsize of {SESSION_SECRET} should at least 256 (the given one is {bitsize})."
+        );
+        value.into_bytes()
+    } else {
+        tracing::info!("Env var {SESSION_SECRET} hasn't been set. Using a random secret.");
+        rand::random::<[u8; 32]>().to_vec()
+    };
+    let ttl = std::env::var(SESSION_TTL).map_or(3600, |value| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&ttl| ttl != 0)
+            .unwrap_or_else(|| panic!("{SESSION_TTL} must be a positive integer!"))
+    });
+    SessionConfig { secret, ttl }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SessionClaims {
+    type Rejection = crate::Error;
+
+    /// Verify the session token given by header `Authorization: Bearer <token>`.
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(crate::Error::SessionInvalid("missing bearer token"))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        SESSION_CONFIG.verify(token.trim(), now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn test_config() -> SessionConfig {
+        SessionConfig {
+            secret: b"0123456789abcdef0123456789abcdef".to_vec(),
+            ttl: 60,
+        }
+    }
+
+    #[test]
+    fn test_session_token() {
+        let config = test_config();
+        let scopes = vec!["log_upload".to_owned()];
+        let (token, claims) = config.issue("alice", NOW, config.ttl, scopes, 10);
+        assert_eq!(claims.exp, NOW + 60);
+        assert_eq!(config.verify(&token, NOW + 59).unwrap(), claims);
+        assert!(config.verify(&token, NOW + 60).is_err());
+
+        let other = SessionConfig {
+            secret: b"another secret".to_vec(),
+            ..test_config()
+        };
+        assert!(other.verify(&token, NOW).is_err());
+        // The claims cannot be changed without the secret.
+        let (forged, _) = other.issue("bob", NOW, other.ttl, Vec::new(), 10);
+        let (payload, _) = forged.split_once('.').unwrap();
+        let (_, signature) = token.split_once('.').unwrap();
+        assert!(
+            config
+                .verify(&format!("{payload}.{signature}"), NOW)
+                .is_err()
+        );
+        assert!(config.verify("no-dot", NOW).is_err());
+    }
+
+    #[test]
+    fn test_session_step_up() {
+        let config = test_config();
+        let scopes = |scopes: &[&str]| scopes.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
+        let (_, claims) = config.issue("alice", NOW, config.ttl, scopes(&["log_upload"]), 10);
+        // The code of the session (or an older one) cannot step up.
+        for step in [9, 10] {
+            assert!(matches!(
+                config.step_up(claims.clone(), NOW + 30, 60, Vec::new(), step),
+                Err(crate::Error::TotpReplayed)
+            ));
+        }
+        let requested = scopes(&["cheats", "log_upload"]);
+        let (token, elevated) = config.step_up(claims, NOW + 30, 60, requested, 11).unwrap();
+        assert_eq!(elevated.sub, "alice");
+        assert_eq!(elevated.auth_time, NOW + 30);
+        assert_eq!(elevated.totp_step, 11);
+        assert_eq!(elevated.exp, NOW + 90);
+        assert_eq!(elevated.scopes, ["log_upload", "cheats"]);
+        assert_eq!(config.verify(&token, NOW + 30).unwrap(), elevated);
+    }
+
+    #[test]
+    fn test_session_config_default() {
+        assert!(std::env::var(SESSION_SECRET).is_err());
+        assert_eq!(SESSION_CONFIG.ttl, 3600);
+        assert_eq!(SESSION_CONFIG.secret.len(), 32);
+    }
+
+    #[test]
+    #[should_panic(expected = "The bitsize of SESSION_SECRET should at least 256")]
+    fn test_session_secret_var_panic() {
+        unsafe { std::env::set_var(SESSION_SECRET, "too short") }
+        let _ = &*SESSION_CONFIG;
+    }
+
+    #[test]
+    #[should_panic(expected = "SESSION_TTL must be a positive integer!")]
+    fn test_session_ttl_var_panic() {
+        unsafe { std::env::set_var(SESSION_TTL, "0") }
+        let _ = &*SESSION_CONFIG;
+    }
+}
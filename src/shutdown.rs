@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Whether the server is draining in-flight requests before shutting down.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Return true once a shutdown signal has been received.
+pub(crate) fn is_draining() -> bool {
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Mark the server as draining, which makes the health check fail.
+pub(crate) fn start_draining() {
+    DRAINING.store(true, Ordering::Relaxed);
+}
+
+/// Mark the server as draining, then wait for `pre_stop_delay` while connections are
+/// still accepted, so that load balancers see the failing health check first.
+pub(crate) async fn drain_after(pre_stop_delay: Duration) {
+    start_draining();
+    if !pre_stop_delay.is_zero() {
+        tracing::info!(
+            "Failing health checks for {}s before refusing connections.",
+            pre_stop_delay.as_secs()
+        );
+        tokio::time::sleep(pre_stop_delay).await;
+    }
+}
+
+/// Wait until `SIGTERM` or `SIGINT` (`ctrl-c`) is received.
+///
+/// The server is marked as draining as soon as a signal is received. After `SIGTERM`,
+/// it resolves only once [`PRE_STOP_DELAY`](crate::PRE_STOP_DELAY) seconds have elapsed,
+/// so new connections keep being accepted until load balancers have noticed.
+///
+/// # Panics
+///
+/// Panics if signal handlers cannot be installed.
+pub(crate) async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .unwrap_or_else(|e| panic!("Failed to install SIGINT handler. Error: {e}."));
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        signal(SignalKind::terminate())
+            .unwrap_or_else(|e| panic!("Failed to install SIGTERM handler. Error: {e}."))
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    let pre_stop_delay = tokio::select! {
+        () = ctrl_c => {
+            tracing::info!("SIGINT received.");
+            Duration::ZERO
+        }
+        () = terminate => {
+            tracing::info!("SIGTERM received.");
+            Duration::from_secs(*crate::PRE_STOP_DELAY)
+        }
+    };
+    drain_after(pre_stop_delay).await;
+    tracing::info!(
+        "Draining in-flight requests (timeout: {}s).",
+        *crate::DRAIN_TIMEOUT
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_after() {
+        let draining = tokio::spawn(drain_after(Duration::from_millis(300)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(is_draining());
+        assert!(!draining.is_finished());
+        draining.await.unwrap();
+    }
+}
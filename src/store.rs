@@ -0,0 +1,1253 @@
+use crate::approval::Approval;
+use crate::bundle::{ConflictPolicy, ImportDiff};
+use crate::group::{EffectivePolicy, Group, GroupInfo, Policy, TotpParams};
+use crate::otpauth::OtpauthProfile;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, RwLock};
+
+/// Env var used to set the path of the file (JSON) where enrolled accounts are kept.
+const ACCOUNTS_PATH: &str = "ACCOUNTS_PATH";
+
+/// Size of secrets generated for enrolled accounts (160 bits, as recommended by RFC 4226).
+const SECRET_LEN: usize = 20;
+/// Max length of account names.
+pub(crate) const MAX_NAME_LEN: usize = 64;
+
+/// An account, which owns a TOTP secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Account {
+    /// Name of the account (see [`validate_name`]).
+    pub(crate) name: String,
+    /// The raw secret, which is base32-encoded in the accounts file.
+    #[serde(with = "base32_secret")]
+    pub(crate) secret: Vec<u8>,
+    /// Seconds since the Unix epoch.
+    pub(crate) created_at: u64,
+    /// Issuer and label shown by authenticator apps.
+    #[serde(flatten)]
+    pub(crate) profile: OtpauthProfile,
+    /// Whether codes of the account are rejected.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) disabled: bool,
+    /// Parameters which the secret has been generated with.
+    #[serde(default, skip_serializing_if = "TotpParams::is_default")]
+    pub(crate) totp: TotpParams,
+    /// The group whose policy the account inherits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) group: Option<String>,
+    /// Settings which override the ones of the group.
+    #[serde(default, skip_serializing_if = "Policy::is_empty")]
+    pub(crate) policy: Policy,
+}
+
+impl Account {
+    /// Create an account with the default TOTP parameters, which isn't in any group.
+    pub(crate) fn new(
+        name: &str,
+        secret: Vec<u8>,
+        created_at: u64,
+        profile: OtpauthProfile,
+    ) -> Self {
+        Account {
+            name: name.to_owned(),
+            secret,
+            created_at,
+            profile,
+            disabled: false,
+            totp: TotpParams::default(),
+            group: None,
+            policy: Policy::default(),
+        }
+    }
+
+    /// Create an account with a random secret, whose parameters are given by `policy`.
+    fn generate(name: &str, profile: OtpauthProfile, policy: &EffectivePolicy) -> Self {
+        Account {
+            totp: policy.totp,
+            ..Account::new(
+                name,
+                rand::random::<[u8; SECRET_LEN]>().to_vec(),
+                crate::utils::now_secs(),
+                profile,
+            )
+        }
+    }
+
+    /// Get the base32-encoded secret, which users may type into authenticator apps.
+    pub(crate) fn secret_base32(&self) -> String {
+        totp_rs::Secret::Raw(self.secret.clone())
+            .to_encoded()
+            .to_string()
+    }
+}
+
+/// (De)serialize secrets as base32 strings.
+mod base32_secret {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        secret: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let encoded = totp_rs::Secret::Raw(secret.to_vec()).to_encoded();
+        serializer.serialize_str(&encoded.to_string())
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        totp_rs::Secret::Encoded(encoded)
+            .to_bytes()
+            .map_err(|e| serde::de::Error::custom(format!("invalid base32 secret: {e}")))
+    }
+}
+
+/// An account, without its secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AccountInfo {
+    /// Name of the account.
+    #[schema(example = "alice")]
+    pub name: String,
+    /// When the account was enrolled (seconds since the Unix epoch).
+    pub created_at: u64,
+    /// Issuer and label shown by authenticator apps, if they've been customized.
+    #[serde(flatten)]
+    pub profile: OtpauthProfile,
+    /// Whether codes of the account are rejected.
+    #[serde(default)]
+    pub disabled: bool,
+    /// Parameters which the secret has been generated with.
+    #[serde(default)]
+    pub totp: TotpParams,
+    /// The group whose policy the account inherits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "game-x")]
+    pub group: Option<String>,
+    /// Settings which override the ones of the group.
+    #[serde(default, skip_serializing_if = "Policy::is_empty")]
+    pub policy: Policy,
+}
+
+impl From<Account> for AccountInfo {
+    fn from(account: Account) -> Self {
+        AccountInfo {
+            name: account.name,
+            created_at: account.created_at,
+            profile: account.profile,
+            disabled: account.disabled,
+            totp: account.totp,
+            group: account.group,
+            policy: account.policy,
+        }
+    }
+}
+
+/// The secret of an account, which should be added to authenticator apps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Enrollment {
+    /// Name of the account.
+    #[schema(example = "alice")]
+    pub account: String,
+    /// The base32-encoded secret.
+    pub secret: String,
+    /// The `otpauth://` URL, which is usually shown as a QR code.
+    pub otpauth_url: String,
+}
+
+impl From<Account> for Enrollment {
+    fn from(account: Account) -> Self {
+        Enrollment {
+            secret: account.secret_base32(),
+            otpauth_url: crate::otpauth::OTPAUTH_CONFIG.url(&account),
+            account: account.name,
+        }
+    }
+}
+
+/// Content of the accounts file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AccountsFile {
+    accounts: Vec<Account>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<Group>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    approvals: Vec<Approval>,
+}
+
+/// Check if the account name is valid.
+///
+/// Names are 1 to 64 characters of ASCII letters, digits, `-`, `_`, `.` and `@`.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::AccountNameInvalid`] if the name is invalid.
+pub(crate) fn validate_name(name: &str) -> crate::Result<()> {
+    let valid = (1..=MAX_NAME_LEN).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_.@".contains(&b));
+    if valid {
+        Ok(())
+    } else {
+        Err(crate::Error::AccountNameInvalid {
+            account: name.to_owned(),
+        })
+    }
+}
+
+/// Accounts which TOTP codes are verified against.
+///
+/// The default account is set by env var `RAW_SECRET` and is read-only,
+/// while the other ones are enrolled by requests and kept in the accounts file
+/// along with groups and approvals.
+///
+/// Whenever more than one are locked, accounts are locked before groups,
+/// which are locked before approvals.
+#[derive(Debug)]
+pub(crate) struct AccountStore {
+    /// Path of the accounts file, or `None` if accounts are kept in memory only.
+    path: Option<PathBuf>,
+    default_account: Account,
+    accounts: RwLock<BTreeMap<String, Account>>,
+    groups: RwLock<BTreeMap<String, Group>>,
+    approvals: RwLock<BTreeMap<String, Approval>>,
+}
+
+impl AccountStore {
+    /// Open the accounts file (if any), which is created on the first change.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the file exists but cannot be read or parsed.
+    pub(crate) fn open(path: Option<PathBuf>, default_secret: Vec<u8>) -> crate::Result<Self> {
+        let file = match &path {
+            Some(path) if path.exists() => {
+                let content = std::fs::read(path)?;
+                serde_json::from_slice::<AccountsFile>(&content).map_err(std::io::Error::from)?
+            }
+            _ => AccountsFile::default(),
+        };
+        let accounts = file
+            .accounts
+            .into_iter()
+            .map(|account| (account.name.clone(), account))
+            .collect();
+        let groups = file
+            .groups
+            .into_iter()
+            .map(|group| (group.name.clone(), group))
+            .collect();
+        let approvals = file
+            .approvals
+            .into_iter()
+            .map(|approval| (approval.id.clone(), approval))
+            .collect();
+        Ok(AccountStore {
+            path,
+            default_account: Account::new(
+                crate::totp::DEFAULT_ACCOUNT,
+                default_secret,
+                crate::utils::now_secs(),
+                OtpauthProfile::default(),
+            ),
+            accounts: RwLock::new(accounts),
+            groups: RwLock::new(groups),
+            approvals: RwLock::new(approvals),
+        })
+    }
+
+    /// Get the account by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::AccountNotFound`] if the account doesn't exist.
+    pub(crate) fn get(&self, name: &str) -> crate::Result<Account> {
+        if name == self.default_account.name {
+            return Ok(self.default_account.clone());
+        }
+        self.read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            })
+    }
+
+    /// List every account, starting with the default one.
+    pub(crate) fn list(&self) -> Vec<Account> {
+        std::iter::once(self.default_account.clone())
+            .chain(self.read().values().cloned())
+            .collect()
+    }
+
+    /// Enroll a new account with a random secret, which joins the group (if any).
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the name or the profile is invalid, the account already exists,
+    /// the group doesn't exist, or the accounts file cannot be written.
+    pub(crate) fn enroll(
+        &self,
+        name: &str,
+        profile: OtpauthProfile,
+        group: Option<&str>,
+    ) -> crate::Result<Account> {
+        validate_name(name)?;
+        profile.validate()?;
+        let mut accounts = self.write();
+        if name == self.default_account.name || accounts.contains_key(name) {
+            return Err(crate::Error::AccountExists {
+                account: name.to_owned(),
+            });
+        }
+        let group_policy = match group {
+            Some(group) => self.get_group(group)?.policy,
+            None => Policy::default(),
+        };
+        let mut account = Account::generate(name, profile, &group_policy.resolve());
+        account.group = group.map(ToOwned::to_owned);
+        accounts.insert(name.to_owned(), account.clone());
+        self.save(&accounts).inspect_err(|_| {
+            accounts.remove(name);
+        })?;
+        Ok(account)
+    }
+
+    /// Add accounts with their secrets, e.g. from a migration payload.
+    ///
+    /// Either every account is added, or none of them is.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if any name or profile is invalid, any account already exists
+    /// (or appears twice), or if the accounts file cannot be written.
+    pub(crate) fn import(&self, imported: Vec<Account>) -> crate::Result<()> {
+        let mut accounts = self.write();
+        let mut names = std::collections::BTreeSet::new();
+        for account in &imported {
+            self.validate(account)?;
+            let name = account.name.as_str();
+            if name == self.default_account.name
+                || accounts.contains_key(name)
+                || !names.insert(name)
+            {
+                return Err(crate::Error::AccountExists {
+                    account: name.to_owned(),
+                });
+            }
+        }
+        let previous = accounts.clone();
+        accounts.extend(
+            imported
+                .into_iter()
+                .map(|account| (account.name.clone(), account)),
+        );
+        self.save(&accounts).inspect_err(|_| {
+            *accounts = previous;
+        })
+    }
+
+    /// Restore accounts from a bundle, resolving conflicts with existing accounts by `policy`.
+    ///
+    /// Either every change is made, or none of them is. Nothing is changed in dry runs.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if any name or profile is invalid, any account appears twice,
+    /// an existing account differs under [`ConflictPolicy::Fail`], the default account
+    /// would be overwritten, or if the accounts file cannot be written.
+    pub(crate) fn restore(
+        &self,
+        restored: Vec<Account>,
+        policy: ConflictPolicy,
+        dry_run: bool,
+    ) -> crate::Result<ImportDiff> {
+        let created_at = crate::utils::now_secs();
+        let mut accounts = self.write();
+        let mut diff = ImportDiff {
+            dry_run,
+            ..ImportDiff::default()
+        };
+        let mut names = std::collections::BTreeSet::new();
+        let mut changes = Vec::new();
+        for mut account in restored {
+            self.validate(&account)?;
+            let name = account.name.clone();
+            if !names.insert(name.clone()) {
+                return Err(crate::Error::AccountExists { account: name });
+            }
+            let existing = if name == self.default_account.name {
+                Some(&self.default_account)
+            } else {
+                accounts.get(&name)
+            };
+            let Some(existing) = existing else {
+                if account.created_at == 0 {
+                    account.created_at = created_at;
+                }
+                diff.added.push(name);
+                changes.push(account);
+                continue;
+            };
+            if existing.secret == account.secret
+                && existing.profile == account.profile
+                && existing.totp == account.totp
+            {
+                diff.unchanged.push(name);
+                continue;
+            }
+            match policy {
+                ConflictPolicy::Skip => diff.skipped.push(name),
+                ConflictPolicy::Fail => return Err(crate::Error::AccountExists { account: name }),
+                ConflictPolicy::Overwrite if name == self.default_account.name => {
+                    return Err(crate::Error::AccountReadOnly { account: name });
+                }
+                ConflictPolicy::Overwrite => {
+                    account.created_at = created_at;
+                    account.disabled = existing.disabled;
+                    account.group.clone_from(&existing.group);
+                    account.policy = existing.policy.clone();
+                    diff.updated.push(name);
+                    changes.push(account);
+                }
+            }
+        }
+        if dry_run || changes.is_empty() {
+            return Ok(diff);
+        }
+        let previous = accounts.clone();
+        accounts.extend(
+            changes
+                .into_iter()
+                .map(|account| (account.name.clone(), account)),
+        );
+        self.save(&accounts).inspect_err(|_| {
+            *accounts = previous;
+        })?;
+        Ok(diff)
+    }
+
+    /// List enrolled accounts, i.e. every account but the default one.
+    pub(crate) fn enrolled(&self) -> Vec<Account> {
+        self.read().values().cloned().collect()
+    }
+
+    /// Replace the secret of the account with a random one, keeping its profile, group and policy.
+    ///
+    /// The new secret is generated with the parameters of the current policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the account is read-only or doesn't exist,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn rotate(&self, name: &str) -> crate::Result<Account> {
+        let mut accounts = self.write_existing(name)?;
+        let Some(existing) = accounts.get(name) else {
+            return Err(crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            });
+        };
+        let account = Account {
+            group: existing.group.clone(),
+            policy: existing.policy.clone(),
+            ..Account::generate(name, existing.profile.clone(), &self.policy(existing))
+        };
+        let previous = accounts.insert(name.to_owned(), account.clone());
+        self.save(&accounts).inspect_err(|_| {
+            accounts.extend(previous.map(|previous| (name.to_owned(), previous)));
+        })?;
+        Ok(account)
+    }
+
+    /// Disable or enable the account, whose codes are rejected while it's disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the account is read-only or doesn't exist,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn set_disabled(&self, name: &str, disabled: bool) -> crate::Result<Account> {
+        let mut accounts = self.write_existing(name)?;
+        let Some(account) = accounts.get_mut(name) else {
+            return Err(crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            });
+        };
+        let previous = std::mem::replace(&mut account.disabled, disabled);
+        let account = account.clone();
+        self.save(&accounts).inspect_err(|_| {
+            if let Some(account) = accounts.get_mut(name) {
+                account.disabled = previous;
+            }
+        })?;
+        Ok(account)
+    }
+
+    /// Remove the account.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the account is read-only or doesn't exist,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn remove(&self, name: &str) -> crate::Result<Account> {
+        let mut accounts = self.write_existing(name)?;
+        let account = accounts
+            .remove(name)
+            .ok_or_else(|| crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            })?;
+        self.save(&accounts).inspect_err(|_| {
+            accounts.insert(name.to_owned(), account.clone());
+        })?;
+        Ok(account)
+    }
+
+    /// Move the account into the group (or out of any group if `None`),
+    /// replacing its own policy.
+    ///
+    /// Parameters of the secret stay as they are until it's rotated.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the policy is invalid, the account is read-only or doesn't exist,
+    /// the group doesn't exist, or the accounts file cannot be written.
+    pub(crate) fn set_policy(
+        &self,
+        name: &str,
+        group: Option<String>,
+        policy: Policy,
+    ) -> crate::Result<Account> {
+        policy.validate()?;
+        let mut accounts = self.write_existing(name)?;
+        if let Some(group) = &group {
+            self.get_group(group)?;
+        }
+        let Some(account) = accounts.get_mut(name) else {
+            return Err(crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            });
+        };
+        let previous = (
+            std::mem::replace(&mut account.group, group),
+            std::mem::replace(&mut account.policy, policy),
+        );
+        let account = account.clone();
+        self.save(&accounts).inspect_err(|_| {
+            if let Some(account) = accounts.get_mut(name) {
+                (account.group, account.policy) = previous;
+            }
+        })?;
+        Ok(account)
+    }
+
+    /// Resolve the policy of the account, which overrides the one of its group.
+    pub(crate) fn policy(&self, account: &Account) -> EffectivePolicy {
+        let group_policy = account
+            .group
+            .as_deref()
+            .and_then(|group| {
+                self.read_groups()
+                    .get(group)
+                    .map(|group| group.policy.clone())
+            })
+            .unwrap_or_default();
+        account.policy.clone().or(&group_policy).resolve()
+    }
+
+    /// Get the group by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::GroupNotFound`] if the group doesn't exist.
+    pub(crate) fn get_group(&self, name: &str) -> crate::Result<Group> {
+        self.read_groups()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| crate::Error::GroupNotFound {
+                group: name.to_owned(),
+            })
+    }
+
+    /// List every group with its members.
+    pub(crate) fn groups(&self) -> Vec<GroupInfo> {
+        let accounts = self.read();
+        self.read_groups()
+            .values()
+            .map(|group| GroupInfo {
+                group: group.clone(),
+                members: accounts
+                    .values()
+                    .filter(|account| account.group.as_ref() == Some(&group.name))
+                    .map(|account| account.name.clone())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Get the group and its members.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::GroupNotFound`] if the group doesn't exist.
+    pub(crate) fn members(&self, name: &str) -> crate::Result<(Group, Vec<Account>)> {
+        let accounts = self.read();
+        let group = self.get_group(name)?;
+        let members = accounts
+            .values()
+            .filter(|account| account.group.as_deref() == Some(name))
+            .cloned()
+            .collect();
+        Ok((group, members))
+    }
+
+    /// Create or replace the group.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the name or the policy is invalid,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn put_group(&self, group: Group) -> crate::Result<Group> {
+        validate_name(&group.name)?;
+        group.policy.validate()?;
+        let accounts = self.read();
+        let mut groups = self.write_groups();
+        let name = group.name.clone();
+        let previous = groups.insert(name.clone(), group.clone());
+        self.save_with_groups(&accounts, &groups)
+            .inspect_err(|_| match previous {
+                Some(previous) => {
+                    groups.insert(name.clone(), previous);
+                }
+                None => {
+                    groups.remove(&name);
+                }
+            })?;
+        Ok(group)
+    }
+
+    /// Remove the group, which must have no members.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the group doesn't exist or has members,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn remove_group(&self, name: &str) -> crate::Result<Group> {
+        let accounts = self.read();
+        let mut groups = self.write_groups();
+        let in_use = accounts
+            .values()
+            .any(|account| account.group.as_deref() == Some(name));
+        if in_use {
+            return Err(crate::Error::GroupInUse {
+                group: name.to_owned(),
+            });
+        }
+        let group = groups
+            .remove(name)
+            .ok_or_else(|| crate::Error::GroupNotFound {
+                group: name.to_owned(),
+            })?;
+        self.save_with_groups(&accounts, &groups).inspect_err(|_| {
+            groups.insert(name.to_owned(), group.clone());
+        })?;
+        Ok(group)
+    }
+
+    /// Keep the approval, forgetting expired ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the group doesn't exist, has fewer members than the approval requires,
+    /// or if the accounts file cannot be written.
+    pub(crate) fn create_approval(&self, approval: Approval, now: u64) -> crate::Result<Approval> {
+        let accounts = self.read();
+        let groups = self.read_groups();
+        if !groups.contains_key(&approval.group) {
+            return Err(crate::Error::GroupNotFound {
+                group: approval.group,
+            });
+        }
+        let members = accounts
+            .values()
+            .filter(|account| account.group.as_ref() == Some(&approval.group))
+            .count();
+        if members < usize::from(approval.required) {
+            return Err(crate::Error::InvalidInput(format!(
+                "group {:?} has only {members} members, fewer than {} required",
+                approval.group, approval.required
+            )));
+        }
+        let mut approvals = self.write_approvals();
+        let previous = approvals.clone();
+        approvals.retain(|_, approval| approval.expires_at > now);
+        approvals.insert(approval.id.clone(), approval.clone());
+        self.save_all(&accounts, &groups, &approvals)
+            .inspect_err(|_| *approvals = previous)?;
+        Ok(approval)
+    }
+
+    /// Get the approval by id.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::ApprovalNotFound`] if the approval doesn't exist.
+    pub(crate) fn get_approval(&self, id: &str) -> crate::Result<Approval> {
+        self.read_approvals()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| crate::Error::ApprovalNotFound { id: id.to_owned() })
+    }
+
+    /// Add the account, whose code has been verified at `now`, to the approvers.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the approval doesn't exist, rejects the account
+    /// (see [`Approval::approve`]), or if the accounts file cannot be written.
+    pub(crate) fn approve(&self, id: &str, account: &Account, now: u64) -> crate::Result<Approval> {
+        let accounts = self.read();
+        let groups = self.read_groups();
+        let mut approvals = self.write_approvals();
+        let Some(approval) = approvals.get_mut(id) else {
+            return Err(crate::Error::ApprovalNotFound { id: id.to_owned() });
+        };
+        let previous = approval.clone();
+        approval.approve(account, now)?;
+        let approval = approval.clone();
+        self.save_all(&accounts, &groups, &approvals)
+            .inspect_err(|_| {
+                approvals.insert(id.to_owned(), previous);
+            })?;
+        Ok(approval)
+    }
+
+    /// Check if the name, the profile, the TOTP parameters and the group of the account are valid.
+    fn validate(&self, account: &Account) -> crate::Result<()> {
+        validate_name(&account.name)?;
+        account.profile.validate()?;
+        account.totp.validate()?;
+        account.policy.validate()?;
+        match &account.group {
+            Some(group) => self.get_group(group).map(drop),
+            None => Ok(()),
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Account>> {
+        self.accounts
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Account>> {
+        self.accounts
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn read_groups(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Group>> {
+        self.groups
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn write_groups(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Group>> {
+        self.groups
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn read_approvals(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Approval>> {
+        self.approvals
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn write_approvals(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, Approval>> {
+        self.approvals
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Lock accounts for writing, given that the account exists and isn't read-only.
+    fn write_existing(
+        &self,
+        name: &str,
+    ) -> crate::Result<std::sync::RwLockWriteGuard<'_, BTreeMap<String, Account>>> {
+        if name == self.default_account.name {
+            return Err(crate::Error::AccountReadOnly {
+                account: name.to_owned(),
+            });
+        }
+        let accounts = self.write();
+        if !accounts.contains_key(name) {
+            return Err(crate::Error::AccountNotFound {
+                account: name.to_owned(),
+            });
+        }
+        Ok(accounts)
+    }
+
+    /// Write accounts (along with groups) to the accounts file (if any).
+    fn save(&self, accounts: &BTreeMap<String, Account>) -> crate::Result<()> {
+        self.save_with_groups(accounts, &self.read_groups())
+    }
+
+    /// Write accounts and groups (along with approvals) to the accounts file (if any).
+    fn save_with_groups(
+        &self,
+        accounts: &BTreeMap<String, Account>,
+        groups: &BTreeMap<String, Group>,
+    ) -> crate::Result<()> {
+        self.save_all(accounts, groups, &self.read_approvals())
+    }
+
+    /// Write accounts, groups and approvals to the accounts file (if any).
+    fn save_all(
+        &self,
+        accounts: &BTreeMap<String, Account>,
+        groups: &BTreeMap<String, Group>,
+        approvals: &BTreeMap<String, Approval>,
+    ) -> crate::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = AccountsFile {
+            accounts: accounts.values().cloned().collect(),
+            groups: groups.values().cloned().collect(),
+            approvals: approvals.values().cloned().collect(),
+        };
+        let content = serde_json::to_vec_pretty(&file).map_err(std::io::Error::from)?;
+        write_atomically(path, &content)
+    }
+}
+
+/// Write `content` to a temporary file which then replaces `path`,
+/// so that the file is never left half-written.
+fn write_atomically(path: &Path, content: &[u8]) -> crate::Result<()> {
+    use std::io::Write;
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Accounts which TOTP codes are verified against.
+///
+/// If env var `ACCOUNTS_PATH` hasn't been set, enrolled accounts are kept in memory only.
+///
+/// # Panics
+///
+/// Panics if the accounts file cannot be read or parsed.
+pub(crate) static ACCOUNTS: LazyLock<AccountStore> = LazyLock::new(init_accounts);
+
+fn init_accounts() -> AccountStore {
+    let path = std::env::var_os(ACCOUNTS_PATH).map(PathBuf::from);
+    if path.is_none() {
+        tracing::info!("Env var {ACCOUNTS_PATH} hasn't been set. Accounts are kept in memory.");
+    }
+    AccountStore::open(path.clone(), crate::VEC_SECRET.clone()).unwrap_or_else(|e| {
+        panic!("Failed to open accounts file {path:?}. Error: {e}.");
+    })
+}
+
+/// List every account in the accounts file (see env var `ACCOUNTS_PATH`),
+/// including the default one set by env var `RAW_SECRET`.
+///
+/// # Panics
+///
+/// Panics if the accounts file cannot be read or parsed.
+pub fn list_accounts() -> Vec<AccountInfo> {
+    ACCOUNTS.list().into_iter().map(AccountInfo::from).collect()
+}
+
+/// Enroll a new account with a random secret into the accounts file.
+///
+/// A running server doesn't see the change until it's restarted.
+///
+/// # Errors
+///
+/// Returns Err if the name or the profile is invalid, the account already exists,
+/// or the accounts file cannot be written.
+pub fn add_account(name: &str, profile: OtpauthProfile) -> crate::Result<Enrollment> {
+    ACCOUNTS.enroll(name, profile, None).map(Enrollment::from)
+}
+
+/// Accounts which have been imported from a migration payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Accounts which have been added.
+    pub imported: Vec<AccountInfo>,
+    /// Names of unsupported accounts (e.g. HOTP) and why they've been skipped.
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+/// Import the TOTP accounts of a migration payload (`otpauth-migration://`)
+/// into the accounts file, skipping unsupported ones (e.g. HOTP).
+///
+/// A running server doesn't see the change until it's restarted.
+///
+/// # Errors
+///
+/// Returns Err if any account is invalid or already exists (in which case
+/// nothing is imported), or if the accounts file cannot be written.
+pub fn import_accounts(payload: &crate::MigrationPayload) -> crate::Result<ImportReport> {
+    let created_at = crate::utils::now_secs();
+    let (supported, skipped): (Vec<_>, Vec<_>) = payload
+        .otp_parameters
+        .iter()
+        .partition(|parameters| parameters.check_supported().is_ok());
+    let skipped = skipped
+        .into_iter()
+        .filter_map(|p| p.check_supported().err().map(|e| (p.name.clone(), e)))
+        .collect();
+    let accounts = supported
+        .into_iter()
+        .map(|parameters| parameters.to_account(created_at))
+        .collect::<crate::Result<Vec<_>>>()?;
+    ACCOUNTS.import(accounts.clone())?;
+    Ok(ImportReport {
+        imported: accounts.into_iter().map(AccountInfo::from).collect(),
+        skipped,
+    })
+}
+
+/// Export accounts (every one if `names` is empty) as a migration payload,
+/// which can be imported by authenticator apps as a QR code.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::AccountNotFound`] if any account doesn't exist,
+/// or [`crate::Error::InvalidInput`] if any account has a period other than
+/// 30 seconds or 7 digits, which payloads cannot carry.
+pub fn export_accounts(names: &[String]) -> crate::Result<crate::MigrationPayload> {
+    let accounts = if names.is_empty() {
+        ACCOUNTS.list()
+    } else {
+        names
+            .iter()
+            .map(|name| ACCOUNTS.get(name))
+            .collect::<crate::Result<_>>()?
+    };
+    Ok(crate::MigrationPayload {
+        otp_parameters: accounts
+            .iter()
+            .map(crate::OtpParameters::try_from)
+            .collect::<crate::Result<_>>()?,
+        version: 1,
+        batch_size: 1,
+        batch_index: 0,
+        batch_id: rand::random(),
+    })
+}
+
+/// Remove the account from the accounts file.
+///
+/// A running server doesn't see the change until it's restarted.
+///
+/// # Errors
+///
+/// Returns Err if the account is read-only or doesn't exist,
+/// or if the accounts file cannot be written.
+pub fn remove_account(name: &str) -> crate::Result<()> {
+    ACCOUNTS.remove(name).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("totp-accounts-{}.json", rand::random::<u64>()))
+    }
+
+    fn open(path: &Path) -> AccountStore {
+        AccountStore::open(Some(path.to_owned()), b"0123456789abcdef".to_vec()).unwrap()
+    }
+
+    #[rstest]
+    #[case("alice", true)]
+    #[case("alice@example.com", true)]
+    #[case("svc-1_a.b", true)]
+    #[case("", false)]
+    #[case("a b", false)]
+    #[case("a/b", false)]
+    #[case("ä", false)]
+    #[case(&"a".repeat(65), false)]
+    fn test_validate_name(#[case] name: &str, #[case] valid: bool) {
+        assert_eq!(validate_name(name).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_account_store_lifecycle() {
+        let path = temp_path();
+        let store = open(&path);
+        assert_eq!(store.list().len(), 1);
+        assert_eq!(store.get("default").unwrap().secret, b"0123456789abcdef");
+
+        let profile = OtpauthProfile {
+            issuer: Some("Game Studio".to_owned()),
+            label: None,
+        };
+        let alice = store.enroll("alice", profile.clone(), None).unwrap();
+        assert_eq!(alice.secret.len(), SECRET_LEN);
+        assert!(matches!(
+            store.enroll("alice", OtpauthProfile::default(), None),
+            Err(crate::Error::AccountExists { .. })
+        ));
+        assert!(matches!(
+            store.enroll("default", OtpauthProfile::default(), None),
+            Err(crate::Error::AccountExists { .. })
+        ));
+        // Accounts are persisted, except for the default one.
+        let reopened = open(&path);
+        assert_eq!(reopened.get("alice").unwrap(), alice);
+        assert_eq!(reopened.list().len(), 2);
+
+        let rotated = store.rotate("alice").unwrap();
+        assert_ne!(rotated.secret, alice.secret);
+        assert_eq!(rotated.profile, profile);
+        assert_eq!(open(&path).get("alice").unwrap(), rotated);
+
+        store.remove("alice").unwrap();
+        assert!(matches!(
+            open(&path).get("alice"),
+            Err(crate::Error::AccountNotFound { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_account_store_import() {
+        let path = temp_path();
+        let store = open(&path);
+        let account =
+            |name: &str| Account::new(name, vec![1; SECRET_LEN], 0, OtpauthProfile::default());
+        store
+            .import(vec![account("alice"), account("bob")])
+            .unwrap();
+        assert_eq!(open(&path).get("bob").unwrap(), account("bob"));
+        // Nothing is imported if any account conflicts.
+        for accounts in [
+            vec![account("carol"), account("alice")],
+            vec![account("carol"), account("carol")],
+            vec![account("carol"), account("default")],
+            vec![account("carol"), account("a b")],
+        ] {
+            assert!(store.import(accounts).is_err());
+            assert!(store.get("carol").is_err());
+        }
+        assert_eq!(open(&path).list().len(), 3);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[rstest]
+    #[case(ConflictPolicy::Skip, &[], &["alice"])]
+    #[case(ConflictPolicy::Overwrite, &["alice"], &[])]
+    fn test_account_store_restore(
+        #[case] policy: ConflictPolicy,
+        #[case] updated: &[&str],
+        #[case] skipped: &[&str],
+    ) {
+        let path = temp_path();
+        let store = open(&path);
+        let account = |name: &str, secret: u8| {
+            Account::new(name, vec![secret; SECRET_LEN], 1, OtpauthProfile::default())
+        };
+        store
+            .import(vec![account("alice", 1), account("bob", 1)])
+            .unwrap();
+        let restored = || vec![account("alice", 2), account("bob", 1), account("carol", 1)];
+        let diff = store.restore(restored(), policy, true).unwrap();
+        assert!(diff.dry_run);
+        assert_eq!(diff.added, ["carol"]);
+        assert_eq!(diff.updated, updated);
+        assert_eq!(diff.unchanged, ["bob"]);
+        assert_eq!(diff.skipped, skipped);
+        assert!(store.get("carol").is_err());
+        let applied = store.restore(restored(), policy, false).unwrap();
+        assert_eq!(
+            applied,
+            ImportDiff {
+                dry_run: false,
+                ..diff
+            }
+        );
+        let store = open(&path);
+        assert_eq!(store.get("carol").unwrap().secret, vec![1; SECRET_LEN]);
+        let expected = if updated.is_empty() { 1 } else { 2 };
+        assert_eq!(
+            store.get("alice").unwrap().secret,
+            vec![expected; SECRET_LEN]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_account_store_restore_err() {
+        let store = AccountStore::open(None, b"0123456789abcdef".to_vec()).unwrap();
+        let account = |name: &str, secret: u8| {
+            Account::new(name, vec![secret; SECRET_LEN], 1, OtpauthProfile::default())
+        };
+        store.import(vec![account("alice", 1)]).unwrap();
+        let restore = |accounts, policy| store.restore(accounts, policy, false);
+        // Nothing is restored if any account conflicts.
+        let err = restore(
+            vec![account("carol", 1), account("alice", 2)],
+            ConflictPolicy::Fail,
+        );
+        assert!(matches!(err, Err(crate::Error::AccountExists { .. })));
+        let err = restore(
+            vec![account("carol", 1), account("carol", 1)],
+            ConflictPolicy::Skip,
+        );
+        assert!(matches!(err, Err(crate::Error::AccountExists { .. })));
+        let err = restore(
+            vec![account("carol", 1), account("default", 1)],
+            ConflictPolicy::Overwrite,
+        );
+        assert!(matches!(err, Err(crate::Error::AccountReadOnly { .. })));
+        assert!(store.get("carol").is_err());
+        assert_eq!(store.get("alice").unwrap().secret, vec![1; SECRET_LEN]);
+    }
+
+    #[test]
+    fn test_account_store_groups() {
+        let path = temp_path();
+        let store = open(&path);
+        assert!(matches!(
+            store.enroll("alice", OtpauthProfile::default(), Some("game-x")),
+            Err(crate::Error::GroupNotFound { .. })
+        ));
+        let policy = Policy {
+            digits: Some(8),
+            skew: Some(2),
+            ..Policy::default()
+        };
+        store
+            .put_group(Group {
+                name: "game-x".to_owned(),
+                policy,
+            })
+            .unwrap();
+        let alice = store
+            .enroll("alice", OtpauthProfile::default(), Some("game-x"))
+            .unwrap();
+        assert_eq!(alice.totp.digits, 8);
+        assert_eq!(store.policy(&alice).skew, 2);
+        // The account overrides the policy of its group.
+        let alice = store
+            .set_policy(
+                "alice",
+                Some("game-x".to_owned()),
+                Policy {
+                    skew: Some(0),
+                    ..Policy::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(store.policy(&alice).skew, 0);
+        assert_eq!(store.policy(&alice).totp.digits, 8);
+        // Groups are persisted along with their members.
+        let reopened = open(&path);
+        let groups = reopened.groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].members, ["alice"]);
+        assert_eq!(reopened.get("alice").unwrap(), alice);
+
+        assert!(matches!(
+            store.remove_group("game-x"),
+            Err(crate::Error::GroupInUse { .. })
+        ));
+        store.set_policy("alice", None, Policy::default()).unwrap();
+        store.remove_group("game-x").unwrap();
+        assert!(matches!(
+            open(&path).get_group("game-x"),
+            Err(crate::Error::GroupNotFound { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_account_store_approvals() {
+        let path = temp_path();
+        let store = open(&path);
+        let approval = |required, now| Approval::new("ops".to_owned(), required, None, now, 60);
+        assert!(matches!(
+            store.create_approval(approval(2, 100), 100),
+            Err(crate::Error::GroupNotFound { .. })
+        ));
+        store
+            .put_group(Group {
+                name: "ops".to_owned(),
+                policy: Policy::default(),
+            })
+            .unwrap();
+        let mut members = Vec::new();
+        for name in ["alice", "bob"] {
+            members.push(
+                store
+                    .enroll(name, OtpauthProfile::default(), Some("ops"))
+                    .unwrap(),
+            );
+        }
+        assert!(matches!(
+            store.create_approval(approval(3, 100), 100),
+            Err(crate::Error::InvalidInput(_))
+        ));
+        let expired = store.create_approval(approval(2, 100), 100).unwrap();
+        let pending = store.create_approval(approval(2, 160), 160).unwrap();
+        // Expired approvals are forgotten when others are created.
+        assert!(matches!(
+            store.get_approval(&expired.id),
+            Err(crate::Error::ApprovalNotFound { .. })
+        ));
+        store.approve(&pending.id, &members[0], 161).unwrap();
+        // Approvals are persisted along with their approvers.
+        let reopened = open(&path);
+        assert_eq!(
+            reopened.get_approval(&pending.id).unwrap().approvers,
+            ["alice"]
+        );
+        let approved = reopened.approve(&pending.id, &members[1], 162).unwrap();
+        assert_eq!(approved.approvers, ["alice", "bob"]);
+        assert_eq!(approved.approved_at, Some(162));
+        assert!(matches!(
+            store.approve("missing", &members[1], 162),
+            Err(crate::Error::ApprovalNotFound { .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[rstest]
+    #[case("default")]
+    #[case("missing")]
+    fn test_account_store_unchangeable(#[case] name: &str) {
+        let store = AccountStore::open(None, b"0123456789abcdef".to_vec()).unwrap();
+        assert!(store.rotate(name).is_err());
+        assert!(store.remove(name).is_err());
+        assert!(store.get("default").is_ok());
+    }
+
+    #[test]
+    fn test_account_store_invalid_file() {
+        let path = temp_path();
+        std::fs::write(
+            &path,
+            r#"{"accounts":[{"name":"a","secret":"!","created_at":0}]}"#,
+        )
+        .unwrap();
+        assert!(AccountStore::open(Some(path.clone()), Vec::new()).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
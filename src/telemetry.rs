@@ -0,0 +1,465 @@
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkError;
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use std::sync::{LazyLock, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+
+/// Env var which is used to set [`TelemetryConfig::exporter`].
+const TELEMETRY_EXPORTER: &str = "TELEMETRY_EXPORTER";
+/// Env var which is used to set [`TelemetryConfig::logs`].
+const TELEMETRY_LOGS: &str = "TELEMETRY_LOGS";
+/// Env var which is used to set [`TelemetryConfig::metrics`].
+const TELEMETRY_METRICS: &str = "TELEMETRY_METRICS";
+/// Env var which is used to set [`TelemetryConfig::traces`].
+const TELEMETRY_TRACES: &str = "TELEMETRY_TRACES";
+/// Env var which is used to set [`TelemetryConfig::sampler_ratio`].
+const TELEMETRY_SAMPLER_RATIO: &str = "TELEMETRY_SAMPLER_RATIO";
+/// Env var which is used to set [`TelemetryConfig::log_format`].
+const LOG_FORMAT: &str = "LOG_FORMAT";
+/// Standard OpenTelemetry env var of the OTLP endpoint.
+///
+/// If it has been set while [`TELEMETRY_EXPORTER`] hasn't,
+/// [`ExporterKind::OtlpGrpc`] will be used.
+const OTEL_EXPORTER_OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Where telemetry signals (logs, metrics and traces) are exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExporterKind {
+    /// Don't export telemetry signals (fmt logs are still written to stdout).
+    None,
+    /// Export by OTLP over gRPC (tonic).
+    OtlpGrpc,
+    /// Export by OTLP over HTTP with protobuf payloads.
+    OtlpHttp,
+    /// Print telemetry signals to stdout (for debugging).
+    Stdout,
+}
+
+impl std::str::FromStr for ExporterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "otlp-grpc" | "grpc" => Ok(Self::OtlpGrpc),
+            "otlp-http" | "http" => Ok(Self::OtlpHttp),
+            "stdout" => Ok(Self::Stdout),
+            other => Err(format!(
+                "{TELEMETRY_EXPORTER} must be one of none, otlp-grpc, otlp-http, stdout (the given one is {other:?})."
+            )),
+        }
+    }
+}
+
+/// Output format of the logs written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// Human-readable text.
+    Text,
+    /// Newline-delimited JSON objects.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "{LOG_FORMAT} must be either text or json (the given one is {other:?})."
+            )),
+        }
+    }
+}
+
+/// Telemetry configuration read from env vars.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TelemetryConfig {
+    /// Which exporter is used (env var `TELEMETRY_EXPORTER`).
+    pub(crate) exporter: ExporterKind,
+    /// Whether logs are exported (env var `TELEMETRY_LOGS`).
+    pub(crate) logs: bool,
+    /// Whether metrics are exported (env var `TELEMETRY_METRICS`).
+    pub(crate) metrics: bool,
+    /// Whether traces are exported (env var `TELEMETRY_TRACES`).
+    pub(crate) traces: bool,
+    /// Ratio of root spans to sample, within `0.0..=1.0` (env var `TELEMETRY_SAMPLER_RATIO`).
+    pub(crate) sampler_ratio: f64,
+    /// Format of logs written to stdout (env var `LOG_FORMAT`).
+    pub(crate) log_format: LogFormat,
+}
+
+/// Telemetry configuration.
+///
+/// # Panics
+///
+/// Panics when any of the related env vars cannot be parsed.
+pub(crate) static TELEMETRY_CONFIG: LazyLock<TelemetryConfig> =
+    LazyLock::new(init_telemetry_config);
+
+fn init_telemetry_config() -> TelemetryConfig {
+    let exporter = match std::env::var(TELEMETRY_EXPORTER) {
+        Ok(value) => value.parse().unwrap_or_else(|e: String| panic!("{e}")),
+        Err(_) if std::env::var(OTEL_EXPORTER_OTLP_ENDPOINT).is_ok() => ExporterKind::OtlpGrpc,
+        Err(_) => ExporterKind::None,
+    };
+    let sampler_ratio = std::env::var(TELEMETRY_SAMPLER_RATIO).map_or(1.0, |value| {
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| (0.0..=1.0).contains(v))
+            .unwrap_or_else(|| {
+                panic!("{TELEMETRY_SAMPLER_RATIO} must be a number between 0.0 and 1.0.")
+            })
+    });
+    let log_format = std::env::var(LOG_FORMAT).map_or(LogFormat::Text, |value| {
+        value.parse().unwrap_or_else(|e: String| panic!("{e}"))
+    });
+    TelemetryConfig {
+        exporter,
+        logs: parse_bool_var(TELEMETRY_LOGS),
+        metrics: parse_bool_var(TELEMETRY_METRICS),
+        traces: parse_bool_var(TELEMETRY_TRACES),
+        sampler_ratio,
+        log_format,
+    }
+}
+
+/// Parse a boolean env var, which defaults to `true` if it hasn't been set.
+fn parse_bool_var(key: &str) -> bool {
+    let Ok(value) = std::env::var(key) else {
+        return true;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => true,
+        "false" | "0" | "off" | "no" => false,
+        _ => panic!("{key} must be a boolean (true or false)."),
+    }
+}
+
+/// OpenTelemetry SDK providers, which are only set when the signal is enabled.
+#[derive(Debug, Default)]
+struct OtelSdkProviders {
+    logger: Option<SdkLoggerProvider>,
+    meter: Option<SdkMeterProvider>,
+    tracer: Option<SdkTracerProvider>,
+}
+
+impl OtelSdkProviders {
+    fn force_flush(&self) -> Result<(), OTelSdkError> {
+        if let Some(logger) = &self.logger {
+            logger.force_flush()?;
+        }
+        if let Some(meter) = &self.meter {
+            meter.force_flush()?;
+        }
+        if let Some(tracer) = &self.tracer {
+            tracer.force_flush()?;
+        }
+        Ok(())
+    }
+
+    fn shutdown(&self) -> Result<(), OTelSdkError> {
+        if let Some(logger) = &self.logger {
+            logger.shutdown()?;
+        }
+        if let Some(meter) = &self.meter {
+            meter.shutdown()?;
+        }
+        if let Some(tracer) = &self.tracer {
+            tracer.shutdown()?;
+        }
+        Ok(())
+    }
+}
+
+static OTEL_SDK_PROVIDERS: OnceLock<OtelSdkProviders> = OnceLock::new();
+
+/// Initialize the global tracing subscriber and OpenTelemetry SDK providers.
+///
+/// Which exporter and signals are used is determined by env vars `TELEMETRY_EXPORTER`,
+/// `TELEMETRY_LOGS`, `TELEMETRY_METRICS`, `TELEMETRY_TRACES` and `TELEMETRY_SAMPLER_RATIO`.
+/// When `on_lambda` is true, fmt logs are written without ANSI colors
+/// and timestamps, since AWS `CloudWatch` Logs already records the time.
+///
+/// # Panics
+///
+/// Panics when it's called more than once, or when any exporter cannot be built.
+pub fn init_telemetry(on_lambda: bool) {
+    use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+    use tracing_opentelemetry::MetricsLayer;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let config = &*TELEMETRY_CONFIG;
+    let providers = init_opentelemetry(config);
+
+    let otel_log_layer = providers
+        .logger
+        .as_ref()
+        .map(OpenTelemetryTracingBridge::new);
+    let otel_metrics_layer = providers.meter.clone().map(MetricsLayer::new);
+    let otel_trace_layer = providers.tracer.as_ref().map(|tracer_provider| {
+        use opentelemetry::trace::TracerProvider;
+        let tracer = tracer_provider.tracer(crate::CRATE_NAME);
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    });
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or(format!("{}={}", crate::CRATE_NAME, LevelFilter::INFO).into()),
+        )
+        .with(fmt_layer(config.log_format, on_lambda))
+        .with(otel_log_layer)
+        .with(otel_metrics_layer)
+        .with(otel_trace_layer)
+        .init();
+
+    OTEL_SDK_PROVIDERS
+        .set(providers)
+        .unwrap_or_else(|_| panic!("telemetry has already been initialized."));
+    tracing::debug!(?config, "Telemetry has been initialized.");
+}
+
+/// Flush all the telemetry signals that are buffered by OpenTelemetry SDK providers.
+///
+/// It does nothing if [`init_telemetry`] hasn't been called.
+pub fn flush_telemetry() {
+    if let Some(providers) = OTEL_SDK_PROVIDERS.get() {
+        providers.force_flush().unwrap_or_else(|e| {
+            tracing::warn!("failed to force_flush opentelemetry sdk providers. error: {e}");
+        });
+    }
+}
+
+/// Flush and shut down OpenTelemetry SDK providers.
+///
+/// It should be called once before the process exits.
+/// It does nothing if [`init_telemetry`] hasn't been called.
+pub fn shutdown_telemetry() {
+    if let Some(providers) = OTEL_SDK_PROVIDERS.get() {
+        providers.shutdown().unwrap_or_else(|e| {
+            tracing::warn!("failed to shutdown opentelemetry sdk providers. error: {e}");
+        });
+    }
+}
+
+fn fmt_layer<S>(
+    log_format: LogFormat,
+    on_lambda: bool,
+) -> Box<dyn tracing_subscriber::Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    use tracing_subscriber::Layer;
+    let layer = tracing_subscriber::fmt::layer();
+    // There's decoding issue on AWS Lambda if ansi is enabled,
+    // and time info already exists in AWS CloudWatch Logs.
+    match (log_format, on_lambda) {
+        (LogFormat::Text, false) => layer.boxed(),
+        (LogFormat::Text, true) => layer.with_ansi(false).without_time().boxed(),
+        (LogFormat::Json, false) => layer.json().boxed(),
+        (LogFormat::Json, true) => layer.json().with_ansi(false).without_time().boxed(),
+    }
+}
+
+fn init_opentelemetry(config: &TelemetryConfig) -> OtelSdkProviders {
+    use opentelemetry::KeyValue;
+    use opentelemetry_semantic_conventions as semcon;
+
+    if config.exporter == ExporterKind::None {
+        return OtelSdkProviders::default();
+    }
+    let resource = Resource::builder()
+        .with_attribute(KeyValue::new(
+            semcon::resource::SERVICE_NAME,
+            crate::CRATE_NAME,
+        ))
+        .with_attribute(KeyValue::new(
+            semcon::resource::SERVICE_VERSION,
+            crate::PKG_VERSION,
+        ))
+        .build();
+    init_propagator();
+    OtelSdkProviders {
+        logger: config.logs.then(|| init_logger(config.exporter, &resource)),
+        meter: config
+            .metrics
+            .then(|| init_meter(config.exporter, &resource)),
+        tracer: config
+            .traces
+            .then(|| init_tracer(config.exporter, config.sampler_ratio, &resource)),
+    }
+}
+
+fn init_logger(exporter: ExporterKind, resource: &Resource) -> SdkLoggerProvider {
+    use opentelemetry_otlp::LogExporter;
+    let builder = SdkLoggerProvider::builder().with_resource(resource.clone());
+    let builder = match exporter {
+        ExporterKind::OtlpGrpc => builder.with_batch_exporter(
+            LogExporter::builder()
+                .with_tonic()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build LogExporter. error: {e}")),
+        ),
+        ExporterKind::OtlpHttp => builder.with_batch_exporter(
+            LogExporter::builder()
+                .with_http()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build LogExporter. error: {e}")),
+        ),
+        ExporterKind::Stdout => {
+            builder.with_simple_exporter(opentelemetry_stdout::LogExporter::default())
+        }
+        ExporterKind::None => builder,
+    };
+    builder.build()
+}
+
+fn init_meter(exporter: ExporterKind, resource: &Resource) -> SdkMeterProvider {
+    use opentelemetry_otlp::MetricExporter;
+    let builder = SdkMeterProvider::builder().with_resource(resource.clone());
+    let builder = match exporter {
+        ExporterKind::OtlpGrpc => builder.with_periodic_exporter(
+            MetricExporter::builder()
+                .with_tonic()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build MetricExporter. error: {e}")),
+        ),
+        ExporterKind::OtlpHttp => builder.with_periodic_exporter(
+            MetricExporter::builder()
+                .with_http()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build MetricExporter. error: {e}")),
+        ),
+        ExporterKind::Stdout => {
+            builder.with_periodic_exporter(opentelemetry_stdout::MetricExporter::default())
+        }
+        ExporterKind::None => builder,
+    };
+    let meter_provider = builder.build();
+    opentelemetry::global::set_meter_provider(meter_provider.clone());
+    meter_provider
+}
+
+fn init_tracer(
+    exporter: ExporterKind,
+    sampler_ratio: f64,
+    resource: &Resource,
+) -> SdkTracerProvider {
+    use opentelemetry_otlp::SpanExporter;
+    let builder = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            sampler_ratio,
+        ))))
+        .with_resource(resource.clone());
+    let builder = match exporter {
+        ExporterKind::OtlpGrpc => builder.with_batch_exporter(
+            SpanExporter::builder()
+                .with_tonic()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build SpanExporter. error: {e}")),
+        ),
+        ExporterKind::OtlpHttp => builder.with_batch_exporter(
+            SpanExporter::builder()
+                .with_http()
+                .build()
+                .unwrap_or_else(|e| panic!("failed to build SpanExporter. error: {e}")),
+        ),
+        ExporterKind::Stdout => {
+            builder.with_simple_exporter(opentelemetry_stdout::SpanExporter::default())
+        }
+        ExporterKind::None => builder,
+    };
+    let tracer_provider = builder.build();
+    opentelemetry::global::set_tracer_provider(tracer_provider.clone());
+    tracer_provider
+}
+
+fn init_propagator() {
+    use opentelemetry::propagation::{TextMapCompositePropagator, TextMapPropagator};
+    let propagators: Vec<Box<dyn TextMapPropagator + Send + Sync>> = vec![
+        Box::new(opentelemetry_sdk::propagation::TraceContextPropagator::new()),
+        Box::new(opentelemetry_sdk::propagation::BaggagePropagator::new()),
+    ];
+    let composite_propagator = TextMapCompositePropagator::new(propagators);
+    opentelemetry::global::set_text_map_propagator(composite_propagator);
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_telemetry_config_default() {
+        assert!(std::env::var(TELEMETRY_EXPORTER).is_err());
+        assert!(std::env::var(OTEL_EXPORTER_OTLP_ENDPOINT).is_err());
+        let expected = TelemetryConfig {
+            exporter: ExporterKind::None,
+            logs: true,
+            metrics: true,
+            traces: true,
+            sampler_ratio: 1.0,
+            log_format: LogFormat::Text,
+        };
+        assert_eq!(*TELEMETRY_CONFIG, expected);
+    }
+
+    #[test]
+    fn test_telemetry_config_otlp_endpoint() {
+        unsafe { std::env::set_var(OTEL_EXPORTER_OTLP_ENDPOINT, "http://localhost:4317") }
+        assert_eq!(TELEMETRY_CONFIG.exporter, ExporterKind::OtlpGrpc);
+    }
+
+    #[rstest]
+    #[case("none", ExporterKind::None)]
+    #[case("otlp-grpc", ExporterKind::OtlpGrpc)]
+    #[case("OTLP-HTTP", ExporterKind::OtlpHttp)]
+    #[case("stdout", ExporterKind::Stdout)]
+    fn test_telemetry_exporter_var(#[case] value: &str, #[case] expected: ExporterKind) {
+        unsafe { std::env::set_var(TELEMETRY_EXPORTER, value) }
+        assert_eq!(TELEMETRY_CONFIG.exporter, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "TELEMETRY_EXPORTER must be one of")]
+    fn test_telemetry_exporter_var_panic() {
+        unsafe { std::env::set_var(TELEMETRY_EXPORTER, "zipkin") }
+        let _ = &*TELEMETRY_CONFIG;
+    }
+
+    #[rstest]
+    #[case("false", false)]
+    #[case("0", false)]
+    #[case("true", true)]
+    fn test_telemetry_signal_vars(#[case] value: &str, #[case] expected: bool) {
+        unsafe { std::env::set_var(TELEMETRY_METRICS, value) }
+        assert_eq!(TELEMETRY_CONFIG.metrics, expected);
+        assert!(TELEMETRY_CONFIG.logs);
+        assert!(TELEMETRY_CONFIG.traces);
+    }
+
+    #[rstest]
+    #[case("-0.1")]
+    #[case("1.5")]
+    #[case("abc")]
+    #[should_panic(expected = "TELEMETRY_SAMPLER_RATIO must be a number between 0.0 and 1.0")]
+    fn test_telemetry_sampler_ratio_panic(#[case] value: &str) {
+        unsafe { std::env::set_var(TELEMETRY_SAMPLER_RATIO, value) }
+        let _ = &*TELEMETRY_CONFIG;
+    }
+
+    #[test]
+    fn test_log_format_json() {
+        unsafe { std::env::set_var(LOG_FORMAT, "json") }
+        assert_eq!(TELEMETRY_CONFIG.log_format, LogFormat::Json);
+    }
+}
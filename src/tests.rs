@@ -29,6 +29,27 @@ async fn setup_server(
     (addr, tx, handle)
 }
 
+/// API key of [`authenticated_client`], which can access every account.
+const TEST_API_KEY: &str = "test-key";
+
+/// Enable authentication by [`TEST_API_KEY`], and return a client which sends it.
+///
+/// It must be called before the server is set up.
+#[expect(unsafe_code)]
+fn authenticated_client() -> reqwest::Client {
+    use sha2::{Digest, Sha256};
+    let api_keys = format!("test:{}", hex::encode(Sha256::digest(TEST_API_KEY)));
+    unsafe { std::env::set_var("API_KEYS", api_keys) }
+    let headers = reqwest::header::HeaderMap::from_iter([(
+        reqwest::header::HeaderName::from_static("x-api-key"),
+        reqwest::header::HeaderValue::from_static(TEST_API_KEY),
+    )]);
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap()
+}
+
 /// Wait until the server is ready.
 async fn wait_until_ready(addr: SocketAddr) {
     use std::time::Duration;
@@ -95,6 +116,20 @@ async fn test_health() {
     let _ = handle.await.unwrap();
 }
 
+#[tokio::test]
+async fn test_health_draining() {
+    let (addr, tx, handle) = setup_server(app()).await;
+    crate::shutdown::start_draining();
+    let response = reqwest::Client::new()
+        .get(format!("http://{addr}/health"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
 #[rstest]
 #[tokio::test]
 #[case("12345")]
@@ -154,24 +189,1303 @@ async fn test_timeout_middleware() {
 async fn test_too_many_requests() {
     let (addr, tx, handle) = setup_server(app()).await;
     let client = reqwest::Client::new();
-    // There's a health check request in `setup_server()` above,
-    // which already counts 1 request, thus `- 1` is used here.
-    for _ in 0..(*crate::RATE_LIMIT - 1) {
+    for _ in 0..*crate::RATE_LIMIT {
+        let response = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+    for _ in 0..3 {
+        let response = client.get(format!("http://{addr}/")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let header = |name: &str| response.headers()[name].to_str().unwrap().to_owned();
+        let retry_after: u64 = header("retry-after").parse().unwrap();
+        assert!((1..=30).contains(&retry_after));
+        assert_eq!(header("ratelimit-limit"), crate::RATE_LIMIT.to_string());
+        assert_eq!(header("ratelimit-remaining"), "0");
+        assert_eq!(header("ratelimit-reset"), retry_after.to_string());
+        let body = response.text().await.unwrap();
+        assert!(body.starts_with("Error: too many requests"), "{body}");
+    }
+    // Health checks are exempt from rate limiting.
+    let response = client
+        .get(format!("http://{addr}/health"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_too_many_requests_by_account() {
+    unsafe {
+        std::env::set_var("RATE_LIMIT_POLICIES", "per-account:burst=2,key=account");
+        std::env::set_var("RATE_LIMIT_ROUTES", "/=per-account");
+    }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    // The header doesn't matter, since the body names the account.
+    let send = |account: &'static str| {
+        client
+            .post(format!("http://{addr}/"))
+            .header("x-totp-account", "someone-else")
+            .json(&crate::InputToken::new("000000").with_account(account))
+            .send()
+    };
+    for _ in 0..2 {
+        let response = send("alice").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+    let response = send("alice").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["ratelimit-limit"], "2");
+    // Another account from the same IP isn't affected.
+    let response = send("bob").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_too_many_requests_by_api_key() {
+    use sha2::{Digest, Sha256};
+    let hash = |key: &str| hex::encode(Sha256::digest(key));
+    let api_keys = format!("game:{},other:{}", hash("game-key"), hash("other-key"));
+    unsafe {
+        std::env::set_var("API_KEYS", api_keys);
+        std::env::set_var("RATE_LIMIT_POLICIES", "per-key:burst=2,key=api-key");
+        std::env::set_var("RATE_LIMIT_ROUTES", "/=per-key");
+    }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let send = |api_key: &'static str| {
+        client
+            .get(format!("http://{addr}/"))
+            .header("x-api-key", api_key)
+            .send()
+    };
+    for _ in 0..2 {
+        let response = send("game-key").await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let response = send("game-key").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    // Made-up keys are rejected rather than given limits of their own.
+    let response = send("made-up-key").await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    // Another key from the same IP isn't affected.
+    let response = send("other-key").await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_too_many_requests_unauthenticated() {
+    use sha2::{Digest, Sha256};
+    let api_keys = format!("game:{}", hex::encode(Sha256::digest("game-key")));
+    unsafe {
+        std::env::set_var("API_KEYS", api_keys);
+        std::env::set_var("RATE_LIMIT_POLICIES", "per-key:burst=100,key=api-key");
+        std::env::set_var("RATE_LIMIT_ROUTES", "/v1/verify=per-key");
+    }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let send = |path: &'static str| {
+        client
+            .post(format!("http://{addr}{path}"))
+            .header("x-api-key", "made-up-key")
+            .json(&crate::InputToken::new("000000"))
+            .send()
+    };
+    // Routes keyed by IP and those keyed by API key are limited by IP before
+    // authentication, so that guessing keys is limited as well.
+    for path in ["/", "/v1/verify"] {
+        for _ in 0..*crate::RATE_LIMIT {
+            let response = send(path).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = send(path).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_too_many_requests_behind_proxy() {
+    unsafe { std::env::set_var("TRUSTED_PROXIES", "127.0.0.1") }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let send = |client_ip: &'static str| {
+        client
+            .get(format!("http://{addr}/"))
+            .header("x-forwarded-for", client_ip)
+            .send()
+    };
+    for _ in 0..*crate::RATE_LIMIT {
+        let response = send("192.0.2.1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let response = send("192.0.2.1").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    // Another client behind the same proxy isn't affected.
+    let response = send("192.0.2.2").await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_api_key_auth() {
+    use sha2::{Digest, Sha256};
+    let hash = |key: &str| hex::encode(Sha256::digest(key));
+    let api_keys = format!(
+        "game:{}:default,other:{}:alice",
+        hash("game-key"),
+        hash("other-key")
+    );
+    unsafe { std::env::set_var("API_KEYS", api_keys) }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let send = |api_key: Option<&'static str>| {
+        let request = reqwest::Client::new()
+            .post(format!("http://{addr}"))
+            .json(&crate::InputToken::new("000000"));
+        match api_key {
+            Some(key) => request.header("x-api-key", key),
+            None => request,
+        }
+        .send()
+    };
+    for api_key in [None, Some("wrong-key")] {
+        let response = send(api_key).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = response.text().await.unwrap();
+        assert!(body.starts_with("Error: authentication failed"), "{body}");
+    }
+    // The key which isn't allowed to access the default account.
+    let response = send(Some("other-key")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    // Authenticated, so the TOTP itself is checked.
+    let response = send(Some("game-key")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.text().await.unwrap(), "Error: invalid TOTP");
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_hmac_auth() {
+    unsafe { std::env::set_var("HMAC_KEYS", "ci:ci-secret") }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let token = crate::try_get_token(&crate::VEC_SECRET).unwrap();
+    let body = serde_json::to_vec(&crate::InputToken::new(token)).unwrap();
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut message = format!("{timestamp}.POST./.").into_bytes();
+    message.extend_from_slice(&body);
+    let signature = format!(
+        "sha256={}",
+        crate::webhook::hmac_sha256_hex(b"ci-secret", &message)
+    );
+    let send = || {
+        client
+            .post(format!("http://{addr}/"))
+            .header("content-type", "application/json")
+            .header("x-totp-key-id", "ci")
+            .header("x-totp-timestamp", timestamp.to_string())
+            .header("x-totp-signature", &signature)
+            .body(body.clone())
+            .send()
+    };
+    let response = send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // The same signed request cannot be replayed.
+    let response = send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Error: authentication failed: replayed request"
+    );
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_role_based_access() {
+    use sha2::{Digest, Sha256};
+    let hash = |key: &str| hex::encode(Sha256::digest(key));
+    let api_keys = format!(
+        "game:{}:alice,audit:{},ops:{}",
+        hash("game-key"),
+        hash("audit-key"),
+        hash("ops-key")
+    );
+    unsafe {
+        std::env::set_var("API_KEYS", api_keys);
+        std::env::set_var("ROLE_BINDINGS", "game=verifier,audit=auditor,ops=admin");
+    }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let send = |method: reqwest::Method, path: &str, api_key: &str| {
+        client
+            .request(method, format!("http://{addr}/v1{path}"))
+            .header("x-api-key", api_key)
+            .send()
+    };
+    let verify = client
+        .post(format!("http://{addr}/v1/verify"))
+        .header("x-api-key", "game-key")
+        .json(&crate::InputToken::new("000000").with_account("alice"))
+        .send();
+    assert_eq!(verify.await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    // Verifiers can't manage accounts, nor read the audit log.
+    let response = send(reqwest::Method::DELETE, "/admin/accounts/alice", "game-key");
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.text().await.unwrap(),
+        r#"Error: caller "game" isn't granted the "manage" permission"#
+    );
+    let response = send(reqwest::Method::GET, "/admin/audit", "game-key");
+    assert_eq!(response.await.unwrap().status(), StatusCode::FORBIDDEN);
+
+    // Auditors can read the audit log, where denials are recorded.
+    let response = send(reqwest::Method::GET, "/admin/audit?limit=2", "audit-key");
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let records: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(records[0]["action"], "deny");
+    assert_eq!(records[0]["account"], "*");
+    assert_eq!(records[1]["action"], "deny");
+    assert_eq!(records[1]["account"], "alice");
+    let response = send(
+        reqwest::Method::POST,
+        "/admin/accounts/alice/reset",
+        "audit-key",
+    );
+    assert_eq!(response.await.unwrap().status(), StatusCode::FORBIDDEN);
+
+    // Admins can do anything.
+    let response = send(
+        reqwest::Method::POST,
+        "/admin/accounts/alice/reset",
+        "ops-key",
+    );
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+/// Send a CORS preflight request for `POST /` from `origin`.
+async fn preflight(addr: SocketAddr, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(reqwest::Method::OPTIONS, format!("http://{addr}/"))
+        .header("origin", origin)
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "content-type,x-api-key")
+        .send()
+        .await
+        .unwrap()
+}
+
+#[expect(unsafe_code)]
+fn set_cors_env() {
+    unsafe {
+        std::env::set_var(
+            "CORS_ALLOWED_ORIGINS",
+            "https://tools.example.com,https://*.internal.example.com",
+        );
+        std::env::set_var("CORS_MAX_AGE", "120");
+        // Preflight requests are answered without credentials.
+        std::env::set_var("API_KEYS", format!("game:{}", "0".repeat(64)));
+    }
+}
+
+#[rstest]
+#[case::server(app as fn() -> axum::Router)]
+#[case::lambda(crate::lambda::app_aws_lambda as fn() -> axum::Router)]
+#[tokio::test]
+async fn test_cors_preflight(#[case] router: fn() -> axum::Router) {
+    set_cors_env();
+    let (addr, tx, handle) = setup_server(router()).await;
+    for origin in [
+        "https://tools.example.com",
+        "https://a.internal.example.com",
+    ] {
+        let response = preflight(addr, origin).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], origin);
+        assert_eq!(headers["access-control-max-age"], "120");
+        let methods = headers["access-control-allow-methods"].to_str().unwrap();
+        assert!(methods.contains("POST"), "{methods}");
+        let allowed = headers["access-control-allow-headers"].to_str().unwrap();
+        assert!(allowed.contains("x-api-key"), "{allowed}");
+        assert!(!headers.contains_key("access-control-allow-credentials"));
+    }
+    for origin in ["https://evil.example.com", "https://internal.example.com"] {
+        let response = preflight(addr, origin).await;
+        assert!(
+            !response
+                .headers()
+                .contains_key("access-control-allow-origin")
+        );
+    }
+    // Actual requests get CORS headers as well.
+    let response = reqwest::Client::new()
+        .get(format!("http://{addr}/health"))
+        .header("origin", "https://tools.example.com")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://tools.example.com"
+    );
+    let exposed = headers["access-control-expose-headers"].to_str().unwrap();
+    assert!(exposed.contains("retry-after"), "{exposed}");
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cors_disabled() {
+    let (addr, tx, handle) = setup_server(app()).await;
+    let response = preflight(addr, "https://tools.example.com").await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(
+        !response
+            .headers()
+            .contains_key("access-control-allow-origin")
+    );
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_root_is_deprecated() {
+    let (addr, tx, handle) = setup_server(app()).await;
+    let response = reqwest::Client::new()
+        .post(format!("http://{addr}"))
+        .json(&crate::InputToken::new("12345"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()["deprecation"], "true");
+    assert_eq!(
+        response.headers()["link"],
+        r#"</v1/verify>; rel="successor-version""#
+    );
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_v1_account_lifecycle() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("alice-{}", rand::random::<u32>());
+    let current_token = |enrollment: &Value| {
+        let secret = totp_rs::Secret::Encoded(enrollment["secret"].as_str().unwrap().to_owned());
+        crate::try_get_token(&secret.to_bytes().unwrap()).unwrap()
+    };
+    let next_token = |enrollment: &Value| {
+        let secret = totp_rs::Secret::Encoded(enrollment["secret"].as_str().unwrap().to_owned());
+        let totp = crate::totp::new_totp_with_label(
+            secret.to_bytes().unwrap(),
+            &crate::TotpParams::default(),
+            1,
+            crate::PKG_NAME,
+            &account,
+        );
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        totp.generate(now + 30)
+    };
+
+    // Enroll a new account.
+    let response = client
+        .post(url("/enrollment"))
+        .json(&json!({ "account": account, "issuer": "a:b" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client
+        .post(url("/enrollment"))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("account={account}&issuer=Game+Studio"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let enrollment: Value = response.json().await.unwrap();
+    assert!(
+        enrollment["otpauth_url"]
+            .as_str()
+            .unwrap()
+            .starts_with(&format!("otpauth://totp/Game%20Studio:{account}?"))
+    );
+    let response = client
+        .post(url("/enrollment"))
+        .json(&json!({ "account": account }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = client.get(url(&format!("/accounts/{account}"))).send();
+    let info: Value = response.await.unwrap().json().await.unwrap();
+    assert_eq!(info["issuer"], "Game Studio");
+
+    // Verify a code of the account, which is accepted once,
+    // and exchange the next one for a session.
+    let input_token = crate::InputToken::new(current_token(&enrollment)).with_account(&account);
+    let response = client.post(url("/verify")).json(&input_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    let response = client.post(url("/sessions")).json(&input_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let input_token = crate::InputToken::new(next_token(&enrollment)).with_account(&account);
+    let response = client.post(url("/sessions")).json(&input_token).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let session: Value = response.json().await.unwrap();
+    let response = client
+        .get(url("/sessions"))
+        .bearer_auth(session["token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let claims: Value = response.json().await.unwrap();
+    assert_eq!(claims["sub"], account.as_str());
+    let response = client
+        .get(url("/sessions"))
+        .bearer_auth("forged.token")
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    // Rotate the secret, and then remove the account.
+    let rotate = format!("/admin/accounts/{account}/rotate");
+    let response = client.post(url(&rotate)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let rotated: Value = response.json().await.unwrap();
+    assert_ne!(rotated["secret"], enrollment["secret"]);
+    let remove = format!("/admin/accounts/{account}");
+    let response = client.delete(url(&remove)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = client.post(url("/verify")).json(&input_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    // The default account is read-only.
+    let response = client.delete(url("/admin/accounts/default")).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::CONFLICT);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[rstest]
+#[case("application/x-www-form-urlencoded", "token=12345", 400)]
+#[case("text/plain", "12345", 400)]
+#[case("text/plain", "000000", 401)]
+#[case("application/json", "{", 400)]
+#[case("application/xml", "<token/>", 415)]
+#[tokio::test]
+async fn test_verify_content_types(
+    #[case] content_type: &str,
+    #[case] body: &'static str,
+    #[case] expected: u16,
+) {
+    let (addr, tx, handle) = setup_server(app()).await;
+    let response = reqwest::Client::new()
+        .post(format!("http://{addr}/v1/verify"))
+        .header("content-type", content_type)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), expected);
+    assert!(response.text().await.unwrap().starts_with("Error: "));
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[rstest]
+#[case("/default/qr.png", 200, Some("image/png"))]
+#[case("/default/qr.svg?size=512&ec=H", 200, Some("image/svg+xml"))]
+#[case("/default/qr.png?size=8", 400, None)]
+#[case("/default/qr.png?ec=x", 400, None)]
+#[case("/default/qr.gif", 404, None)]
+#[case("/nobody/qr.svg", 404, None)]
+#[tokio::test]
+async fn test_account_qr(
+    #[case] path: &str,
+    #[case] expected: u16,
+    #[case] content_type: Option<&str>,
+) {
+    let (addr, tx, handle) = setup_server(app()).await;
+    let response = reqwest::get(format!("http://{addr}/v1/accounts{path}"))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), expected);
+    if let Some(content_type) = content_type {
+        assert_eq!(response.headers()["content-type"], content_type);
+        assert_eq!(response.headers()["cache-control"], "no-store");
+        assert!(!response.bytes().await.unwrap().is_empty());
+    }
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_bundle() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1/admin{path}");
+    let account = format!("bundle-{}", rand::random::<u32>());
+    // Unauthenticated callers can't export anything.
+    let response = reqwest::Client::new()
+        .get(url("/export?format=csv&encrypt=false"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = client
+        .post(format!("http://{addr}/v1/enrollment"))
+        .json(&json!({ "account": account }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let enrollment: Value = response.json().await.unwrap();
+
+    // Export, which is encrypted unless told otherwise.
+    let response = client.get(url("/export")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client.get(url("/export?format=csv&encrypt=false")).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/csv");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    let csv = response.text().await.unwrap();
+    let secret = enrollment["secret"].as_str().unwrap();
+    assert!(
+        csv.contains(&format!("{account},,,SHA1,6,30,{secret},")),
+        "{csv}"
+    );
+    assert!(!csv.contains("\ndefault,"));
+    let response = client
+        .get(url("/export"))
+        .header("x-bundle-passphrase", "correct horse")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bundle = response.bytes().await.unwrap();
+    assert!(crate::bundle::is_encrypted(&bundle));
+    let accounts = crate::bundle::decode(&bundle, Some("correct horse"), None).unwrap();
+    assert!(accounts.iter().any(|a| a.name == account));
+
+    // Import a bundle where the account has another secret.
+    let mut changed = crate::store::ACCOUNTS.get(&account).unwrap();
+    changed.secret = vec![7; 20];
+    let bundle = crate::bundle::encode(
+        &[changed.clone()],
+        crate::BundleFormat::Json,
+        Some("correct horse"),
+    )
+    .unwrap();
+    let import = |query: &str, passphrase: &str| {
+        client
+            .post(url(&format!("/import?{query}")))
+            .header("x-bundle-passphrase", passphrase)
+            .body(bundle.clone())
+            .send()
+    };
+    let response = import("", "wrong").await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = import("policy=fail", "correct horse").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = import("policy=skip", "correct horse").await.unwrap();
+    let diff: Value = response.json().await.unwrap();
+    assert_eq!(diff["skipped"], json!([account]));
+    let response = import("policy=overwrite&dry_run=true", "correct horse");
+    let diff: Value = response.await.unwrap().json().await.unwrap();
+    assert_eq!(diff["dry_run"], true);
+    assert_eq!(diff["updated"], json!([account]));
+    assert_ne!(
+        crate::store::ACCOUNTS.get(&account).unwrap().secret,
+        changed.secret
+    );
+    let response = import("policy=overwrite", "correct horse").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        crate::store::ACCOUNTS.get(&account).unwrap().secret,
+        changed.secret
+    );
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_account_lifecycle() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("carol-{}", rand::random::<u32>());
+    let admin = |path: &str| url(&format!("/admin/accounts/{account}{path}"));
+
+    // Create an account, which is listed with its failures.
+    let response = client
+        .post(url("/admin/accounts"))
+        .json(&json!({ "account": account }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let enrollment: Value = response.json().await.unwrap();
+    let secret = totp_rs::Secret::Encoded(enrollment["secret"].as_str().unwrap().to_owned());
+    let token = crate::try_get_token(&secret.to_bytes().unwrap()).unwrap();
+    let wrong_token = crate::InputToken::new("000000").with_account(&account);
+    let response = client.post(url("/verify")).json(&wrong_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let response = client.get(url("/admin/accounts")).send().await.unwrap();
+    let accounts: Vec<Value> = response.json().await.unwrap();
+    let listed = accounts.iter().find(|a| a["name"] == account.as_str());
+    assert_eq!(listed.unwrap()["failures"], 1);
+    assert_eq!(listed.unwrap()["disabled"], false);
+
+    // Codes of a disabled account are rejected until it's enabled.
+    let input_token = crate::InputToken::new(token).with_account(&account);
+    let response = client.post(admin("/disable")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let info: Value = response.json().await.unwrap();
+    assert_eq!(info["disabled"], true);
+    let response = client.post(url("/verify")).json(&input_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::FORBIDDEN);
+    let response = client.post(admin("/enable")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client.post(url("/verify")).json(&input_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+
+    // Reset failures.
+    let response = client.post(url("/verify")).json(&wrong_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let response = client.post(admin("/reset")).send().await.unwrap();
+    let info: Value = response.json().await.unwrap();
+    assert_eq!(info["failures"], 0);
+    let response = client.post(url("/admin/accounts/nobody/reset")).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    // Recent audit records of the account, newest first.
+    let response = client
+        .get(url(&format!("/admin/audit?account={account}&limit=3")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let records: Vec<Value> = response.json().await.unwrap();
+    let actions: Vec<&str> = records
+        .iter()
+        .map(|record| record["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["reset", "verify", "verify"]);
+    let response = client.get(url("/admin/audit?limit=0")).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::BAD_REQUEST);
+
+    let response = client.delete(admin("")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = client.post(admin("/disable")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_group_verify() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let suffix = rand::random::<u32>();
+    let group = format!("game-{suffix}");
+    let members = [format!("dave-{suffix}"), format!("erin-{suffix}")];
+
+    // Members inherit the number of digits of the group.
+    let response = client
+        .put(url(&format!("/admin/groups/{group}")))
+        .json(&json!({ "digits": 8 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut tokens = Vec::new();
+    for member in &members {
         let response = client
-            .get(format!("http://{addr}/health"))
+            .post(url("/enrollment"))
+            .json(&json!({ "account": member, "group": group }))
             .send()
             .await
             .unwrap();
-        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::CREATED);
+        tokens.push(crate::current_token(member).unwrap());
     }
-    for _ in 0..3 {
+    assert_eq!(tokens[1].len(), 8);
+
+    // The code of any member is valid for the group.
+    let verify = url(&format!("/groups/{group}/verify"));
+    let response = client.post(&verify).json(&json!({ "token": tokens[1] }));
+    let response = response.send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let matched: Value = response.json().await.unwrap();
+    assert_eq!(matched["account"], members[1].as_str());
+    let wrong_token = json!({ "token": "00000000" });
+    let response = client.post(&verify).json(&wrong_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let response = client.post(url("/groups/nobody/verify")).json(&wrong_token);
+    assert_eq!(
+        response.send().await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Groups cannot be removed while they have members.
+    let response = client.get(url("/admin/groups")).send().await.unwrap();
+    let groups: Vec<Value> = response.json().await.unwrap();
+    let listed = groups.iter().find(|g| g["name"] == group.as_str()).unwrap();
+    assert_eq!(listed["members"], json!(members));
+    let response = client.delete(url(&format!("/admin/groups/{group}"))).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::CONFLICT);
+    for member in &members {
         let response = client
-            .get(format!("http://{addr}/health"))
+            .put(url(&format!("/admin/accounts/{member}/policy")))
+            .json(&json!({}))
             .send()
             .await
             .unwrap();
-        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.status(), StatusCode::OK);
     }
+    let response = client.delete(url(&format!("/admin/groups/{group}"))).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::NO_CONTENT);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_unlock_token() {
+    use crate::offline::{MAX_UNLOCK_TOKEN_LEN, decode_unlock_token, verify_unlock_token};
+    use base64::Engine;
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("grace-{}", rand::random::<u32>());
+    crate::add_account(&account, crate::OtpauthProfile::default()).unwrap();
+    let policy = json!({ "scopes": { "console": {} } });
+    let response = client
+        .put(url(&format!("/admin/accounts/{account}/policy")))
+        .json(&policy)
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    let token = crate::current_token(&account).unwrap();
+
+    // Features which aren't granted as scopes are left out.
+    let response = client
+        .post(url("/unlocks"))
+        .json(&json!({
+            "token": token,
+            "account": account,
+            "device_id": "device-1",
+            "features": ["console", "cheats"],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let unlock: Value = response.json().await.unwrap();
+    assert_eq!(unlock["claims"]["features"], json!(["console"]));
+
+    // The token is verified offline with the public key.
+    let response = client.get(url("/unlocks/key")).send().await.unwrap();
+    let key: Value = response.json().await.unwrap();
+    let public_key = base64::engine::general_purpose::STANDARD
+        .decode(key["public_key"].as_str().unwrap())
+        .unwrap();
+    let mut buf = [0; MAX_UNLOCK_TOKEN_LEN];
+    let bytes = decode_unlock_token(unlock["token"].as_str().unwrap(), &mut buf).unwrap();
+    let now = unlock["claims"]["iat"].as_u64().unwrap();
+    let claims =
+        verify_unlock_token(&public_key.try_into().unwrap(), bytes, "device-1", now).unwrap();
+    assert_eq!(claims.account, account);
+    assert!(claims.has_feature("console"));
+    assert!(!claims.has_feature("cheats"));
+
+    // Invalid requests are rejected before the code is checked.
+    let response = client
+        .post(url("/unlocks"))
+        .json(&json!({ "token": "000000", "device_id": "" }))
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::BAD_REQUEST);
+    let response = client
+        .post(url("/unlocks"))
+        .json(&json!({ "token": "000000", "device_id": "device-1" }))
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_scopes() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("heidi-{}", rand::random::<u32>());
+    let enrollment = crate::add_account(&account, crate::OtpauthProfile::default()).unwrap();
+    let policy = json!({
+        "scopes": { "log_upload": {}, "cheats": { "fresh": true, "second_code": true } },
+    });
+    let response = client
+        .put(url(&format!("/admin/accounts/{account}/policy")))
+        .json(&policy)
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    let secret = totp_rs::Secret::Encoded(enrollment.secret)
+        .to_bytes()
+        .unwrap();
+    let totp = crate::totp::new_totp_with_label(
+        secret,
+        &crate::TotpParams::default(),
+        1,
+        crate::PKG_NAME,
+        &account,
+    );
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let current = totp.generate(now);
+    let previous = totp.generate(now - 30);
+
+    // Codes are accepted once, so they are forgotten to be sent again.
+    let verify = async |input_token: crate::InputToken| {
+        crate::totp::USED_STEPS.forget(&account);
+        let input_token = input_token.with_account(&account);
+        let response = client.post(url("/verify")).json(&input_token).send();
+        response.await.unwrap()
+    };
+    let scope = "log_upload cheats console";
+    let response = verify(crate::InputToken::new(&current).with_scope(scope)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let verification: Value = response.json().await.unwrap();
+    assert_eq!(verification["scopes"], json!(["log_upload"]));
+    assert_eq!(verification["denied"], json!(["cheats", "console"]));
+
+    // Privileged scopes require a fresh code and a second one.
+    let input_token = crate::InputToken::new(&current)
+        .with_second_token(&previous)
+        .with_scope("cheats");
+    let verification: Value = verify(input_token).await.json().await.unwrap();
+    assert_eq!(verification["scopes"], json!(["cheats"]));
+    let input_token = crate::InputToken::new(&previous)
+        .with_second_token(&current)
+        .with_scope("cheats");
+    let verification: Value = verify(input_token).await.json().await.unwrap();
+    assert_eq!(verification["denied"], json!(["cheats"]));
+    let input_token = crate::InputToken::new(&current).with_second_token(&current);
+    assert_eq!(verify(input_token).await.status(), StatusCode::UNAUTHORIZED);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_app() {
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(crate::server::admin_app()).await;
+    let response = client
+        .get(format!("http://{addr}/v1/admin/accounts"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // Only the admin API is served.
+    let response = client
+        .post(format!("http://{addr}/v1/verify"))
+        .body("123456")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = client.get(format!("http://{addr}/health")).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_admin_anonymous() {
+    // Without authentication, the admin API isn't served with the rest of the API.
+    let (addr, tx, handle) = setup_server(app()).await;
+    let response = reqwest::get(format!("http://{addr}/v1/admin/accounts"));
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+
+    // Anonymous callers are rejected even on its own listeners.
+    let (addr, tx, handle) = setup_server(crate::server::admin_app()).await;
+    let response = reqwest::get(format!("http://{addr}/v1/admin/accounts"));
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+#[expect(unsafe_code)]
+async fn test_two_person_approval() {
+    use serde_json::{Value, json};
+    use sha2::{Digest, Sha256};
+
+    let suffix = rand::random::<u32>();
+    let group = format!("ops-{suffix}");
+    let members = [format!("ivan-{suffix}"), format!("judy-{suffix}")];
+    let outsider = format!("oscar-{suffix}");
+    let client = authenticated_client();
+    // Another key, which can access only one of the members.
+    let hash = |key: &str| hex::encode(Sha256::digest(key));
+    let api_keys = format!(
+        "test:{},limited:{}:{}",
+        hash(TEST_API_KEY),
+        hash("limited-key"),
+        members[0]
+    );
+    unsafe { std::env::set_var("API_KEYS", api_keys) }
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+
+    let response = client
+        .put(url(&format!("/admin/groups/{group}")))
+        .json(&json!({}))
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    for member in &members {
+        let response = client
+            .post(url("/enrollment"))
+            .json(&json!({ "account": member, "group": group }))
+            .send();
+        assert_eq!(response.await.unwrap().status(), StatusCode::CREATED);
+    }
+    crate::add_account(&outsider, crate::OtpauthProfile::default()).unwrap();
+
+    // The group has fewer members than required.
+    let approvals = url(&format!("/groups/{group}/approvals"));
+    let response = client
+        .post(&approvals)
+        .json(&json!({ "required": 3 }))
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::BAD_REQUEST);
+    let request = json!({ "purpose": "enable god mode on eu-1" });
+    // Callers which can't access every member can neither create nor read approvals.
+    let response = client
+        .post(&approvals)
+        .header("x-api-key", "limited-key")
+        .json(&request)
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::FORBIDDEN);
+    let response = client.post(&approvals).json(&request).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let approval: Value = response.json().await.unwrap();
+    assert_eq!(approval["status"], "pending");
+    assert_eq!(approval["required"], 2);
+    let approve = url(&format!("/approvals/{}", approval["id"].as_str().unwrap()));
+    let response = client
+        .get(&approve)
+        .header("x-api-key", "limited-key")
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::FORBIDDEN);
+    // A code of the account `periods` after the current one.
+    let code = |account: &str, periods: u64| {
+        let account = crate::store::ACCOUNTS.get(account).unwrap();
+        let totp = crate::totp::new_totp_with_label(
+            account.secret.clone(),
+            &account.totp,
+            1,
+            crate::PKG_NAME,
+            &account.name,
+        );
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let token = totp.generate(now + periods * account.totp.period);
+        json!({ "token": token, "account": account.name })
+    };
+
+    let response = client.post(&approve).json(&code(&members[0], 0)).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let approval: Value = response.json().await.unwrap();
+    assert_eq!(approval["status"], "pending");
+    assert_eq!(approval["approvers"], json!([members[0]]));
+    // Codes are accepted once, each member approves once, and only members approve.
+    let response = client.post(&approve).json(&code(&members[0], 0)).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    for (account, periods) in [(&members[0], 1), (&outsider, 0)] {
+        let response = client.post(&approve).json(&code(account, periods)).send();
+        assert_eq!(response.await.unwrap().status(), StatusCode::CONFLICT);
+    }
+    let wrong_token = json!({ "token": "000000", "account": members[1] });
+    let response = client.post(&approve).json(&wrong_token).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    let response = client.post(&approve).json(&code(&members[1], 0)).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let approval: Value = response.json().await.unwrap();
+    assert_eq!(approval["status"], "approved");
+    assert_eq!(approval["approvers"], json!(members));
+    let response = client.get(&approve).send().await.unwrap();
+    let fetched: Value = response.json().await.unwrap();
+    assert_eq!(fetched, approval);
+    let response = client.get(url("/approvals/missing")).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_challenge_response() {
+    use base64::Engine;
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use serde_json::{Value, json};
+
+    let (addr, tx, handle) = setup_server(app()).await;
+    let client = reqwest::Client::new();
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("kate-{}", rand::random::<u32>());
+    crate::add_account(&account, crate::OtpauthProfile::default()).unwrap();
+    let nonce = format!("txn-{}", rand::random::<u32>());
+    let challenge = |nonce: &str| {
+        let request = json!({ "account": account, "nonce": nonce, "purpose": "transfer 500 gems" });
+        client.post(url("/challenges")).json(&request).send()
+    };
+
+    let response = challenge("two words").await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = challenge(&nonce).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: Value = response.json().await.unwrap();
+    assert_eq!(created["account"], account.as_str());
+    let answer = url(&format!("/challenges/{}", created["id"].as_str().unwrap()));
+
+    // Invalid codes leave the challenge pending.
+    let response = client
+        .post(&answer)
+        .json(&json!({ "token": "000000" }))
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let token = crate::current_token(&account).unwrap();
+    let response = client.post(&answer).json(&json!({ "token": token })).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let assertion: Value = response.json().await.unwrap();
+    assert_eq!(assertion["claims"]["sub"], account.as_str());
+    assert_eq!(assertion["claims"]["nonce"], nonce.as_str());
+
+    // Relying parties verify assertions with the public key.
+    let response = client.get(url("/unlocks/key")).send().await.unwrap();
+    let key: Value = response.json().await.unwrap();
+    let key = STANDARD
+        .decode(key["public_key"].as_str().unwrap())
+        .unwrap();
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&key.try_into().unwrap()).unwrap();
+    let (payload, signature) = assertion["assertion"]
+        .as_str()
+        .unwrap()
+        .split_once('.')
+        .unwrap();
+    let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+    let signature = ed25519_dalek::Signature::from_slice(&signature).unwrap();
+    let message = [crate::challenge::ASSERTION_DOMAIN, payload.as_bytes()].concat();
+    key.verify_strict(&message, &signature).unwrap();
+    assert!(key.verify_strict(payload.as_bytes(), &signature).is_err());
+
+    // Challenges are answered once, and codes cannot answer another one.
+    let response = client.post(&answer).json(&json!({ "token": token })).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    let response = challenge(&nonce).await.unwrap();
+    let created: Value = response.json().await.unwrap();
+    let answer = url(&format!("/challenges/{}", created["id"].as_str().unwrap()));
+    let response = client.post(&answer).json(&json!({ "token": token })).send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    // Codes which have answered a challenge are rejected by other routes as well.
+    let response = client
+        .post(url("/verify"))
+        .json(&json!({ "token": token, "account": account }));
+    assert_eq!(
+        response.send().await.unwrap().status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_step_up() {
+    use serde_json::{Value, json};
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("liam-{}", rand::random::<u32>());
+    let enrollment = crate::add_account(&account, crate::OtpauthProfile::default()).unwrap();
+    let policy = json!({ "scopes": { "log_upload": {}, "cheats": {} } });
+    let response = client
+        .put(url(&format!("/admin/accounts/{account}/policy")))
+        .json(&policy)
+        .send();
+    assert_eq!(response.await.unwrap().status(), StatusCode::OK);
+    let secret = totp_rs::Secret::Encoded(enrollment.secret)
+        .to_bytes()
+        .unwrap();
+    let totp = crate::totp::new_totp_with_label(
+        secret,
+        &crate::TotpParams::default(),
+        1,
+        crate::PKG_NAME,
+        &account,
+    );
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let current = totp.generate(now);
+    // The next code is within the skew of the policy.
+    let next = totp.generate(now + 30);
+
+    let input_token = crate::InputToken::new(&current)
+        .with_account(&account)
+        .with_scope("log_upload");
+    let response = client.post(url("/sessions")).json(&input_token).send();
+    let session: Value = response.await.unwrap().json().await.unwrap();
+    let bearer = format!("Bearer {}", session["token"].as_str().unwrap());
+    let step_up = async |bearer: &str, input_token: crate::InputToken| {
+        let response = client
+            .post(url("/sessions/step-up"))
+            .header(reqwest::header::AUTHORIZATION, bearer)
+            .json(&input_token)
+            .send();
+        response.await.unwrap()
+    };
+
+    // The code which created the session cannot step it up.
+    let response = step_up(&bearer, crate::InputToken::new(&current)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = step_up("Bearer forged", crate::InputToken::new(&next)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = step_up(&bearer, crate::InputToken::new(&next).with_scope("cheats")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let elevated: Value = response.json().await.unwrap();
+    let claims = &elevated["claims"];
+    assert_eq!(claims["sub"], account.as_str());
+    assert_eq!(claims["scopes"], json!(["log_upload", "cheats"]));
+    assert!(claims["auth_time"].as_u64() >= session["claims"]["auth_time"].as_u64());
+    assert!(claims["totp_step"].as_u64() > session["claims"]["totp_step"].as_u64());
+    let response = client
+        .get(url("/sessions"))
+        .bearer_auth(elevated["token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    let checked: Value = response.json().await.unwrap();
+    assert_eq!(&checked, claims);
+
+    tx.send(()).unwrap();
+    let _ = handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_step_up_with_second_code() {
+    use serde_json::Value;
+
+    let client = authenticated_client();
+    let (addr, tx, handle) = setup_server(app()).await;
+    let url = |path: &str| format!("http://{addr}/v1{path}");
+    let account = format!("mona-{}", rand::random::<u32>());
+    let enrollment = crate::add_account(&account, crate::OtpauthProfile::default()).unwrap();
+    let secret = totp_rs::Secret::Encoded(enrollment.secret)
+        .to_bytes()
+        .unwrap();
+    let totp = crate::totp::new_totp_with_label(
+        secret,
+        &crate::TotpParams::default(),
+        1,
+        crate::PKG_NAME,
+        &account,
+    );
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let current = totp.generate(now);
+    let next = totp.generate(now + 30);
+
+    let input_token = crate::InputToken::new(&current)
+        .with_second_token(&next)
+        .with_account(&account);
+    let response = client.post(url("/sessions")).json(&input_token).send();
+    let response = response.await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let session: Value = response.json().await.unwrap();
+
+    // The second code which created the session cannot step it up either.
+    let response = client
+        .post(url("/sessions/step-up"))
+        .bearer_auth(session["token"].as_str().unwrap())
+        .json(&crate::InputToken::new(&next))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.text().await.unwrap(),
+        format!("Error: {}", crate::Error::TotpReplayed)
+    );
+
     tx.send(()).unwrap();
     let _ = handle.await.unwrap();
 }
@@ -0,0 +1,610 @@
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+/// Env var used to set the path of the certificate chain (PEM).
+const TLS_CERT_PATH: &str = "TLS_CERT_PATH";
+/// Env var used to set the path of the private key (PEM).
+const TLS_KEY_PATH: &str = "TLS_KEY_PATH";
+/// Env var used to set the path of the CA bundle (PEM) which client certificates
+/// are verified against. Mutual TLS is enabled if it has been set.
+const TLS_CLIENT_CA_PATH: &str = "TLS_CLIENT_CA_PATH";
+/// Env var used to set the port of the plain HTTP listener redirecting to HTTPS.
+const TLS_REDIRECT_PORT: &str = "TLS_REDIRECT_PORT";
+/// Env var used to set how often (in seconds) certificate files are checked for changes.
+const TLS_RELOAD_INTERVAL: &str = "TLS_RELOAD_INTERVAL";
+
+/// Connections which haven't completed the TLS handshake in time are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS settings of the standalone server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TlsConfig {
+    /// Path of the certificate chain (PEM).
+    pub(crate) cert_path: PathBuf,
+    /// Path of the private key (PEM).
+    pub(crate) key_path: PathBuf,
+    /// Path of the CA bundle (PEM) to verify client certificates (mutual TLS).
+    pub(crate) client_ca_path: Option<PathBuf>,
+    /// Port of the plain HTTP listener redirecting to HTTPS.
+    pub(crate) redirect_port: Option<u16>,
+    /// How often certificate files are checked for changes.
+    pub(crate) reload_interval: Duration,
+}
+
+/// TLS settings, which are `None` if env var `TLS_CERT_PATH` hasn't been set
+/// (in which case plain HTTP is served).
+///
+/// # Panics
+///
+/// Panics when only one of `TLS_CERT_PATH` and `TLS_KEY_PATH` has been set,
+/// or when any TLS env var cannot be parsed.
+pub(crate) static TLS_CONFIG: LazyLock<Option<TlsConfig>> = LazyLock::new(init_tls_config);
+
+fn init_tls_config() -> Option<TlsConfig> {
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let (cert_path, key_path) = match (var(TLS_CERT_PATH), var(TLS_KEY_PATH)) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => {
+            for name in [TLS_CLIENT_CA_PATH, TLS_REDIRECT_PORT] {
+                assert!(var(name).is_none(), "{name} requires {TLS_CERT_PATH}.");
+            }
+            tracing::info!("Env var {TLS_CERT_PATH} hasn't been set. TLS is disabled.");
+            return None;
+        }
+        _ => panic!("{TLS_CERT_PATH} and {TLS_KEY_PATH} must be set together."),
+    };
+    let redirect_port = var(TLS_REDIRECT_PORT).map(|value| {
+        value
+            .parse::<u16>()
+            .unwrap_or_else(|_| panic!("{TLS_REDIRECT_PORT} must be a valid port number!"))
+    });
+    let reload_interval = var(TLS_RELOAD_INTERVAL).map_or(30, |value| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&v| v != 0)
+            .unwrap_or_else(|| panic!("{TLS_RELOAD_INTERVAL} must be a positive integer!"))
+    });
+    Some(TlsConfig {
+        cert_path: cert_path.into(),
+        key_path: key_path.into(),
+        client_ca_path: var(TLS_CLIENT_CA_PATH).map(Into::into),
+        redirect_port,
+        reload_interval: Duration::from_secs(reload_interval),
+    })
+}
+
+/// Load certificates and keys from files, and build the rustls [`ServerConfig`].
+///
+/// # Errors
+///
+/// Returns Err if any file cannot be read or parsed,
+/// or if the private key doesn't match the certificate.
+pub(crate) fn load_server_config(config: &TlsConfig) -> crate::Result<Arc<ServerConfig>> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+    let invalid = |path: &PathBuf, e: &dyn std::fmt::Display| {
+        crate::Error::TlsConfig(format!("{}: {e}", path.display()))
+    };
+    let read_certs = |path: &PathBuf| -> crate::Result<Vec<CertificateDer<'static>>> {
+        let pem = std::fs::read(path)?;
+        let certs = CertificateDer::pem_slice_iter(&pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(path, &e))?;
+        if certs.is_empty() {
+            return Err(invalid(path, &"no certificate found"));
+        }
+        Ok(certs)
+    };
+
+    let certs = read_certs(&config.cert_path)?;
+    let key = PrivateKeyDer::from_pem_slice(&std::fs::read(&config.key_path)?)
+        .map_err(|e| invalid(&config.key_path, &e))?;
+
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| crate::Error::TlsConfig(e.to_string()))?;
+    let builder = if let Some(ca_path) = &config.client_ca_path {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in read_certs(ca_path)? {
+            roots.add(cert).map_err(|e| invalid(ca_path, &e))?;
+        }
+        let verifier =
+            rustls::server::WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| invalid(ca_path, &e))?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(&config.cert_path, &e))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+/// A rustls [`ServerConfig`] which is replaced when certificate files change.
+#[derive(Debug, Clone)]
+pub(crate) struct ReloadableServerConfig(Arc<RwLock<Arc<ServerConfig>>>);
+
+impl ReloadableServerConfig {
+    /// Load the server config, and watch its files for changes in the background.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the server config cannot be loaded (see [`load_server_config`]).
+    pub(crate) fn watch(config: &'static TlsConfig) -> crate::Result<Self> {
+        let reloadable = Self(Arc::new(RwLock::new(load_server_config(config)?)));
+        let watched = reloadable.clone();
+        tokio::spawn(async move {
+            let mut last_modified = modified_times(config);
+            loop {
+                tokio::time::sleep(config.reload_interval).await;
+                let modified = modified_times(config);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                match watched.reload(config) {
+                    Ok(()) => tracing::info!("TLS certificates have been reloaded."),
+                    Err(e) => tracing::error!("Failed to reload TLS certificates. Error: {e}."),
+                }
+            }
+        });
+        Ok(reloadable)
+    }
+
+    /// Reload the server config from files, keeping the current one on failure.
+    ///
+    /// # Errors
+    ///
+    /// Returns Err if the server config cannot be loaded (see [`load_server_config`]).
+    pub(crate) fn reload(&self, config: &TlsConfig) -> crate::Result<()> {
+        let server_config = load_server_config(config)?;
+        *self
+            .0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = server_config;
+        Ok(())
+    }
+
+    /// Get the current server config.
+    fn current(&self) -> Arc<ServerConfig> {
+        self.0
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// Modified times of the files referred by `config`.
+fn modified_times(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [
+        Some(&config.cert_path),
+        Some(&config.key_path),
+        config.client_ca_path.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+    .collect()
+}
+
+/// A [`Listener`] which accepts TLS connections.
+///
+/// Handshakes are performed concurrently, so that slow clients
+/// don't block other connections.
+pub(crate) struct TlsListener {
+    listener: TcpListener,
+    server_config: ReloadableServerConfig,
+    handshakes: JoinSet<
+        Option<(
+            tokio_rustls::server::TlsStream<TcpStream>,
+            std::net::SocketAddr,
+        )>,
+    >,
+}
+
+impl TlsListener {
+    /// Accept TLS connections on `listener` with the given server config.
+    pub(crate) fn new(listener: TcpListener, server_config: ReloadableServerConfig) -> Self {
+        Self {
+            listener,
+            server_config,
+            handshakes: JoinSet::new(),
+        }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = tokio_rustls::server::TlsStream<TcpStream>;
+    type Addr = std::net::SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            tokio::select! {
+                (stream, addr) = Listener::accept(&mut self.listener) => {
+                    let acceptor = tokio_rustls::TlsAcceptor::from(self.server_config.current());
+                    self.handshakes.spawn(async move {
+                        match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                            Ok(Ok(stream)) => Some((stream, addr)),
+                            Ok(Err(e)) => {
+                                tracing::debug!("TLS handshake with {addr} failed. Error: {e}.");
+                                None
+                            }
+                            Err(_) => {
+                                tracing::debug!("TLS handshake with {addr} timed out.");
+                                None
+                            }
+                        }
+                    });
+                }
+                Some(result) = self.handshakes.join_next(), if !self.handshakes.is_empty() => {
+                    if let Ok(Some(connection)) = result {
+                        return connection;
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        self.listener.local_addr()
+    }
+}
+
+/// The TLS client certificate of a connection, which is inserted into request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientCert {
+    /// Common name (CN) of the subject.
+    pub(crate) common_name: String,
+}
+
+impl ClientCert {
+    /// Parse the common name of a DER-encoded certificate.
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let common_name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+        Some(ClientCert {
+            common_name: common_name.to_owned(),
+        })
+    }
+}
+
+/// Connections which may carry a verified TLS client certificate.
+pub(crate) trait PeerCertificate {
+    /// The client certificate of the connection, if any.
+    fn client_cert(&self) -> Option<ClientCert> {
+        None
+    }
+}
+
+impl PeerCertificate for TcpStream {}
+
+#[cfg(unix)]
+impl PeerCertificate for tokio::net::UnixStream {}
+
+impl PeerCertificate for tokio_rustls::server::TlsStream<TcpStream> {
+    fn client_cert(&self) -> Option<ClientCert> {
+        let (_, connection) = self.get_ref();
+        ClientCert::from_der(connection.peer_certificates()?.first()?)
+    }
+}
+
+/// Build the `Location` of the HTTPS counterpart of a plain HTTP request.
+///
+/// Returns `None` if `host` isn't a valid `Host` header.
+fn https_location(host: &str, https_port: u16, path_and_query: &str) -> Option<String> {
+    let authority: axum::http::uri::Authority = host.parse().ok()?;
+    let host = authority.host();
+    Some(if https_port == 443 {
+        format!("https://{host}{path_and_query}")
+    } else {
+        format!("https://{host}:{https_port}{path_and_query}")
+    })
+}
+
+/// Router of the plain HTTP listener which redirects every request to HTTPS.
+pub(crate) fn redirect_app(https_port: u16) -> axum::Router {
+    use axum::http::{HeaderMap, StatusCode, Uri, header};
+    use axum::response::{IntoResponse, Redirect};
+
+    let redirect = move |headers: HeaderMap, uri: Uri| async move {
+        let path_and_query = uri.path_and_query().map_or("/", |pq| pq.as_str());
+        headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .and_then(|host| https_location(host, https_port, path_and_query))
+            .map_or_else(
+                || (StatusCode::BAD_REQUEST, "Error: invalid Host header").into_response(),
+                |location| Redirect::permanent(&location).into_response(),
+            )
+    };
+    axum::Router::new().fallback(redirect)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use rstest::rstest;
+    use std::path::Path;
+
+    /// A CA and certificates signed by it, written to a temp dir.
+    struct TestPki {
+        dir: PathBuf,
+        ca_pem: String,
+        client_identity_pem: String,
+    }
+
+    impl TestPki {
+        fn generate() -> Self {
+            use rcgen::{
+                BasicConstraints, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair,
+            };
+
+            let dir = std::env::temp_dir().join(format!("totp-tls-{}", rand::random::<u64>()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+            let server_key = KeyPair::generate().unwrap();
+            let server_cert = CertificateParams::new(vec!["localhost".to_owned()])
+                .unwrap()
+                .signed_by(&server_key, &ca)
+                .unwrap();
+            let client_key = KeyPair::generate().unwrap();
+            let mut client_params = CertificateParams::new(vec!["client".to_owned()]).unwrap();
+            client_params
+                .distinguished_name
+                .push(DnType::CommonName, "client");
+            let client_cert = client_params.signed_by(&client_key, &ca).unwrap();
+
+            std::fs::write(dir.join("cert.pem"), server_cert.pem()).unwrap();
+            std::fs::write(dir.join("key.pem"), server_key.serialize_pem()).unwrap();
+            std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+            TestPki {
+                dir,
+                ca_pem: ca.pem(),
+                client_identity_pem: client_cert.pem() + &client_key.serialize_pem(),
+            }
+        }
+
+        fn tls_config(&self, mtls: bool) -> TlsConfig {
+            TlsConfig {
+                cert_path: self.dir.join("cert.pem"),
+                key_path: self.dir.join("key.pem"),
+                client_ca_path: mtls.then(|| self.dir.join("ca.pem")),
+                redirect_port: None,
+                reload_interval: Duration::from_secs(30),
+            }
+        }
+
+        /// Copy the server certificate and key of `other` into this dir.
+        fn replace_server_cert(&self, other: &TestPki) {
+            for name in ["cert.pem", "key.pem"] {
+                std::fs::copy(other.dir.join(name), self.dir.join(name)).unwrap();
+            }
+        }
+
+        fn client(&self, identity: bool) -> reqwest::Client {
+            let mut builder = reqwest::Client::builder()
+                .tls_certs_only([reqwest::Certificate::from_pem(self.ca_pem.as_bytes()).unwrap()]);
+            if identity {
+                let identity = reqwest::Identity::from_pem(self.client_identity_pem.as_bytes());
+                builder = builder.identity(identity.unwrap());
+            }
+            builder.build().unwrap()
+        }
+    }
+
+    impl Drop for TestPki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn serve_tls(server_config: ReloadableServerConfig) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let listener = TlsListener::new(listener, server_config);
+        let app = crate::server::app();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            let _shutdown_tx = shutdown_tx;
+            crate::server::serve(listener, app, shutdown_rx).await
+        });
+        port
+    }
+
+    async fn get_health(
+        client: &reqwest::Client,
+        port: u16,
+    ) -> reqwest::Result<reqwest::StatusCode> {
+        let url = format!("https://localhost:{port}/health");
+        client.get(url).send().await.map(|r| r.status())
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let pki = TestPki::generate();
+        let config = Box::leak(Box::new(pki.tls_config(false)));
+        let port = serve_tls(ReloadableServerConfig::watch(config).unwrap()).await;
+        let status = get_health(&pki.client(false), port).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_mtls() {
+        let pki = TestPki::generate();
+        let config = Box::leak(Box::new(pki.tls_config(true)));
+        let port = serve_tls(ReloadableServerConfig::watch(config).unwrap()).await;
+        // Clients without a certificate signed by the CA are rejected.
+        assert!(get_health(&pki.client(false), port).await.is_err());
+        assert!(
+            get_health(&TestPki::generate().client(true), port)
+                .await
+                .is_err()
+        );
+        let status = get_health(&pki.client(true), port).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_mtls_roles() {
+        unsafe { std::env::set_var("ROLE_BINDINGS", "cert:client=auditor") }
+        let pki = TestPki::generate();
+        let config = Box::leak(Box::new(pki.tls_config(true)));
+        let port = serve_tls(ReloadableServerConfig::watch(config).unwrap()).await;
+        let client = pki.client(true);
+        let url = |path: &str| format!("https://localhost:{port}/v1/admin{path}");
+        // The certificate identifies the caller, whose roles are bound to its common name.
+        let response = client.get(url("/audit")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let response = client.get(url("/export")).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.text().await.unwrap(),
+            r#"Error: caller "cert:client" isn't granted the "manage" permission"#
+        );
+    }
+
+    #[test]
+    fn test_client_cert_from_der() {
+        use rustls::pki_types::CertificateDer;
+        use rustls::pki_types::pem::PemObject;
+
+        let pki = TestPki::generate();
+        let pem = pki.client_identity_pem.as_bytes();
+        let der = CertificateDer::from_pem_slice(pem).unwrap();
+        let expected = ClientCert {
+            common_name: "client".to_owned(),
+        };
+        assert_eq!(ClientCert::from_der(&der), Some(expected));
+        assert_eq!(ClientCert::from_der(b"invalid"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let pki = TestPki::generate();
+        let config = pki.tls_config(false);
+        let server_config =
+            ReloadableServerConfig(Arc::new(RwLock::new(load_server_config(&config).unwrap())));
+        let port = serve_tls(server_config.clone()).await;
+        let new_pki = TestPki::generate();
+        assert!(get_health(&new_pki.client(false), port).await.is_err());
+
+        pki.replace_server_cert(&new_pki);
+        server_config.reload(&config).unwrap();
+        assert!(get_health(&pki.client(false), port).await.is_err());
+        let status = get_health(&new_pki.client(false), port).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+
+        // The current config is kept if the new files are invalid.
+        std::fs::write(&config.key_path, "invalid").unwrap();
+        assert!(server_config.reload(&config).is_err());
+        let status = get_health(&new_pki.client(false), port).await.unwrap();
+        assert_eq!(status, reqwest::StatusCode::OK);
+    }
+
+    #[rstest]
+    #[case::missing_cert("missing.pem", "key.pem")]
+    #[case::invalid_cert("key.pem", "key.pem")]
+    #[case::invalid_key("cert.pem", "cert.pem")]
+    fn test_load_server_config_err(#[case] cert: &str, #[case] key: &str) {
+        let pki = TestPki::generate();
+        let config = TlsConfig {
+            cert_path: pki.dir.join(cert),
+            key_path: pki.dir.join(key),
+            ..pki.tls_config(false)
+        };
+        assert!(load_server_config(&config).is_err());
+    }
+
+    #[rstest]
+    #[case("example.com", 443, "/", Some("https://example.com/"))]
+    #[case("example.com:80", 443, "/?a=1", Some("https://example.com/?a=1"))]
+    #[case(
+        "example.com:8080",
+        8443,
+        "/health",
+        Some("https://example.com:8443/health")
+    )]
+    #[case("[::1]:8080", 8443, "/", Some("https://[::1]:8443/"))]
+    #[case("exa mple.com", 443, "/", None)]
+    fn test_https_location(
+        #[case] host: &str,
+        #[case] https_port: u16,
+        #[case] path_and_query: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        let location = https_location(host, https_port, path_and_query);
+        assert_eq!(location.as_deref(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_app() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, redirect_app(8443)).await });
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let response = client
+            .post(format!("http://{addr}/?a=1"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()["location"],
+            "https://127.0.0.1:8443/?a=1"
+        );
+    }
+
+    #[test]
+    fn test_tls_config_default() {
+        assert!(std::env::var(TLS_CERT_PATH).is_err());
+        assert_eq!(*TLS_CONFIG, None);
+    }
+
+    #[test]
+    fn test_tls_config_var() {
+        unsafe {
+            std::env::set_var(TLS_CERT_PATH, "/etc/tls/cert.pem");
+            std::env::set_var(TLS_KEY_PATH, "/etc/tls/key.pem");
+            std::env::set_var(TLS_CLIENT_CA_PATH, "/etc/tls/ca.pem");
+            std::env::set_var(TLS_REDIRECT_PORT, "80");
+        }
+        let config = TLS_CONFIG.as_ref().unwrap();
+        assert_eq!(config.cert_path, Path::new("/etc/tls/cert.pem"));
+        assert_eq!(config.key_path, Path::new("/etc/tls/key.pem"));
+        assert_eq!(
+            config.client_ca_path.as_deref(),
+            Some(Path::new("/etc/tls/ca.pem"))
+        );
+        assert_eq!(config.redirect_port, Some(80));
+        assert_eq!(config.reload_interval, Duration::from_secs(30));
+    }
+
+    #[rstest]
+    #[case(TLS_CERT_PATH, "/etc/tls/cert.pem")]
+    #[case(TLS_CLIENT_CA_PATH, "/etc/tls/ca.pem")]
+    #[case(TLS_REDIRECT_PORT, "80")]
+    #[should_panic(expected = "TLS_CERT_PATH")]
+    fn test_tls_config_var_panic(#[case] name: &str, #[case] value: &str) {
+        unsafe { std::env::set_var(name, value) }
+        let _ = &*TLS_CONFIG;
+    }
+}
@@ -1,9 +1,15 @@
-use axum::Json;
+use crate::extract::Input;
+use crate::group::{DEFAULT_SKEW, EffectivePolicy, GROUP_PREFIX, TotpParams};
+use crate::scope::{Proof, ScopeGrant};
+use crate::store::Account;
+use axum::http::HeaderMap;
 use serde::{Deserialize, Serialize};
-use std::sync::LazyLock;
-use totp_rs::{Algorithm, TOTP};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use totp_rs::TOTP;
 
-const TOKEN_DIGITS: usize = 6;
+/// The account name of the secret set by env var [`RAW_SECRET`].
+pub(crate) const DEFAULT_ACCOUNT: &str = "default";
 
 /// Env var used to get raw secret of TOTP.
 const RAW_SECRET: &str = "RAW_SECRET";
@@ -52,22 +58,44 @@ fn init_vec_secret() -> Vec<u8> {
     }
 }
 
-/// Create a new instance of [`TOTP`] with given parameters.
+/// Create a new instance of [`TOTP`] with the default parameters.
 ///
 /// # Panics
 ///
-/// It panics if the `digit` or `secret` size is invalid.
-/// `digit` is set by [`TOKEN_DIGITS`], thus it's unlikely to be invalid.
+/// It panics if the `secret` size is invalid.
 /// `secret` must have bitsize of at least 128 or it will panic.
 fn new_totp(secret: impl Into<Vec<u8>>) -> totp_rs::TOTP {
+    new_totp_with_label(
+        secret,
+        &TotpParams::default(),
+        DEFAULT_SKEW,
+        crate::PKG_NAME,
+        "incognito",
+    )
+}
+
+/// Create a new instance of [`TOTP`] whose otpauth URL has the given issuer and label.
+///
+/// # Panics
+///
+/// See [`new_totp`]. It also panics if `params` are out of range (see [`TotpParams::validate`]),
+/// or if `issuer` or `label` contains a colon, which
+/// [`crate::otpauth::OtpauthProfile::validate`] rules out.
+pub(crate) fn new_totp_with_label(
+    secret: impl Into<Vec<u8>>,
+    params: &TotpParams,
+    skew: u8,
+    issuer: &str,
+    label: &str,
+) -> totp_rs::TOTP {
     TOTP::new(
-        Algorithm::SHA1,
-        TOKEN_DIGITS,
-        1,
-        30,
+        params.algorithm.into(),
+        params.digits.into(),
+        skew,
+        params.period,
         secret.into(),
-        Some(crate::PKG_NAME.to_owned()),
-        "incognito".to_owned(),
+        Some(issuer.to_owned()),
+        label.to_owned(),
     )
     .unwrap_or_else(|e| panic!("Failed creating a new instance of TOTP: {e}."))
 }
@@ -94,10 +122,26 @@ pub fn try_get_token(secret: &[u8]) -> crate::Result<String> {
     Ok(token)
 }
 
-/// The 6-digits token that users input.
-#[derive(Debug, Serialize, Deserialize)]
+/// The token that users input.
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct InputToken {
+    /// The current TOTP code (6 digits unless the policy of the account says otherwise).
+    #[schema(example = "123456")]
     token: String,
+    /// The account which the token belongs to.
+    ///
+    /// It falls back to header `X-Totp-Account`, and then to the default account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "alice")]
+    account: Option<String>,
+    /// Scopes which are requested, separated by spaces.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "log_upload cheats")]
+    scope: Option<String>,
+    /// A second, different code of the account, which privileged scopes may require.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "654321")]
+    second_token: Option<String>,
 }
 
 impl InputToken {
@@ -105,27 +149,379 @@ impl InputToken {
     pub fn new(value: impl Into<String>) -> Self {
         InputToken {
             token: value.into(),
+            account: None,
+            scope: None,
+            second_token: None,
         }
     }
+
+    /// Set the account which the token belongs to.
+    #[must_use]
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Request scopes, which are separated by spaces.
+    #[must_use]
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Send a second, different code of the account along.
+    #[must_use]
+    pub fn with_second_token(mut self, second_token: impl Into<String>) -> Self {
+        self.second_token = Some(second_token.into());
+        self
+    }
+
+    /// Get the token.
+    pub(crate) fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Get the second token, if any.
+    pub(crate) fn second_token(&self) -> Option<&str> {
+        self.second_token.as_deref()
+    }
+
+    /// Get the requested scopes.
+    pub(crate) fn scopes(&self) -> Vec<String> {
+        self.scope
+            .as_deref()
+            .map(crate::scope::parse_scope)
+            .unwrap_or_default()
+    }
+
+    /// Get the account given by the body, header `X-Totp-Account` or the default one.
+    pub(crate) fn account<'a>(&'a self, headers: &'a HeaderMap) -> &'a str {
+        account_or_default(self.account.as_deref(), headers)
+    }
+}
+
+/// Get the account if given, or else the one of header `X-Totp-Account` or the default one.
+pub(crate) fn account_or_default<'a>(account: Option<&'a str>, headers: &'a HeaderMap) -> &'a str {
+    account
+        .or_else(|| {
+            headers
+                .get(crate::rate_limit::ACCOUNT_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .filter(|account| !account.is_empty())
+        .unwrap_or(DEFAULT_ACCOUNT)
+}
+
+impl crate::extract::FromPlainText for InputToken {
+    /// The text is the token itself.
+    fn from_plain_text(text: &str) -> Self {
+        InputToken::new(text)
+    }
+}
+
+/// The account of a valid code and the scopes which it has been granted.
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct Verification {
+    /// The account which the token belongs to.
+    #[schema(example = "alice")]
+    account: String,
+    /// The requested scopes.
+    #[serde(flatten)]
+    grant: ScopeGrant,
+}
+
+/// An account whose code has been verified.
+#[derive(Debug)]
+pub(crate) struct Verified {
+    pub(crate) account: Account,
+    /// The policy of the account.
+    pub(crate) policy: EffectivePolicy,
+    /// How strongly the code proves possession of the secret.
+    pub(crate) proof: Proof,
+    /// The latest time step (i.e. the counter of RFC 6238) which the codes are valid for.
+    pub(crate) step: u64,
+}
+
+impl Verified {
+    /// Grant the requested scopes within the policy of the account.
+    pub(crate) fn grant(&self, requested: &[String]) -> ScopeGrant {
+        crate::scope::grant(&self.policy.scopes, requested, self.proof)
+    }
 }
 
-/// Check if the given token is valid.
-#[tracing::instrument]
-pub(crate) async fn check_current(Json(input_token): Json<InputToken>) -> crate::Result<()> {
+/// Check if the given token is valid, granting the requested scopes
+/// within the policy of the account.
+///
+/// The caller must be allowed to access the account,
+/// and every attempt is recorded in the audit log.
+#[utoipa::path(
+    post,
+    path = "/v1/verify",
+    tag = "verify",
+    request_body(
+        description = "The token, which is also accepted in the query string without a body.",
+        content(
+            (InputToken = "application/json"),
+            (InputToken = "application/x-www-form-urlencoded"),
+            (String = "text/plain", example = "123456"),
+        )
+    ),
+    responses(
+        (status = 200, description = "The token is valid.", body = Verification),
+        (status = 400, response = crate::Error),
+        (status = 401, response = crate::Error),
+        (status = 403, response = crate::Error),
+        (status = 404, response = crate::Error),
+        (status = 413, response = crate::Error),
+        (status = 415, response = crate::Error),
+        (status = 429, response = crate::Error),
+    )
+)]
+#[tracing::instrument(skip(headers))]
+pub(crate) async fn check_current(
+    client: crate::ClientInfo,
+    caller: crate::Caller,
+    headers: HeaderMap,
+    Input(input_token): Input<InputToken>,
+) -> crate::Result<axum::Json<Verification>> {
     tracing::debug!("{input_token:?}");
-    let token = input_token.token;
-    if token.len() != TOKEN_DIGITS || token.parse::<u32>().is_err() {
-        return Err(crate::Error::TotpInvalidFormat);
+    let account = input_token.account(&headers);
+    let verified = verify(
+        &client,
+        &caller,
+        account,
+        &input_token.token,
+        input_token.second_token(),
+    )?;
+    Ok(axum::Json(Verification {
+        grant: verified.grant(&input_token.scopes()),
+        account: verified.account.name,
+    }))
+}
+
+/// Check if `token` (and `second_token`, if any) is valid for the account
+/// on behalf of the caller, recording the attempt in the audit log.
+///
+/// Invalid codes are counted per account, which is locked out once they
+/// reach the lockout threshold of its policy. Valid codes are accepted once
+/// (see [`UsedSteps`]).
+///
+/// # Errors
+///
+/// Returns Err if the caller cannot access the account, the account doesn't
+/// exist, is disabled or locked out, or either token is invalid or has been used.
+pub(crate) fn verify(
+    client: &crate::ClientInfo,
+    caller: &crate::Caller,
+    account: &str,
+    token: &str,
+    second_token: Option<&str>,
+) -> crate::Result<Verified> {
+    use crate::audit::{AuditAction, record};
+    use crate::lockout::LOCKOUTS;
+    use crate::utils::now_secs;
+    let mut locked_out = false;
+    let result = caller
+        .authorize(account)
+        .and_then(|()| client.check_account_limit(account))
+        .and_then(|()| crate::store::ACCOUNTS.get(account))
+        .and_then(|account| {
+            let now = now_secs();
+            let policy = check_account(caller, &account, now)?;
+            let result = check_token(&account, policy.skew, token, now).and_then(|step| {
+                let second_step = second_token
+                    .map(|second_token| {
+                        check_second_token(&account, policy.skew, token, second_token, now)
+                    })
+                    .transpose()?;
+                let (oldest, latest) = second_step.map_or((step, step), |second_step| {
+                    (step.min(second_step), step.max(second_step))
+                });
+                USED_STEPS.accept(&account.name, oldest, latest)?;
+                Ok((step, latest, second_step.is_some()))
+            });
+            if !matches!(result, Err(crate::Error::TotpInvalidFormat(_))) {
+                locked_out = LOCKOUTS.record(&account.name, result.is_ok(), now, &policy.lockout);
+            }
+            let (step, latest, second_code) = result?;
+            let proof = Proof {
+                fresh: step == now.div_euclid(account.totp.period),
+                second_code,
+            };
+            Ok(Verified {
+                account,
+                policy,
+                proof,
+                // Both codes are spent, so the later step is the one to replay-check against.
+                step: latest,
+            })
+        });
+    record(AuditAction::Verify, account, client, &result);
+    if locked_out {
+        record(AuditAction::Lockout, account, client, &Ok(()));
     }
-    let totp = new_totp(VEC_SECRET.clone());
-    if totp.check_current(&token)? {
-        tracing::debug!("Correct TOTP: {token}.");
+    result
+}
+
+/// Check if `token` is valid for any member of the group on behalf of the caller,
+/// returning the first member (by name) which it's valid for.
+///
+/// Members which the caller cannot access, or which are disabled or locked out,
+/// are skipped. Invalid codes are counted against the group (`group:<name>`)
+/// under the lockout threshold of its policy, and the attempt is recorded
+/// in the audit log against the matched member or the group.
+///
+/// # Errors
+///
+/// Returns Err if the group doesn't exist or is locked out,
+/// or if the token isn't valid for any member.
+pub(crate) fn verify_group(
+    client: &crate::ClientInfo,
+    caller: &crate::Caller,
+    group: &str,
+    token: &str,
+) -> crate::Result<(Account, EffectivePolicy)> {
+    use crate::audit::{AuditAction, record};
+    use crate::lockout::LOCKOUTS;
+    use crate::utils::now_secs;
+    let key = format!("{GROUP_PREFIX}{group}");
+    let mut locked_out = false;
+    let result = client
+        .check_account_limit(&key)
+        .and_then(|()| crate::store::ACCOUNTS.members(group))
+        .and_then(|(group, members)| {
+            // Members may have different numbers of digits, which are checked one by one.
+            if !(6..=8).contains(&token.len()) || !token.bytes().all(|b| b.is_ascii_digit()) {
+                let digits = TotpParams::default().digits;
+                return Err(crate::Error::TotpInvalidFormat(digits));
+            }
+            let now = now_secs();
+            LOCKOUTS.check(&key, now)?;
+            let matched = members.into_iter().find_map(|member| {
+                caller.authorize(&member.name).ok()?;
+                let policy = check_account(caller, &member, now).ok()?;
+                let step = check_token(&member, policy.skew, token, now).ok()?;
+                USED_STEPS.accept(&member.name, step, step).ok()?;
+                Some((member, policy))
+            });
+            let config = group.policy.resolve().lockout;
+            locked_out = LOCKOUTS.record(&key, matched.is_some(), now, &config);
+            let (member, policy) = matched.ok_or(crate::Error::TotpInvalid)?;
+            LOCKOUTS.record(&member.name, true, now, &policy.lockout);
+            Ok((member, policy))
+        });
+    let account = result
+        .as_ref()
+        .map_or(key.as_str(), |(member, _)| &member.name);
+    record(AuditAction::Verify, account, client, &result);
+    if locked_out {
+        record(AuditAction::Lockout, &key, client, &Ok(()));
+    }
+    result
+}
+
+/// Check if the account is enabled, isn't locked out, and may be verified by the caller
+/// under its policy, which is returned.
+fn check_account(
+    caller: &crate::Caller,
+    account: &Account,
+    now: u64,
+) -> crate::Result<EffectivePolicy> {
+    if account.disabled {
+        return Err(crate::Error::AccountDisabled {
+            account: account.name.clone(),
+        });
+    }
+    let policy = crate::store::ACCOUNTS.policy(account);
+    policy.authorize(caller, &account.name)?;
+    crate::lockout::LOCKOUTS.check(&account.name, now)?;
+    Ok(policy)
+}
+
+/// Check if the second token is valid for the account and differs from the first one.
+///
+/// At least one period of skew is allowed, since only one code is valid otherwise.
+///
+/// Returns the time step which the second token is valid for.
+fn check_second_token(
+    account: &Account,
+    skew: u8,
+    token: &str,
+    second_token: &str,
+    now: u64,
+) -> crate::Result<u64> {
+    if second_token == token {
+        return Err(crate::Error::TotpInvalid);
+    }
+    check_token(account, skew.max(1), second_token, now)
+}
+
+/// Check if the token is valid for the account at `now`, allowing `skew` periods
+/// behind or ahead of the current one.
+///
+/// Returns the time step (i.e. the counter of RFC 6238) which the token is valid for.
+fn check_token(account: &Account, skew: u8, token: &str, now: u64) -> crate::Result<u64> {
+    let digits = account.totp.digits;
+    if token.len() != usize::from(digits) || token.parse::<u32>().is_err() {
+        return Err(crate::Error::TotpInvalidFormat(digits));
+    }
+    let totp = new_totp_with_label(
+        account.secret.clone(),
+        &account.totp,
+        0,
+        crate::PKG_NAME,
+        &account.name,
+    );
+    let current = now.div_euclid(totp.step);
+    let skew = u64::from(skew);
+    let step = (current.saturating_sub(skew)..=current.saturating_add(skew))
+        .find(|&step| totp.check(token, step * totp.step))
+        .ok_or(crate::Error::TotpInvalid)?;
+    tracing::debug!("Correct TOTP: {token}.");
+    Ok(step)
+}
+
+/// Tracks the time step of the last accepted code per account, so that each code
+/// is accepted once by any route, and older codes of the account are rejected.
+///
+/// Steps are kept in memory only, so a code which has been accepted just before
+/// a restart may be accepted once more within the skew of its policy.
+#[derive(Debug, Default)]
+pub(crate) struct UsedSteps {
+    last_steps: Mutex<HashMap<String, u64>>,
+}
+
+impl UsedSteps {
+    /// Accept the codes of the account valid for `oldest` to `latest` time steps.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::TotpReplayed`] if a code of the account
+    /// valid for `oldest` (or a later step) has been accepted.
+    pub(crate) fn accept(&self, account: &str, oldest: u64, latest: u64) -> crate::Result<()> {
+        let mut last_steps = self
+            .last_steps
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if last_steps.get(account).is_some_and(|&last| last >= oldest) {
+            return Err(crate::Error::TotpReplayed);
+        }
+        last_steps.insert(account.to_owned(), latest);
         Ok(())
-    } else {
-        Err(crate::Error::TotpInvalid)
+    }
+
+    /// Forget the codes of the account, e.g. once its secret has been replaced.
+    pub(crate) fn forget(&self, account: &str) {
+        self.last_steps
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(account);
     }
 }
 
+/// Time steps of the codes which have been accepted, which are kept in memory only.
+pub(crate) static USED_STEPS: LazyLock<UsedSteps> = LazyLock::new(UsedSteps::default);
+
 /// Print the base32-endcode secret by [`tracing::info!()`].
 pub(crate) fn print_secret_base32() {
     let totp = new_totp(VEC_SECRET.clone());
@@ -140,22 +536,99 @@ pub(crate) fn print_secret_base32() {
 /// Panics if the QR code cannot be constructed (e.g. when the data is too long).
 #[expect(clippy::print_stdout)]
 pub(crate) fn print_qr_code() {
-    use qrcode::render::unicode;
-
-    let totp = new_totp(VEC_SECRET.clone());
-    let url = totp.get_url();
+    let account = crate::store::ACCOUNTS
+        .get(DEFAULT_ACCOUNT)
+        .unwrap_or_else(|e| panic!("The default account always exists. Error: {e}."));
+    let url = crate::otpauth::OTPAUTH_CONFIG.url(&account);
     println!("\n{url}");
 
-    let code = qrcode::QrCode::new(url)
+    let image = crate::qr::render_qr_code(&url)
         .unwrap_or_else(|e| panic!("Failed to convert the URL to QR code: {e}."));
-    let image = code
-        .render::<unicode::Dense1x2>()
-        .dark_color(unicode::Dense1x2::Light)
-        .light_color(unicode::Dense1x2::Dark)
-        .build();
     println!("\n{image}");
 }
 
+/// How secrets generated by [`generate_secret`] are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SecretEncoding {
+    /// Alphanumeric characters, which can be used as `RAW_SECRET` as they are.
+    #[default]
+    Raw,
+    /// Base32 (RFC 4648, without padding), which authenticator apps accept as setup keys.
+    Base32,
+    /// Hexadecimal.
+    Hex,
+}
+
+/// Generate a random secret of the given bit length.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if `bits` is smaller than 128 or isn't a multiple of 8.
+pub fn generate_secret(bits: usize, encoding: SecretEncoding) -> crate::Result<String> {
+    use rand::distr::{Alphanumeric, SampleString};
+    if bits < 128 || !bits.is_multiple_of(8) {
+        return Err(crate::Error::InvalidInput(format!(
+            "bit length must be a multiple of 8 and at least 128 (the given one is {bits})"
+        )));
+    }
+    let len = bits.div_euclid(8);
+    let random_bytes = || (0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+    Ok(match encoding {
+        SecretEncoding::Raw => Alphanumeric.sample_string(&mut rand::rng(), len),
+        SecretEncoding::Base32 => totp_rs::Secret::Raw(random_bytes())
+            .to_encoded()
+            .to_string(),
+        SecretEncoding::Hex => hex::encode(random_bytes()),
+    })
+}
+
+/// Get the current token of the account.
+///
+/// The default account is set by env var `RAW_SECRET`,
+/// and the other ones are read from the file at env var `ACCOUNTS_PATH`.
+///
+/// # Errors
+///
+/// Returns Err if the account doesn't exist, or the system time is invalid.
+pub fn current_token(account: &str) -> crate::Result<String> {
+    let account = crate::store::ACCOUNTS.get(account)?;
+    let totp = new_totp_with_label(
+        account.secret,
+        &account.totp,
+        DEFAULT_SKEW,
+        crate::PKG_NAME,
+        &account.name,
+    );
+    Ok(totp.generate_current()?)
+}
+
+/// Check if the token of the account is valid, without recording it in the audit log.
+///
+/// # Errors
+///
+/// Returns Err if the account doesn't exist or the token is invalid.
+pub fn verify_token(account: &str, token: &str) -> crate::Result<()> {
+    let account = crate::store::ACCOUNTS.get(account)?;
+    check_token(
+        &account,
+        crate::store::ACCOUNTS.policy(&account).skew,
+        token,
+        crate::utils::now_secs(),
+    )
+    .map(drop)
+}
+
+/// Get the `otpauth://` URL of the account, which authenticator apps scan.
+///
+/// # Errors
+///
+/// Returns [`crate::Error::AccountNotFound`] if the account doesn't exist.
+pub fn get_otpauth_url(account: &str) -> crate::Result<String> {
+    crate::store::ACCOUNTS
+        .get(account)
+        .map(|account| crate::otpauth::OTPAUTH_CONFIG.url(&account))
+}
+
 #[cfg(test)]
 mod tests {
     #![expect(unsafe_code)]
@@ -163,17 +636,46 @@ mod tests {
     use super::*;
     use rstest::rstest;
 
+    #[test]
+    fn test_used_steps() {
+        let used = UsedSteps::default();
+        used.accept("alice", 10, 10).unwrap();
+        // The same (or an older) code of the account cannot be accepted again.
+        for step in [9, 10] {
+            assert!(matches!(
+                used.accept("alice", step, step),
+                Err(crate::Error::TotpReplayed)
+            ));
+        }
+        assert!(used.accept("bob", 10, 10).is_ok());
+        // Two codes are accepted unless the older one has been used.
+        assert!(used.accept("alice", 10, 11).is_err());
+        assert!(used.accept("alice", 11, 12).is_ok());
+        assert!(used.accept("alice", 12, 12).is_err());
+        used.forget("alice");
+        assert!(used.accept("alice", 12, 12).is_ok());
+    }
+
     /// Get the current token.
-    fn get_token() -> crate::Result<Json<InputToken>> {
+    fn get_token() -> crate::Result<Input<InputToken>> {
         let token = try_get_token(&VEC_SECRET)?;
         let my_token = InputToken::new(token);
-        Ok(Json(my_token))
+        Ok(Input(my_token))
     }
 
     #[tokio::test]
     async fn test_token_checker_correct() {
         let my_token = get_token().unwrap();
-        check_current(my_token).await.unwrap();
+        let verification = check_current(
+            crate::ClientInfo::default(),
+            crate::Caller::anonymous(),
+            HeaderMap::new(),
+            my_token,
+        )
+        .await
+        .unwrap();
+        assert_eq!(verification.account, DEFAULT_ACCOUNT);
+        assert!(verification.grant.scopes.is_empty());
     }
 
     #[test]
@@ -219,4 +721,39 @@ mod tests {
     fn test_print_qr_code() {
         print_qr_code();
     }
+
+    #[rstest]
+    #[case(128, SecretEncoding::Raw, 16)]
+    #[case(160, SecretEncoding::Base32, 32)]
+    #[case(256, SecretEncoding::Hex, 64)]
+    fn test_generate_secret(
+        #[case] bits: usize,
+        #[case] encoding: SecretEncoding,
+        #[case] len: usize,
+    ) {
+        let secret = generate_secret(bits, encoding).unwrap();
+        assert_eq!(secret.len(), len);
+        assert!(secret.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(secret, generate_secret(bits, encoding).unwrap());
+    }
+
+    #[rstest]
+    #[case(0)]
+    #[case(120)]
+    #[case(129)]
+    fn test_generate_secret_err(#[case] bits: usize) {
+        assert!(generate_secret(bits, SecretEncoding::Raw).is_err());
+    }
+
+    #[test]
+    fn test_current_token() {
+        let token = current_token(DEFAULT_ACCOUNT).unwrap();
+        verify_token(DEFAULT_ACCOUNT, &token).unwrap();
+        assert!(current_token("missing").is_err());
+        assert!(
+            get_otpauth_url(DEFAULT_ACCOUNT)
+                .unwrap()
+                .starts_with("otpauth://totp/")
+        );
+    }
 }
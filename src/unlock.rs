@@ -0,0 +1,297 @@
+use crate::offline::{MAX_FEATURES, MAX_FIELD_LEN, UNLOCK_TOKEN_VERSION};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// Env var used to set the key (base64 of a 32-byte Ed25519 seed) which unlock tokens
+/// are signed with.
+const UNLOCK_SIGNING_KEY: &str = "UNLOCK_SIGNING_KEY";
+/// Env var used to set how long (in seconds) unlock tokens are valid.
+const UNLOCK_TTL: &str = "UNLOCK_TTL";
+
+/// Claims of an unlock token, which game clients verify offline
+/// (see [`crate::offline::verify_unlock_token`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub(crate) struct UnlockClaims {
+    /// The account whose code has been verified.
+    #[schema(example = "alice")]
+    pub(crate) account: String,
+    /// The device which the token is bound to.
+    #[schema(example = "3f2a9c1e-device")]
+    pub(crate) device_id: String,
+    /// Features which the token unlocks.
+    #[schema(example = json!(["console", "cheats"]))]
+    pub(crate) features: Vec<String>,
+    /// When the TOTP code was verified (seconds since the Unix epoch).
+    pub(crate) iat: u64,
+    /// When the token expires (seconds since the Unix epoch).
+    pub(crate) exp: u64,
+}
+
+/// Settings of unlock tokens.
+#[derive(Debug)]
+pub(crate) struct UnlockConfig {
+    signing_key: SigningKey,
+    /// Seconds which unlock tokens are valid for.
+    pub(crate) ttl: u64,
+}
+
+impl UnlockConfig {
+    /// The public key, which game clients compile in.
+    pub(crate) fn public_key(&self) -> [u8; 32] {
+        self.signing_key.verifying_key().to_bytes()
+    }
+
+    /// Sign the message with the key of unlock tokens, e.g. assertions of
+    /// challenges (see [`crate::challenge`]), which are verified with the same public key.
+    pub(crate) fn sign(&self, message: &[u8]) -> [u8; 64] {
+        self.signing_key.sign(message).to_bytes()
+    }
+
+    /// Issue an unlock token of the account bound to the device,
+    /// whose code has been verified at `now`.
+    ///
+    /// Tokens are base64url-encoded (see [`crate::offline`] for their layout).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidInput`] if the device id or the features are invalid.
+    pub(crate) fn issue(
+        &self,
+        account: &str,
+        device_id: &str,
+        features: &[String],
+        now: u64,
+    ) -> crate::Result<(String, UnlockClaims)> {
+        validate_claims(device_id, features)?;
+        let mut features = features.to_vec();
+        features.sort_unstable();
+        features.dedup();
+        let claims = UnlockClaims {
+            account: account.to_owned(),
+            device_id: device_id.to_owned(),
+            features,
+            iat: now,
+            exp: now.saturating_add(self.ttl),
+        };
+        let mut token = encode_payload(&claims);
+        let signature = self.sign(&token);
+        token.extend_from_slice(&signature);
+        Ok((URL_SAFE_NO_PAD.encode(token), claims))
+    }
+}
+
+/// Check if the device id and the features fit into unlock tokens.
+///
+/// Features are named like scopes (see [`crate::scope::validate_scope_name`]).
+///
+/// # Errors
+///
+/// Returns [`crate::Error::InvalidInput`] if either of them is invalid.
+pub(crate) fn validate_claims(device_id: &str, features: &[String]) -> crate::Result<()> {
+    let invalid = |reason: String| Err(crate::Error::InvalidInput(reason));
+    if device_id.is_empty() || device_id.len() > MAX_FIELD_LEN {
+        return invalid(format!("device_id must be 1 to {MAX_FIELD_LEN} bytes long"));
+    }
+    if features.len() > MAX_FEATURES {
+        return invalid(format!("at most {MAX_FEATURES} features can be unlocked"));
+    }
+    for feature in features {
+        crate::scope::validate_scope_name(feature)?;
+    }
+    Ok(())
+}
+
+/// Encode the claims in the layout of [`crate::offline`], without the signature.
+fn encode_payload(claims: &UnlockClaims) -> Vec<u8> {
+    let mut payload = vec![UNLOCK_TOKEN_VERSION];
+    payload.extend_from_slice(&claims.iat.to_be_bytes());
+    payload.extend_from_slice(&claims.exp.to_be_bytes());
+    push_str(&mut payload, &claims.account);
+    push_str(&mut payload, &claims.device_id);
+    payload.push(to_u8(claims.features.len()));
+    for feature in &claims.features {
+        push_str(&mut payload, feature);
+    }
+    payload
+}
+
+/// Append the string prefixed by its length.
+fn push_str(payload: &mut Vec<u8>, value: &str) {
+    payload.push(to_u8(value.len()));
+    payload.extend_from_slice(value.as_bytes());
+}
+
+/// Convert lengths, which have been checked by [`validate_claims`] (or by
+/// [`crate::store::validate_name`] for account names), into single bytes.
+fn to_u8(len: usize) -> u8 {
+    u8::try_from(len).unwrap_or_else(|e| panic!("Length {len} has been checked. Error: {e}."))
+}
+
+/// Settings of unlock tokens.
+///
+/// If env var `UNLOCK_SIGNING_KEY` hasn't been set, a random key is used,
+/// thus tokens cannot be verified by public keys compiled into clients.
+///
+/// # Panics
+///
+/// Panics when `UNLOCK_SIGNING_KEY` isn't base64 of 32 bytes,
+/// or when `UNLOCK_TTL` isn't a positive integer.
+pub(crate) static UNLOCK_CONFIG: LazyLock<UnlockConfig> = LazyLock::new(init_unlock_config);
+
+fn init_unlock_config() -> UnlockConfig {
+    let seed = if let Ok(value) = std::env::var(UNLOCK_SIGNING_KEY) {
+        STANDARD
+            .decode(value.trim())
+            .ok()
+            .and_then(|seed| <[u8; 32]>::try_from(seed).ok())
+            .unwrap_or_else(|| panic!("{UNLOCK_SIGNING_KEY} must be base64 of 32 bytes!"))
+    } else {
+        tracing::warn!(
+            "Env var {UNLOCK_SIGNING_KEY} hasn't been set. Using a random key, \
+            whose unlock tokens cannot be verified by clients."
+        );
+        rand::random::<[u8; 32]>()
+    };
+    let ttl = std::env::var(UNLOCK_TTL).map_or(86400, |value| {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|&ttl| ttl != 0)
+            .unwrap_or_else(|| panic!("{UNLOCK_TTL} must be a positive integer!"))
+    });
+    UnlockConfig {
+        signing_key: SigningKey::from_bytes(&seed),
+        ttl,
+    }
+}
+
+/// Generate a random signing key of unlock tokens, returning it base64-encoded
+/// (to be set as env var `UNLOCK_SIGNING_KEY`) along with its public key.
+#[must_use]
+pub fn generate_unlock_key() -> (String, [u8; 32]) {
+    let seed = rand::random::<[u8; 32]>();
+    let public_key = SigningKey::from_bytes(&seed).verifying_key().to_bytes();
+    (STANDARD.encode(seed), public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    #![expect(unsafe_code)]
+
+    use super::*;
+    use crate::offline::{UnlockError, decode_unlock_token, verify_unlock_token};
+    use rstest::rstest;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn test_config() -> UnlockConfig {
+        UnlockConfig {
+            signing_key: SigningKey::from_bytes(&[7; 32]),
+            ttl: 60,
+        }
+    }
+
+    fn features(features: &[&str]) -> Vec<String> {
+        features.iter().map(|&feature| feature.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_unlock_token() {
+        let config = test_config();
+        let (token, claims) = config
+            .issue(
+                "alice",
+                "device-1",
+                &features(&["log", "cheats", "log"]),
+                NOW,
+            )
+            .unwrap();
+        assert_eq!(claims.features, ["cheats", "log"]);
+        assert_eq!(claims.exp, NOW + 60);
+
+        let mut buf = [0; crate::offline::MAX_UNLOCK_TOKEN_LEN];
+        let bytes = decode_unlock_token(&token, &mut buf).unwrap();
+        let key = config.public_key();
+        let verified = verify_unlock_token(&key, bytes, "device-1", NOW + 59).unwrap();
+        assert_eq!(verified.account, "alice");
+        assert_eq!(verified.issued_at, NOW);
+        assert_eq!(verified.expires_at, NOW + 60);
+        assert!(verified.features().eq(["cheats", "log"]));
+        assert!(verified.has_feature("cheats"));
+        assert!(!verified.has_feature("console"));
+
+        let verify = |key, device_id, now| verify_unlock_token(key, bytes, device_id, now);
+        assert_eq!(
+            verify(&key, "device-2", NOW).unwrap_err(),
+            UnlockError::WrongDevice
+        );
+        assert_eq!(
+            verify(&key, "device-1", NOW + 60).unwrap_err(),
+            UnlockError::Expired
+        );
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes();
+        assert_eq!(
+            verify(&other, "device-1", NOW).unwrap_err(),
+            UnlockError::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn test_unlock_token_tampered() {
+        let config = test_config();
+        let (token, _) = config
+            .issue("alice", "device-1", &features(&["log"]), NOW)
+            .unwrap();
+        let mut buf = [0; crate::offline::MAX_UNLOCK_TOKEN_LEN];
+        let mut bytes = decode_unlock_token(&token, &mut buf).unwrap().to_vec();
+        // Extend the expiry.
+        bytes[16] = bytes[16].wrapping_add(1);
+        assert_eq!(
+            verify_unlock_token(&config.public_key(), &bytes, "device-1", NOW).unwrap_err(),
+            UnlockError::InvalidSignature
+        );
+        assert_eq!(
+            verify_unlock_token(&config.public_key(), &bytes[..10], "device-1", NOW).unwrap_err(),
+            UnlockError::Malformed
+        );
+        assert!(decode_unlock_token("not base64!", &mut buf).is_err());
+    }
+
+    #[rstest]
+    #[case("", &[])]
+    #[case(&"d".repeat(65), &[])]
+    #[case("device-1", &[""])]
+    #[case("device-1", &["god mode"])]
+    #[case("device-1", &["f"; 17])]
+    fn test_unlock_claims_invalid(#[case] device_id: &str, #[case] invalid: &[&str]) {
+        assert!(matches!(
+            test_config().issue("alice", device_id, &features(invalid), NOW),
+            Err(crate::Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_unlock_key() {
+        let (seed, public_key) = generate_unlock_key();
+        unsafe { std::env::set_var(UNLOCK_SIGNING_KEY, seed) }
+        assert_eq!(UNLOCK_CONFIG.public_key(), public_key);
+        assert_eq!(UNLOCK_CONFIG.ttl, 86400);
+    }
+
+    #[test]
+    #[should_panic(expected = "UNLOCK_SIGNING_KEY must be base64 of 32 bytes!")]
+    fn test_unlock_signing_key_var_panic() {
+        unsafe { std::env::set_var(UNLOCK_SIGNING_KEY, "dG9vIHNob3J0") }
+        let _ = &*UNLOCK_CONFIG;
+    }
+
+    #[test]
+    #[should_panic(expected = "UNLOCK_TTL must be a positive integer!")]
+    fn test_unlock_ttl_var_panic() {
+        unsafe { std::env::set_var(UNLOCK_TTL, "0") }
+        let _ = &*UNLOCK_CONFIG;
+    }
+}
@@ -13,8 +13,22 @@ pub(crate) async fn handler_405() -> impl IntoResponse {
 }
 
 /// Health check.
+///
+/// It reports unhealthy while the server is draining before shutdown,
+/// so that load balancers stop routing new requests to it.
 pub(crate) async fn health() -> impl IntoResponse {
-    (StatusCode::OK, "200 OK")
+    if crate::is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "503 Service Unavailable")
+    } else {
+        (StatusCode::OK, "200 OK")
+    }
+}
+
+/// Seconds since the Unix epoch, or 0 if the system time is earlier than that.
+pub(crate) fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
 }
 
 /// Sleep for given seconds.
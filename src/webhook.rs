@@ -0,0 +1,520 @@
+use crate::audit::AuditAction;
+use crate::client::ClientInfo;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Env var used to set webhook URLs (comma-separated).
+///
+/// Each URL can be prefixed by its format, e.g. `slack+https://hooks.slack.com/...`
+/// or `discord+https://discord.com/api/webhooks/...`. URLs without a prefix
+/// receive generic JSON payloads.
+const WEBHOOK_URLS: &str = "WEBHOOK_URLS";
+/// Env var used to set the secret which signs webhook requests by HMAC-SHA256.
+const WEBHOOK_SECRET: &str = "WEBHOOK_SECRET";
+/// Env var used to set [`WebhookConfig::failure_threshold`].
+const WEBHOOK_FAILURE_THRESHOLD: &str = "WEBHOOK_FAILURE_THRESHOLD";
+/// Env var used to set [`WebhookConfig::failure_window`] in seconds.
+const WEBHOOK_FAILURE_WINDOW: &str = "WEBHOOK_FAILURE_WINDOW";
+
+/// Header of the Unix timestamp (seconds) when the request is signed.
+pub(crate) const TIMESTAMP_HEADER: &str = "x-totp-timestamp";
+/// Header of the HMAC-SHA256 signature over `{timestamp}.{body}`.
+pub(crate) const SIGNATURE_HEADER: &str = "x-totp-signature";
+
+/// Maximum number of events waiting to be sent to each webhook.
+const QUEUE_CAPACITY: usize = 256;
+/// Timeout of each webhook request, including reading the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Timeout of connecting to a webhook.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of attempts to deliver an event to a webhook.
+const MAX_ATTEMPTS: u32 = 4;
+/// Maximum number of IP addresses tracked for repeated failures.
+const MAX_TRACKED_IPS: usize = 10_000;
+
+/// Payload format of a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WebhookFormat {
+    /// Generic JSON (see [`WebhookEvent`]).
+    Generic,
+    /// Slack incoming webhooks (`{"text": ...}`).
+    Slack,
+    /// Discord webhooks (`{"content": ...}`).
+    Discord,
+}
+
+/// A webhook which events are sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WebhookTarget {
+    pub(crate) format: WebhookFormat,
+    pub(crate) url: String,
+}
+
+impl std::str::FromStr for WebhookTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (format, url) = match s.split_once('+') {
+            Some(("slack", url)) => (WebhookFormat::Slack, url),
+            Some(("discord", url)) => (WebhookFormat::Discord, url),
+            Some(("generic", url)) => (WebhookFormat::Generic, url),
+            _ => (WebhookFormat::Generic, s),
+        };
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!(
+                "{WEBHOOK_URLS} must contain http(s) URLs (the given one is {s:?})."
+            ));
+        }
+        Ok(WebhookTarget {
+            format,
+            url: url.to_owned(),
+        })
+    }
+}
+
+/// Webhook configuration read from env vars.
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookConfig {
+    pub(crate) targets: Vec<WebhookTarget>,
+    pub(crate) secret: Option<String>,
+    /// Number of failures from a new IP which triggers [`WebhookEventKind::RepeatedFailures`].
+    pub(crate) failure_threshold: usize,
+    /// Time window in which failures are counted.
+    pub(crate) failure_window: Duration,
+}
+
+fn init_webhook_config() -> WebhookConfig {
+    let targets = std::env::var(WEBHOOK_URLS)
+        .map(|value| {
+            value
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.parse().unwrap_or_else(|e: String| panic!("{e}")))
+                .collect()
+        })
+        .unwrap_or_default();
+    let parse_var = |key: &str, default_value: u64| {
+        std::env::var(key).map_or(default_value, |value| {
+            value
+                .parse::<u64>()
+                .ok()
+                .filter(|&v| v != 0)
+                .unwrap_or_else(|| panic!("{key} must be a positive integer!"))
+        })
+    };
+    WebhookConfig {
+        targets,
+        secret: std::env::var(WEBHOOK_SECRET).ok(),
+        failure_threshold: usize::try_from(parse_var(WEBHOOK_FAILURE_THRESHOLD, 5))
+            .unwrap_or(usize::MAX),
+        failure_window: Duration::from_secs(parse_var(WEBHOOK_FAILURE_WINDOW, 300)),
+    }
+}
+
+/// Webhook configuration.
+///
+/// # Panics
+///
+/// Panics when any of the related env vars cannot be parsed.
+pub(crate) static WEBHOOK_CONFIG: LazyLock<WebhookConfig> = LazyLock::new(init_webhook_config);
+
+/// What kind of suspicious activity is notified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WebhookEventKind {
+    /// An account has been locked out.
+    Lockout,
+    /// Repeated verification failures from an IP that has never succeeded.
+    RepeatedFailures,
+    /// The secret of an account has been rotated.
+    SecretRotation,
+    /// An account has been enrolled.
+    Enrollment,
+}
+
+/// The generic JSON payload sent to webhooks.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WebhookEvent {
+    pub(crate) event: WebhookEventKind,
+    pub(crate) account: String,
+    pub(crate) source_ip: Option<IpAddr>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) timestamp: u64,
+    pub(crate) message: String,
+}
+
+impl WebhookEvent {
+    fn new(event: WebhookEventKind, account: &str, client: &ClientInfo) -> Self {
+        let ip = client
+            .ip
+            .map_or_else(|| "unknown IP".to_owned(), |ip| ip.to_string());
+        let message = match event {
+            WebhookEventKind::Lockout => format!("Account {account} has been locked out ({ip})."),
+            WebhookEventKind::RepeatedFailures => {
+                format!("Repeated verification failures of account {account} from {ip}.")
+            }
+            WebhookEventKind::SecretRotation => {
+                format!("The secret of account {account} has been rotated ({ip}).")
+            }
+            WebhookEventKind::Enrollment => format!("Account {account} has been enrolled ({ip})."),
+        };
+        WebhookEvent {
+            event,
+            account: account.to_owned(),
+            source_ip: client.ip,
+            request_id: client.request_id.clone(),
+            timestamp: crate::utils::now_secs(),
+            message: format!("[{}] {message}", crate::PKG_NAME),
+        }
+    }
+
+    /// Render the request body in the given format.
+    fn body(&self, format: WebhookFormat) -> Vec<u8> {
+        let value = match format {
+            WebhookFormat::Generic => serde_json::to_value(self),
+            WebhookFormat::Slack => Ok(serde_json::json!({ "text": self.message })),
+            WebhookFormat::Discord => Ok(serde_json::json!({ "content": self.message })),
+        };
+        value
+            .and_then(|v| serde_json::to_vec(&v))
+            .unwrap_or_else(|e| panic!("Failed to serialize webhook event. Error: {e}."))
+    }
+}
+
+/// Compute the hex-encoded HMAC-SHA256 of `message`.
+pub(crate) fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    use hmac::{Hmac, KeyInit, Mac};
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key)
+        .unwrap_or_else(|e| panic!("HMAC accepts keys of any size. Error: {e}."));
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Build the HTTP client of webhooks, so that an unresponsive webhook can't stall deliveries.
+fn http_client(timeout: Duration, connect_timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build the webhook client. Error: {e}."))
+}
+
+/// Send an event to a webhook, retrying with exponential backoff.
+async fn deliver(
+    client: &reqwest::Client,
+    target: &WebhookTarget,
+    secret: Option<&str>,
+    event: &WebhookEvent,
+    backoff: Duration,
+) -> Result<(), String> {
+    let body = event.body(target.format);
+    let mut delay = backoff;
+    let mut last_error = String::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        if attempt > 1 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        let timestamp = crate::utils::now_secs().to_string();
+        let mut request = client
+            .post(&target.url)
+            .header("content-type", "application/json")
+            .header(TIMESTAMP_HEADER, &timestamp);
+        if let Some(secret) = secret {
+            let message = [timestamp.as_bytes(), b".", &body].concat();
+            let signature = hmac_sha256_hex(secret.as_bytes(), &message);
+            request = request.header(SIGNATURE_HEADER, format!("sha256={signature}"));
+        }
+        match request.body(body.clone()).send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) if res.status().is_client_error() && res.status().as_u16() != 429 => {
+                return Err(format!("webhook responded {}", res.status()));
+            }
+            Ok(res) => last_error = format!("webhook responded {}", res.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+        tracing::debug!(
+            attempt,
+            url = target.url,
+            "Failed to deliver webhook: {last_error}."
+        );
+    }
+    Err(last_error)
+}
+
+/// The senders of the bounded queues, one per webhook, which is empty if no webhook is configured.
+static WEBHOOK_QUEUES: LazyLock<Vec<mpsc::Sender<WebhookEvent>>> = LazyLock::new(|| {
+    let config = &*WEBHOOK_CONFIG;
+    let client = http_client(REQUEST_TIMEOUT, CONNECT_TIMEOUT);
+    config
+        .targets
+        .iter()
+        .map(|target| {
+            let (tx, mut rx) = mpsc::channel::<WebhookEvent>(QUEUE_CAPACITY);
+            let client = client.clone();
+            // A separate background task per webhook, so that verification latency isn't
+            // affected and a slow webhook doesn't delay the others.
+            tokio::spawn(async move {
+                let secret = config.secret.as_deref();
+                while let Some(event) = rx.recv().await {
+                    if let Err(e) =
+                        deliver(&client, target, secret, &event, Duration::from_secs(1)).await
+                    {
+                        tracing::error!(url = target.url, "Failed to deliver webhook: {e}.");
+                    }
+                }
+            });
+            tx
+        })
+        .collect()
+});
+
+/// Put an event into the queue of each webhook, which is dropped if that queue is full.
+fn enqueue(event: WebhookEvent) {
+    for tx in WEBHOOK_QUEUES.iter() {
+        if let Err(e) = tx.try_send(event.clone()) {
+            tracing::warn!("Webhook event is dropped: {e}.");
+        }
+    }
+}
+
+/// Verification history of an IP address.
+#[derive(Debug, Default)]
+struct IpHistory {
+    /// Whether any verification from this IP has succeeded.
+    succeeded: bool,
+    /// When recent verifications failed.
+    failures: VecDeque<Instant>,
+}
+
+/// Tracks verification failures per IP address.
+#[derive(Debug, Default)]
+pub(crate) struct FailureTracker {
+    history: HashMap<IpAddr, IpHistory>,
+}
+
+impl FailureTracker {
+    /// Record a verification result, and return true if the failures
+    /// from a new IP have just reached the threshold.
+    pub(crate) fn record(
+        &mut self,
+        ip: IpAddr,
+        success: bool,
+        threshold: usize,
+        window: Duration,
+    ) -> bool {
+        let now = Instant::now();
+        if self.history.len() >= MAX_TRACKED_IPS && !self.history.contains_key(&ip) {
+            self.history
+                .retain(|_, h| h.failures.back().is_some_and(|t| now - *t < window));
+        }
+        let history = self.history.entry(ip).or_default();
+        if success {
+            history.succeeded = true;
+            history.failures.clear();
+            return false;
+        }
+        if history.succeeded {
+            return false;
+        }
+        while history.failures.front().is_some_and(|t| now - *t >= window) {
+            history.failures.pop_front();
+        }
+        history.failures.push_back(now);
+        if history.failures.len() >= threshold {
+            history.failures.clear();
+            return true;
+        }
+        false
+    }
+}
+
+static FAILURE_TRACKER: LazyLock<Mutex<FailureTracker>> = LazyLock::new(Mutex::default);
+
+/// Notify webhooks of an audited operation if it's suspicious.
+pub(crate) fn notify(action: AuditAction, account: &str, client: &ClientInfo, success: bool) {
+    if WEBHOOK_CONFIG.targets.is_empty() {
+        return;
+    }
+    let kind = match action {
+        AuditAction::Lockout => WebhookEventKind::Lockout,
+        AuditAction::Rotate if success => WebhookEventKind::SecretRotation,
+        AuditAction::Enroll if success => WebhookEventKind::Enrollment,
+        AuditAction::Verify => {
+            let Some(ip) = client.ip else { return };
+            let config = &*WEBHOOK_CONFIG;
+            let reached = FAILURE_TRACKER
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .record(ip, success, config.failure_threshold, config.failure_window);
+            if !reached {
+                return;
+            }
+            WebhookEventKind::RepeatedFailures
+        }
+        AuditAction::Rotate
+        | AuditAction::Enroll
+        | AuditAction::Remove
+        | AuditAction::Import
+        | AuditAction::Export
+        | AuditAction::Disable
+        | AuditAction::Enable
+        | AuditAction::Reset
+        | AuditAction::Deny
+        | AuditAction::Policy
+        | AuditAction::Approve => return,
+    };
+    enqueue(WebhookEvent::new(kind, account, client));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use rstest::rstest;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Vec<u8>)>>>;
+
+    /// Start a local HTTP stand-in for webhooks, which fails the first `failures` requests.
+    async fn setup_receiver(failures: usize) -> (String, Received) {
+        let received: Received = Arc::default();
+        let count = Arc::new(AtomicUsize::new(0));
+        let router = axum::Router::new().route(
+            "/hook",
+            axum::routing::post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: axum::body::Bytes| async move {
+                    if count.fetch_add(1, Ordering::Relaxed) < failures {
+                        return StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    received.lock().unwrap().push((headers, body.to_vec()));
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        (format!("http://{addr}/hook"), received)
+    }
+
+    fn test_event() -> WebhookEvent {
+        let client = ClientInfo {
+            ip: Some(IpAddr::from([192, 0, 2, 1])),
+            ..ClientInfo::default()
+        };
+        WebhookEvent::new(WebhookEventKind::Lockout, "default", &client)
+    }
+
+    #[rstest]
+    #[case("https://example.com/hook", WebhookFormat::Generic)]
+    #[case("slack+https://hooks.slack.com/x", WebhookFormat::Slack)]
+    #[case("discord+https://discord.com/api/webhooks/x", WebhookFormat::Discord)]
+    fn test_webhook_target_parse(#[case] value: &str, #[case] format: WebhookFormat) {
+        let target: WebhookTarget = value.parse().unwrap();
+        assert_eq!(target.format, format);
+        assert!(target.url.starts_with("https://"));
+    }
+
+    #[test]
+    fn test_webhook_target_parse_error() {
+        assert!("slack+ftp://example.com".parse::<WebhookTarget>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_signed() {
+        let (url, received) = setup_receiver(0).await;
+        let target = WebhookTarget {
+            format: WebhookFormat::Generic,
+            url,
+        };
+        let event = test_event();
+        let client = reqwest::Client::new();
+        deliver(&client, &target, Some("secret"), &event, Duration::ZERO)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[0];
+        let timestamp = headers[TIMESTAMP_HEADER].to_str().unwrap();
+        let message = [timestamp.as_bytes(), b".", body].concat();
+        let expected = format!("sha256={}", hmac_sha256_hex(b"secret", &message));
+        assert_eq!(headers[SIGNATURE_HEADER].to_str().unwrap(), expected);
+        let json: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(json["event"], "lockout");
+        assert_eq!(json["source_ip"], "192.0.2.1");
+    }
+
+    #[rstest]
+    #[case(WebhookFormat::Slack, "text")]
+    #[case(WebhookFormat::Discord, "content")]
+    #[tokio::test]
+    async fn test_deliver_retry(#[case] format: WebhookFormat, #[case] key: &str) {
+        let (url, received) = setup_receiver(2).await;
+        let target = WebhookTarget { format, url };
+        let client = reqwest::Client::new();
+        deliver(
+            &client,
+            &target,
+            None,
+            &test_event(),
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert!(headers.get(SIGNATURE_HEADER).is_none());
+        let json: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert!(json[key].as_str().unwrap().contains("locked out"));
+    }
+
+    #[tokio::test]
+    async fn test_deliver_timeout() {
+        // A webhook which accepts connections but never responds.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        let target = WebhookTarget {
+            format: WebhookFormat::Generic,
+            url: format!("http://{addr}/hook"),
+        };
+        let client = http_client(Duration::from_millis(50), Duration::from_millis(50));
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            deliver(&client, &target, None, &test_event(), Duration::ZERO),
+        )
+        .await
+        .expect("the delivery should time out");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_failure_tracker() {
+        let mut tracker = FailureTracker::default();
+        let window = Duration::from_mins(1);
+        let new_ip = IpAddr::from([192, 0, 2, 1]);
+        let known_ip = IpAddr::from([192, 0, 2, 2]);
+        assert!(!tracker.record(known_ip, true, 3, window));
+        for _ in 0..2 {
+            assert!(!tracker.record(new_ip, false, 3, window));
+            assert!(!tracker.record(known_ip, false, 3, window));
+        }
+        assert!(tracker.record(new_ip, false, 3, window));
+        assert!(!tracker.record(known_ip, false, 3, window));
+    }
+}
@@ -45,6 +45,91 @@ async fn test_totp_invalid_format() {
     assert_eq!(res.status(), StatusCode::BAD_REQUEST);
 }
 
+/// Every verification is recorded in the audit log, which can be verified by CLI.
+#[tokio::test]
+async fn test_audit_log() {
+    let path = std::env::temp_dir().join(format!("totp-audit-{}.jsonl", rand::random::<u64>()));
+    let path_str = path.to_str().unwrap();
+    let (mut _child, token, port) = common::setup_with_envs(&[("AUDIT_LOG_PATH", path_str)]).await;
+
+    for token in [token, common::get_random_6_digits()] {
+        let _ = reqwest::Client::new()
+            .post(format!("http://localhost:{port}"))
+            .json(&totp_server::InputToken::new(token))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let (status, stdout) = common::run_command(&["audit", "verify", path_str]).await;
+    assert!(status.success());
+    assert!(stdout.contains("2 records"));
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, content.replacen("default", "someone", 1)).unwrap();
+    let (status, _) = common::run_command(&["audit", "verify", path_str]).await;
+    assert!(!status.success());
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Send `SIGTERM` to the server, which should exit gracefully.
+#[tokio::test]
+#[cfg(unix)]
+async fn test_graceful_shutdown() {
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    let envs = [("SHUTDOWN_PRE_STOP_DELAY", "1")];
+    let (mut child, _, port) = common::setup_with_envs(&envs).await;
+    let pid = child.id().expect("the child process has exited");
+    let status = Command::new("kill")
+        .args(["-TERM", &pid.to_string()])
+        .status()
+        .await
+        .unwrap();
+    assert!(status.success());
+
+    // Connections are still accepted during the pre-stop delay, while the health check fails.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let response = reqwest::get(format!("http://localhost:{port}/health"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+    let status = tokio::time::timeout(Duration::from_secs(5), child.wait())
+        .await
+        .expect("the server didn't exit after SIGTERM")
+        .unwrap();
+    assert!(status.success());
+}
+
+/// Listen on IPv4, IPv6 and a Unix domain socket at the same time.
+#[tokio::test]
+#[cfg(unix)]
+async fn test_listen_addrs() {
+    let port = common::get_available_port();
+    let path = std::env::temp_dir().join(format!("totp-{port}.sock"));
+    let listen_addrs = format!("127.0.0.1:{port},[::1]:{port},unix:{}", path.display());
+    let raw_secret = common::get_random_secret();
+    let envs = [("LISTEN_ADDRS", listen_addrs.as_str())];
+    let _child = common::spawn_totp_process(&raw_secret, port, &envs);
+    common::wait_until_ready(port).await;
+
+    for url in [
+        format!("http://127.0.0.1:{port}/health"),
+        format!("http://[::1]:{port}/health"),
+    ] {
+        let response = reqwest::get(url).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+    let client = reqwest::Client::builder()
+        .unix_socket(path.as_path())
+        .build()
+        .unwrap();
+    let response = client.get("http://localhost/health").send().await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+}
+
 #[tokio::test]
 #[ignore = "just a demo which isn't relevant to this project"]
 async fn process_test() -> Result<()> {
@@ -62,3 +147,233 @@ async fn process_test() -> Result<()> {
     println!("output:\n{stdout}");
     Ok(())
 }
+
+/// Enrolled accounts are kept in the accounts file across restarts.
+#[tokio::test]
+async fn test_accounts_path() {
+    let path = std::env::temp_dir().join(format!("totp-accounts-{}.json", rand::random::<u64>()));
+    let envs = [("ACCOUNTS_PATH", path.to_str().unwrap())];
+    let url = |port: u16, path: &str| format!("http://localhost:{port}/v1{path}");
+
+    let (child, _, port) = common::setup_with_envs(&envs).await;
+    let res = reqwest::Client::new()
+        .post(url(port, "/enrollment"))
+        .json(&serde_json::json!({ "account": "alice" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::CREATED);
+    drop(child);
+
+    let (mut _child, _, port) = common::setup_with_envs(&envs).await;
+    let res = reqwest::get(url(port, "/accounts/alice")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Generated secrets are accepted as `RAW_SECRET`, whose codes can be printed and verified.
+#[tokio::test]
+async fn test_cli_secret_and_token() {
+    let (status, secret) = common::run_command(&["gen-secret"]).await;
+    assert!(status.success());
+    let secret = secret.trim();
+    assert_eq!(secret.len(), 20);
+    let (status, hex) =
+        common::run_command(&["gen-secret", "--bits", "256", "--encoding", "hex"]).await;
+    assert!(status.success());
+    assert_eq!(hex.trim().len(), 64);
+    let (status, _) = common::run_command(&["gen-secret", "--bits", "100"]).await;
+    assert!(!status.success());
+
+    let envs = [("RAW_SECRET", secret)];
+    let (status, token) = common::run_command_with_envs(&["token"], &envs).await;
+    assert!(status.success());
+    let token = token.trim();
+    assert_eq!(token.len(), 6);
+    let (status, stdout) = common::run_command_with_envs(&["verify", token], &envs).await;
+    assert!(status.success(), "{stdout}");
+    let wrong = format!("{:0>6}", (token.parse::<u32>().unwrap() + 1) % 1_000_000);
+    let (status, _) = common::run_command_with_envs(&["verify", &wrong], &envs).await;
+    assert!(!status.success());
+
+    let (status, url) = common::run_command_with_envs(&["qr", "--url-only"], &envs).await;
+    assert!(status.success());
+    assert!(url.starts_with("otpauth://totp/"));
+    let path = std::env::temp_dir().join(format!("totp-qr-{}.svg", rand::random::<u64>()));
+    let args = ["qr", "--output", path.to_str().unwrap(), "--ec-level", "h"];
+    let (status, _) = common::run_command_with_envs(&args, &envs).await;
+    assert!(status.success());
+    assert!(std::fs::read_to_string(&path).unwrap().contains("<svg"));
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Accounts can be managed offline in the accounts file.
+#[tokio::test]
+async fn test_cli_accounts() {
+    let path = std::env::temp_dir().join(format!("totp-accounts-{}.json", rand::random::<u64>()));
+    let secret = common::get_random_secret();
+    let envs = [
+        ("RAW_SECRET", secret.as_str()),
+        ("ACCOUNTS_PATH", path.to_str().unwrap()),
+    ];
+    let args = ["accounts", "add", "alice", "--issuer", "Game Studio"];
+    let (status, stdout) = common::run_command_with_envs(&args, &envs).await;
+    assert!(status.success());
+    assert!(stdout.contains("otpauth://totp/Game%20Studio:alice?"));
+    let (status, _) = common::run_command_with_envs(&["accounts", "add", "alice"], &envs).await;
+    assert!(!status.success());
+
+    let (status, stdout) = common::run_command_with_envs(&["accounts", "list"], &envs).await;
+    assert!(status.success());
+    let names: Vec<_> = stdout
+        .lines()
+        .filter_map(|l| l.split('\t').next())
+        .collect();
+    assert_eq!(names, ["default", "alice"]);
+    let (status, _) = common::run_command_with_envs(&["token", "--account", "alice"], &envs).await;
+    assert!(status.success());
+
+    let (status, _) = common::run_command_with_envs(&["accounts", "remove", "alice"], &envs).await;
+    assert!(status.success());
+    let (status, _) =
+        common::run_command_with_envs(&["accounts", "remove", "default"], &envs).await;
+    assert!(!status.success());
+    let (status, _) = common::run_command_with_envs(&["token", "--account", "alice"], &envs).await;
+    assert!(!status.success());
+    std::fs::remove_file(path).unwrap();
+}
+
+/// `config check` fails on invalid env vars.
+#[tokio::test]
+async fn test_cli_config_check() {
+    let secret = common::get_random_secret();
+    let envs = [("RAW_SECRET", secret.as_str())];
+    let (status, stdout) = common::run_command_with_envs(&["config", "check"], &envs).await;
+    assert!(status.success());
+    assert!(stdout.starts_with("OK"));
+    let envs = [("RAW_SECRET", secret.as_str()), ("SESSION_TTL", "0")];
+    let (status, _) = common::run_command_with_envs(&["config", "check"], &envs).await;
+    assert!(!status.success());
+}
+
+/// Accounts are imported from and exported to migration URIs and QR images.
+#[tokio::test]
+async fn test_cli_migration() {
+    const BATCH: &str = "otpauth-migration://offline?data=CkgKFDEyMzQ1Njc4OTAxMjM0NTY3ODkwEh1HYW1lIFN0dWRpbzphbGljZUBleGFtcGxlLmNvbRoLR2FtZSBTdHVkaW8gASgBMAIKHQoQAAECAwQFBgcICQoLDA0ODxIDYm9iIAEoATACCjEKFDEyMzQ1Njc4OTAxMjM0NTY3ODkwEglob3RwLXVzZXIaBkxlZ2FjeSABKAIwATgqEAEYASj50rT%2F%2F%2F%2F%2F%2F%2F8B";
+    let temp_path = |ext: &str| {
+        let path = std::env::temp_dir().join(format!("totp-{}.{ext}", rand::random::<u64>()));
+        path.to_str().unwrap().to_owned()
+    };
+    let secret = common::get_random_secret();
+    let (source, target, image) = (temp_path("json"), temp_path("json"), temp_path("png"));
+    let source_envs = [("RAW_SECRET", secret.as_str()), ("ACCOUNTS_PATH", &source)];
+    let target_envs = [("RAW_SECRET", secret.as_str()), ("ACCOUNTS_PATH", &target)];
+
+    let args = ["accounts", "import", BATCH];
+    let (status, stdout) = common::run_command_with_envs(&args, &source_envs).await;
+    assert!(status.success());
+    assert!(stdout.contains("Imported: alice@example.com"));
+    assert!(stdout.contains("Imported: bob"));
+    assert!(stdout.contains("Skipped: hotp-user"));
+    // Nothing is imported twice.
+    let (status, _) = common::run_command_with_envs(&args, &source_envs).await;
+    assert!(!status.success());
+
+    let args = [
+        "accounts",
+        "export",
+        "alice@example.com",
+        "--output",
+        &image,
+    ];
+    let (status, _) = common::run_command_with_envs(&args, &source_envs).await;
+    assert!(status.success());
+    let args = ["accounts", "import", &image];
+    let (status, stdout) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success(), "{stdout}");
+    let args = ["token", "--account", "alice@example.com"];
+    let (_, expected) = common::run_command_with_envs(&args, &source_envs).await;
+    let (status, token) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success());
+    assert_eq!(token, expected);
+    let args = ["qr", "--url-only", "--account", "alice@example.com"];
+    let (_, url) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(url.starts_with("otpauth://totp/Game%20Studio:alice%40example.com?"));
+
+    for path in [source, target, image] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_cli_bundle() {
+    let temp_path = |ext: &str| {
+        let path = std::env::temp_dir().join(format!("totp-{}.{ext}", rand::random::<u64>()));
+        path.to_str().unwrap().to_owned()
+    };
+    let secret = common::get_random_secret();
+    let (source, target, bundle) = (temp_path("json"), temp_path("json"), temp_path("age"));
+    let passphrase = ("BUNDLE_PASSPHRASE", "correct horse battery staple");
+    let source_envs = [
+        ("RAW_SECRET", secret.as_str()),
+        ("ACCOUNTS_PATH", &source),
+        passphrase,
+    ];
+    let target_envs = [
+        ("RAW_SECRET", secret.as_str()),
+        ("ACCOUNTS_PATH", &target),
+        passphrase,
+    ];
+
+    for name in ["alice", "bob"] {
+        let args = ["accounts", "add", name];
+        let (status, _) = common::run_command_with_envs(&args, &source_envs).await;
+        assert!(status.success());
+    }
+    // Bundles are encrypted unless told otherwise.
+    let args = ["export", "--format", "csv", "--plain"];
+    let (status, csv) = common::run_command_with_envs(&args, &source_envs).await;
+    assert!(status.success());
+    assert!(csv.contains("\nalice,,,SHA1,6,30,"), "{csv}");
+    let args = ["export", "--output", &bundle];
+    let (status, _) = common::run_command_with_envs(&args, &source_envs[..2]).await;
+    assert!(!status.success());
+    let (status, _) = common::run_command_with_envs(&args, &source_envs).await;
+    assert!(status.success());
+    assert!(
+        std::fs::read_to_string(&bundle)
+            .unwrap()
+            .starts_with("-----BEGIN AGE ENCRYPTED FILE-----")
+    );
+
+    // Import into another file, where bob already exists with another secret.
+    let args = ["accounts", "add", "bob"];
+    let (status, _) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success());
+    let args = ["import", &bundle, "--dry-run"];
+    let (status, stdout) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(!status.success(), "{stdout}");
+    let args = ["import", &bundle, "--dry-run", "--policy", "overwrite"];
+    let (status, stdout) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success());
+    assert!(stdout.contains("Added: alice"));
+    assert!(stdout.contains("Updated: bob"));
+    assert!(stdout.contains("Dry run"));
+    let args = ["import", &bundle, "--policy", "skip"];
+    let (status, stdout) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success());
+    assert!(stdout.contains("Skipped: bob"));
+    let args = ["token", "--account", "alice"];
+    let (_, expected) = common::run_command_with_envs(&args, &source_envs).await;
+    let (status, token) = common::run_command_with_envs(&args, &target_envs).await;
+    assert!(status.success());
+    assert_eq!(token, expected);
+    let args = ["token", "--account", "bob"];
+    let (_, expected) = common::run_command_with_envs(&args, &source_envs).await;
+    let (_, token) = common::run_command_with_envs(&args, &target_envs).await;
+    assert_ne!(token, expected);
+
+    for path in [source, target, bundle] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -5,7 +5,7 @@ use tokio::process::Child;
 /// Executable file path (e.g. `./target/debug/totp-server`).
 const EXECUTABLE_PATH: &str = env!(concat!("CARGO_BIN_EXE_", "totp-server"));
 
-fn get_available_port() -> u16 {
+pub(crate) fn get_available_port() -> u16 {
     use std::net::TcpListener;
 
     TcpListener::bind("0.0.0.0:0")
@@ -18,12 +18,13 @@ fn get_available_port() -> u16 {
 /// Spawn a child process to run totp-server.
 /// The process will be killed on drop.
 #[must_use]
-fn spawn_totp_process(raw_secret: &str, port: u16) -> Child {
+pub(crate) fn spawn_totp_process(raw_secret: &str, port: u16, envs: &[(&str, &str)]) -> Child {
     use std::process::Stdio;
     use tokio::process::Command;
     Command::new(EXECUTABLE_PATH)
         .env("RAW_SECRET", raw_secret)
         .env("TCP_BIND_PORT", port.to_string())
+        .envs(envs.iter().copied())
         .stdout(Stdio::null())
         .kill_on_drop(true)
         .spawn()
@@ -35,6 +36,27 @@ pub(crate) fn get_random_secret() -> String {
     Alphanumeric.sample_string(&mut rand::rng(), 32)
 }
 
+/// Run totp-server with the given arguments, and return its exit status and stdout.
+pub(crate) async fn run_command(args: &[&str]) -> (std::process::ExitStatus, String) {
+    run_command_with_envs(args, &[]).await
+}
+
+/// Run totp-server with the given arguments and env vars, and return its exit status and stdout.
+pub(crate) async fn run_command_with_envs(
+    args: &[&str],
+    envs: &[(&str, &str)],
+) -> (std::process::ExitStatus, String) {
+    use tokio::process::Command;
+    let output = Command::new(EXECUTABLE_PATH)
+        .args(args)
+        .envs(envs.iter().copied())
+        .output()
+        .await
+        .expect("failed to run child process");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output.status, stdout)
+}
+
 pub(crate) fn get_random_6_digits() -> String {
     let width = 6;
     let number = rand::random_range(0..=999_999);
@@ -42,7 +64,7 @@ pub(crate) fn get_random_6_digits() -> String {
 }
 
 /// Wait until totp-server is ready.
-async fn wait_until_ready(port: u16) {
+pub(crate) async fn wait_until_ready(port: u16) {
     use reqwest::Client;
     use std::time::Duration;
     use tokio::time::{sleep, timeout};
@@ -85,9 +107,15 @@ async fn wait_until_ready(port: u16) {
 /// it's probably because system time the server is not correct.
 #[must_use]
 pub(crate) async fn setup() -> (Child, String, u16) {
+    setup_with_envs(&[]).await
+}
+
+/// Same as [`setup`], while extra env vars are set for the child process.
+#[must_use]
+pub(crate) async fn setup_with_envs(envs: &[(&str, &str)]) -> (Child, String, u16) {
     let raw_secret = get_random_secret();
     let port = get_available_port();
-    let child = spawn_totp_process(&raw_secret, port);
+    let child = spawn_totp_process(&raw_secret, port, envs);
 
     let token = totp_server::try_get_token(raw_secret.as_bytes()).unwrap();
     wait_until_ready(port).await;